@@ -0,0 +1,198 @@
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use pool_party::{FlagVec, HierarchicalBitVec};
+use pool_party::__bench::{BitVec, BoolVec};
+use rand::Rng;
+use rand::SeedableRng;
+use rand_xoshiro::Xoshiro256StarStar;
+
+/*
+    Compares the flags a HierarchicalFlags pool keeps now, a single set that's
+    searched for zeros to find free slots, against the two it used to keep,
+    where a second set of free flags was always the complement of the first
+    and searched for ones instead. The two sets are a copy of the
+    HierarchicalBitVec from before it kept false summaries, since timing two
+    of today's would charge the old design for upkeep it never did. BitFlags
+    and BoolFlags are measured with a single set too, for scale.
+
+    Usage: cargo bench --bench flags
+
+    Each run fills every slot, frees a random half of them, then churns by
+    freeing a random live slot and allocating another, which is where a pool
+    spends most of its time. Only the flags are measured, without any items,
+    so that the difference isn't drowned out by copying them around.
+*/
+
+const RNG_SEED: u64 = 50;
+const NUM_CHURNS: usize = 1_000_000;
+const MAX_SLOTS_FOR_LINEAR_SEARCH: usize = 1 << 16; // BitFlags and BoolFlags scan from the start on every allocate, so filling more takes minutes
+
+trait Flags {
+    fn with_free_slots(num_slots: usize) -> Self;
+    fn allocate(&mut self) -> usize;
+    fn deallocate(&mut self, slot: usize);
+    fn heap_size_bytes(&self) -> usize;
+}
+
+const BITS_PER_BLOCK: usize = u64::BITS as usize;
+
+// HierarchicalBitVec as it was before false summaries, cut down to what TwoSets uses
+struct OldHierarchicalBitVec {
+    levels: Vec<Vec<u64>>, // levels[0] holds the bits, and each bit above is set if any in the block below it are
+}
+
+impl OldHierarchicalBitVec {
+    fn with_bits(num_bits: usize, value_of_bits: bool) -> Self {
+        let mut levels: Vec<Vec<u64>> = Vec::new();
+        let mut num_bits_at_level: usize = num_bits;
+        loop {
+            let mut level: Vec<u64> = vec![if value_of_bits { u64::MAX } else { 0 }; num_bits_at_level.div_ceil(BITS_PER_BLOCK)];
+            // The bits past the end of the last block mustn't be found
+            if value_of_bits && num_bits_at_level % BITS_PER_BLOCK != 0 {
+                *level.last_mut().unwrap() = (1 << (num_bits_at_level % BITS_PER_BLOCK)) - 1;
+            }
+            levels.push(level);
+            if num_bits_at_level <= BITS_PER_BLOCK {
+                break;
+            }
+            num_bits_at_level = num_bits_at_level.div_ceil(BITS_PER_BLOCK);
+        }
+        return Self { levels }
+    }
+
+    fn set_bit(&mut self, idx: usize, value: bool) {
+        let mut idx: usize = idx;
+        let mut value: bool = value;
+        for level in self.levels.iter_mut() {
+            let block: &mut u64 = &mut level[idx / BITS_PER_BLOCK];
+            if value {
+                *block |= 1 << (idx % BITS_PER_BLOCK);
+            }
+            else {
+                *block &= !(1 << (idx % BITS_PER_BLOCK));
+            }
+            value = *block != 0;
+            idx /= BITS_PER_BLOCK;
+        }
+    }
+
+    fn find_a_true_bit(&self) -> Option<usize> {
+        let top_block: u64 = self.levels[self.levels.len()-1][0];
+        if top_block == 0 {
+            return None
+        }
+
+        let mut idx: usize = top_block.trailing_zeros() as usize;
+        for level in self.levels.iter().rev().skip(1) {
+            idx = idx * BITS_PER_BLOCK + level[idx].trailing_zeros() as usize;
+        }
+        return Some(idx)
+    }
+
+    fn heap_size_bytes(&self) -> usize {
+        return self.levels.capacity() * size_of::<Vec<u64>>() + self.levels.iter().map(|level: &Vec<u64>| level.capacity() * size_of::<u64>()).sum::<usize>()
+    }
+}
+
+struct TwoSets {
+    alloc: OldHierarchicalBitVec,
+    free: OldHierarchicalBitVec,
+}
+
+impl Flags for TwoSets {
+    fn with_free_slots(num_slots: usize) -> Self {
+        return Self { alloc: OldHierarchicalBitVec::with_bits(num_slots, false), free: OldHierarchicalBitVec::with_bits(num_slots, true) }
+    }
+
+    fn allocate(&mut self) -> usize {
+        let slot: usize = self.free.find_a_true_bit().unwrap();
+        self.alloc.set_bit(slot, true);
+        self.free.set_bit(slot, false);
+        return slot
+    }
+
+    fn deallocate(&mut self, slot: usize) {
+        self.alloc.set_bit(slot, false);
+        self.free.set_bit(slot, true);
+    }
+
+    fn heap_size_bytes(&self) -> usize {
+        return self.alloc.heap_size_bytes() + self.free.heap_size_bytes()
+    }
+}
+
+struct OneSet<U: FlagVec> {
+    alloc: U,
+}
+
+impl <U: FlagVec> Flags for OneSet<U> {
+    fn with_free_slots(num_slots: usize) -> Self {
+        return Self { alloc: U::with_flags_in(num_slots, false, Default::default()) }
+    }
+
+    fn allocate(&mut self) -> usize {
+        let slot: usize = self.alloc.find_a_false_flag().unwrap();
+        self.alloc.set_flag(slot, true);
+        return slot
+    }
+
+    fn deallocate(&mut self, slot: usize) {
+        self.alloc.set_flag(slot, false);
+    }
+
+    fn heap_size_bytes(&self) -> usize {
+        return self.alloc.heap_size_bytes()
+    }
+}
+
+struct Report {
+    design: &'static str,
+    num_slots: usize,
+    fill_time: Duration,
+    churn_time: Duration,
+    heap_size_bytes: usize,
+}
+
+fn run<F: Flags>(design: &'static str, num_slots: usize) -> Report {
+    let mut rng: Xoshiro256StarStar = Xoshiro256StarStar::seed_from_u64(RNG_SEED);
+    let mut flags: F = F::with_free_slots(num_slots);
+
+    let start: Instant = Instant::now();
+    let mut live_slots: Vec<usize> = (0..num_slots).map(|_| flags.allocate()).collect();
+    let fill_time: Duration = start.elapsed();
+
+    for _ in 0..num_slots/2 {
+        let slot: usize = live_slots.swap_remove(rng.gen_range(0..live_slots.len()));
+        flags.deallocate(slot);
+    }
+
+    let start: Instant = Instant::now();
+    for _ in 0..NUM_CHURNS {
+        let idx: usize = rng.gen_range(0..live_slots.len());
+        flags.deallocate(live_slots[idx]);
+        live_slots[idx] = black_box(flags.allocate());
+    }
+    let churn_time: Duration = start.elapsed();
+
+    return Report { design, num_slots, fill_time, churn_time, heap_size_bytes: flags.heap_size_bytes() }
+}
+
+fn main() {
+    let mut reports: Vec<Report> = Vec::new();
+    for num_slots in [1 << 10, 1 << 16, 1 << 22] {
+        reports.push( run::<TwoSets>("two sets", num_slots) );
+        reports.push( run::<OneSet<HierarchicalBitVec>>("one set", num_slots) );
+        if num_slots <= MAX_SLOTS_FOR_LINEAR_SEARCH {
+            reports.push( run::<OneSet<BitVec>>("bit vec", num_slots) );
+            reports.push( run::<OneSet<BoolVec>>("bool vec", num_slots) );
+        }
+    }
+
+    println!("{:<12}{:>12}{:>16}{:>16}{:>16}", "design", "slots", "fill (ns/op)", "churn (ns/op)", "flags (KiB)");
+    for report in reports.iter() {
+        let fill_ns_per_op: f64 = report.fill_time.as_nanos() as f64 / report.num_slots as f64;
+        let churn_ns_per_op: f64 = report.churn_time.as_nanos() as f64 / NUM_CHURNS as f64;
+        println!("{:<12}{:>12}{:>16.2}{:>16.2}{:>16.1}", report.design, report.num_slots, fill_ns_per_op, churn_ns_per_op, report.heap_size_bytes as f64 / 1024.0);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,257 @@
+use proc_macro::TokenStream;
+use proc_macro2::{Ident, Span};
+use quote::{format_ident, quote};
+use syn::ext::IdentExt;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Type, Visibility};
+
+/*
+    #[derive(SoaPool)] on a struct Foo generates a FooPool that stores each of
+    Foo's fields in its own column, so a loop that only touches one or two fields
+    only pulls those columns through the cache.
+
+    All the columns share one HierarchicalBitVec, like a HierarchicalFlags pool:
+    `__alloc` says which ids hold an item (and so which slots of every column are
+    initialized), and allocate() finds an open id with its find_a_false_bit().
+    get() and get_mut() return a FooRef/FooMut holding a reference into each
+    column, and iter_<field>() and iter_<field>_mut() walk a single column.
+
+    The generated code only names things through ::pool_party::__private, so it
+    works the same with and without std.
+
+    A struct that implements Drop is rejected, since splitting it into columns
+    means its own drop() would never run. allocate() moves the fields out of the
+    item through a ManuallyDrop, which is only sound because of that.
+*/
+/// ```compile_fail
+/// #[derive(pool_party::SoaPool)]
+/// struct Handle {
+///     id: u32,
+/// }
+///
+/// impl Drop for Handle {
+///     fn drop(&mut self) {}
+/// }
+/// ```
+#[proc_macro_derive(SoaPool)]
+pub fn derive_soa_pool(input: TokenStream) -> TokenStream {
+    let input: DeriveInput = parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => return tokens.into(),
+        Err(error) => return error.to_compile_error().into(),
+    }
+}
+
+struct Column<'a> {
+    vis: &'a Visibility,
+    name: &'a Ident,
+    ty: &'a Type,
+    local: Ident, // so a field named `id` can't shadow anything in iter_mut()
+    iter: Ident,
+    iter_mut: Ident,
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    if !input.generics.params.is_empty() || input.generics.where_clause.is_some() {
+        return Err(syn::Error::new_spanned(&input.generics, "SoaPool can't be derived for generic structs"))
+    }
+
+    let fields = match &input.data {
+        Data::Struct(data) => {
+            match &data.fields {
+                Fields::Named(fields) => &fields.named,
+                _ => return Err(syn::Error::new(Span::call_site(), "SoaPool can only be derived for structs with named fields")),
+            }
+        },
+
+        _ => return Err(syn::Error::new(Span::call_site(), "SoaPool can only be derived for structs")),
+    };
+
+    let columns: Vec<Column> = fields.iter()
+        .map(|field: &syn::Field| {
+            let name: &Ident = field.ident.as_ref().unwrap();
+            return Column {
+                vis: &field.vis,
+                name,
+                ty: &field.ty,
+                local: format_ident!("__{}", name.unraw()),
+                iter: format_ident!("iter_{}", name.unraw()),
+                iter_mut: format_ident!("iter_{}_mut", name.unraw()),
+            }
+        })
+        .collect();
+
+    let vis: &Visibility = &input.vis;
+    let item: &Ident = &input.ident;
+    let pool: Ident = format_ident!("{}Pool", item);
+    let item_ref: Ident = format_ident!("{}Ref", item);
+    let item_mut: Ident = format_ident!("{}Mut", item);
+
+    let vises: Vec<&Visibility> = columns.iter().map(|column: &Column| column.vis).collect();
+    let names: Vec<&Ident> = columns.iter().map(|column: &Column| column.name).collect();
+    let tys: Vec<&Type> = columns.iter().map(|column: &Column| column.ty).collect();
+    let locals: Vec<&Ident> = columns.iter().map(|column: &Column| &column.local).collect();
+    let iters: Vec<&Ident> = columns.iter().map(|column: &Column| &column.iter).collect();
+    let iter_muts: Vec<&Ident> = columns.iter().map(|column: &Column| &column.iter_mut).collect();
+
+    let private = quote!(::pool_party::__private);
+    let pool_doc: String = format!("A pool of [`{}`]s that stores each field in its own column.", item);
+    let ref_doc: String = format!("References to the fields of a [`{}`] stored in a [`{}`].", item, pool);
+    let mut_doc: String = format!("Mutable references to the fields of a [`{}`] stored in a [`{}`].", item, pool);
+    let must_not_impl_drop: Ident = format_ident!("SoaPoolCantBeDerivedFor{}BecauseItImplementsDrop", item);
+
+    return Ok(quote! {
+        // Only one of these impls can apply to the item, so a Drop item fails to compile with this trait's name in the error
+        const _: () = {
+            trait #must_not_impl_drop {}
+            #[allow(drop_bounds)]
+            impl<T: ::core::ops::Drop> #must_not_impl_drop for T {}
+            impl #must_not_impl_drop for #item {}
+        };
+
+        #[doc = #pool_doc]
+        #vis struct #pool {
+            #( #names: #private::Vec<#private::MaybeUninit<#tys>>, )* // only initialized where the id's alloc bit is set
+            __alloc: #private::HierarchicalBitVec, // bits indicating an id holds an item, whose false summaries find the open ones
+            __num_items: usize,
+        }
+
+        #[doc = #ref_doc]
+        #[allow(dead_code)] // a caller that only reads some of the fields shouldn't be warned about the rest
+        #vis struct #item_ref<'a> {
+            #( #vises #names: &'a #tys, )*
+        }
+
+        #[doc = #mut_doc]
+        #[allow(dead_code)]
+        #vis struct #item_mut<'a> {
+            #( #vises #names: &'a mut #tys, )*
+        }
+
+        impl #pool {
+            pub fn new() -> Self {
+                return Self::with_capacity(0)
+            }
+
+            pub fn with_capacity(num_items: usize) -> Self {
+                return Self {
+                    #( #names: Self::uninit_column(num_items), )*
+                    __alloc: #private::HierarchicalBitVec::with_bits(num_items, false),
+                    __num_items: 0,
+                }
+            }
+
+            pub fn len(&self) -> usize {
+                return self.__num_items
+            }
+
+            pub fn is_empty(&self) -> bool {
+                return self.__num_items == 0
+            }
+
+            pub fn get(&self, id: usize) -> #item_ref<'_> {
+                assert!(self.__alloc.get_bit(id));
+                return unsafe {
+                    #item_ref {
+                        #( #names: self.#names[id].assume_init_ref(), )*
+                    }
+                }
+            }
+
+            pub fn get_mut(&mut self, id: usize) -> #item_mut<'_> {
+                assert!(self.__alloc.get_bit(id));
+                return unsafe {
+                    #item_mut {
+                        #( #names: self.#names[id].assume_init_mut(), )*
+                    }
+                }
+            }
+
+            pub fn allocate(&mut self, item: #item) -> usize {
+                self.expand_if_needed();
+
+                let id: usize = self.__alloc.find_a_false_bit().unwrap();
+                self.__alloc.set_bit(id, true);
+                let item: #private::ManuallyDrop<#item> = #private::ManuallyDrop::new(item);
+                #( self.#names[id].write(unsafe { #private::ptr::read(&item.#names) }); )*
+                self.__num_items += 1;
+                return id
+            }
+
+            pub fn deallocate(&mut self, id: usize) {
+                assert!(self.__alloc.get_bit(id));
+                self.__alloc.set_bit(id, false);
+                self.__num_items -= 1;
+                #( unsafe { self.#names[id].assume_init_drop() }; )*
+            }
+
+            /// The ids of every item in the pool.
+            pub fn ids(&self) -> impl Iterator<Item = usize> + '_ {
+                return self.__alloc.true_bits()
+            }
+
+            pub fn iter(&self) -> impl Iterator<Item = #item_ref<'_>> + '_ {
+                return self.ids().map(move |id: usize| self.get(id))
+            }
+
+            pub fn iter_mut(&mut self) -> impl Iterator<Item = #item_mut<'_>> + '_ {
+                #( let #locals: *mut #private::MaybeUninit<#tys> = self.#names.as_mut_ptr(); )*
+                // Every id is yielded at most once, so the references handed out never alias
+                return self.__alloc.true_bits().map(move |id: usize| unsafe {
+                    #item_mut {
+                        #( #names: (*#locals.add(id)).assume_init_mut(), )*
+                    }
+                })
+            }
+
+            #(
+                pub fn #iters(&self) -> impl Iterator<Item = &#tys> + '_ {
+                    return self.ids().map(move |id: usize| unsafe { self.#names[id].assume_init_ref() })
+                }
+
+                pub fn #iter_muts(&mut self) -> impl Iterator<Item = &mut #tys> + '_ {
+                    let column: *mut #private::MaybeUninit<#tys> = self.#names.as_mut_ptr();
+                    // Every id is yielded at most once, so the references handed out never alias
+                    return self.__alloc.true_bits().map(move |id: usize| unsafe { (*column.add(id)).assume_init_mut() })
+                }
+            )*
+
+            fn uninit_column<C>(num_items: usize) -> #private::Vec<#private::MaybeUninit<C>> {
+                let mut column: #private::Vec<#private::MaybeUninit<C>> = #private::Vec::with_capacity(num_items);
+                column.resize_with(num_items, #private::MaybeUninit::uninit);
+                return column
+            }
+
+            fn expand_if_needed(&mut self) {
+                if self.__num_items < self.__alloc.num_bits() {
+                    return
+                }
+
+                const GROWTH_FACTOR: usize = 2;
+                let new_num_items: usize =
+                    if self.__num_items == 0 {
+                        1
+                    }
+                    else {
+                        self.__num_items*GROWTH_FACTOR
+                    };
+                let num_new_items: usize = new_num_items - self.__num_items;
+                self.__alloc.add_bits(num_new_items, false);
+                #( self.#names.resize_with(new_num_items, #private::MaybeUninit::uninit); )*
+            }
+        }
+
+        impl ::core::default::Default for #pool {
+            fn default() -> Self {
+                return Self::new()
+            }
+        }
+
+        impl ::core::ops::Drop for #pool {
+            fn drop(&mut self) {
+                for id in self.__alloc.true_bits() {
+                    #( unsafe { self.#names[id].assume_init_drop() }; )*
+                }
+            }
+        }
+    })
+}
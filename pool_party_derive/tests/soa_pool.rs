@@ -0,0 +1,159 @@
+use pool_party::SoaPool;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+#[derive(SoaPool, Clone, Debug, PartialEq)]
+pub struct Entity {
+    pub position: (f32, f32),
+    pub velocity: (f32, f32),
+    pub name: String,
+}
+
+fn entity(i: usize) -> Entity {
+    return Entity {
+        position: (i as f32, 0.0),
+        velocity: (1.0, i as f32),
+        name: format!("entity {}", i),
+    }
+}
+
+#[test]
+#[should_panic]
+fn test_invalid_get_to_empty_pool() {
+    let pool: EntityPool = EntityPool::new();
+    pool.get(0);
+}
+
+#[test]
+#[should_panic]
+fn test_invalid_get_to_nonempty_pool() {
+    let mut pool: EntityPool = EntityPool::new();
+    let id: usize = pool.allocate(entity(0));
+    pool.allocate(entity(1));
+    pool.deallocate(id);
+    pool.get(id);
+}
+
+#[test]
+fn test_one_item() {
+    let mut pool: EntityPool = EntityPool::new();
+    assert!(pool.is_empty());
+
+    let id: usize = pool.allocate(entity(3));
+    assert!(pool.len() == 1);
+    let item: EntityRef = pool.get(id);
+    assert!(*item.position == (3.0, 0.0));
+    assert!(*item.velocity == (1.0, 3.0));
+    assert!(item.name == "entity 3");
+
+    let item: EntityMut = pool.get_mut(id);
+    item.position.1 = 5.0;
+    item.name.push('!');
+    assert!(*pool.get(id).position == (3.0, 5.0));
+    assert!(pool.get(id).name == "entity 3!");
+
+    pool.deallocate(id);
+    assert!(pool.is_empty());
+    assert!(pool.ids().next().is_none());
+}
+
+#[test]
+fn test_many_items_against_a_hashmap() {
+    let mut pool: EntityPool = EntityPool::with_capacity(10);
+    let mut expected: HashMap<usize, Entity> = HashMap::new();
+    for i in 0..1000 {
+        let id: usize = pool.allocate(entity(i));
+        assert!(expected.insert(id, entity(i)).is_none());
+
+        if i % 3 == 0 {
+            let id: usize = *expected.keys().next().unwrap();
+            pool.deallocate(id);
+            expected.remove(&id);
+        }
+    }
+
+    assert!(pool.len() == expected.len());
+    for (id, item) in expected.iter() {
+        assert!(*pool.get(*id).position == item.position);
+        assert!(*pool.get(*id).velocity == item.velocity);
+        assert!(*pool.get(*id).name == item.name);
+    }
+
+    let mut ids: Vec<usize> = pool.ids().collect();
+    ids.sort();
+    let mut expected_ids: Vec<usize> = expected.keys().copied().collect();
+    expected_ids.sort();
+    assert!(ids == expected_ids);
+    assert!(pool.iter().count() == expected.len());
+}
+
+#[test]
+fn test_column_iterators() {
+    let mut pool: EntityPool = EntityPool::new();
+    let ids: Vec<usize> = (0..100).map(|i: usize| pool.allocate(entity(i))).collect();
+    for id in ids.iter().step_by(2) {
+        pool.deallocate(*id);
+    }
+
+    for position in pool.iter_position_mut() {
+        position.0 += 1.0;
+    }
+    for (id, position) in pool.ids().zip(pool.iter_position()) {
+        assert!(*position == (id as f32 + 1.0, 0.0));
+    }
+
+    for item in pool.iter_mut() {
+        item.velocity.0 = 0.0;
+    }
+    assert!(pool.iter_velocity().all(|velocity: &(f32, f32)| velocity.0 == 0.0));
+    assert!(pool.iter_name().count() == 50);
+    for (id, item) in pool.ids().zip(pool.iter()) {
+        assert!(*item.name == format!("entity {}", id));
+    }
+}
+
+#[test]
+fn test_items_are_dropped_exactly_once() {
+    #[derive(SoaPool)]
+    struct Counted {
+        first: Rc<()>,
+        second: Rc<()>,
+    }
+
+    let counter: Rc<()> = Rc::new(());
+    let mut pool: CountedPool = CountedPool::new();
+    let ids: Vec<usize> = (0..100)
+        .map(|_| pool.allocate(Counted{ first: counter.clone(), second: counter.clone() }))
+        .collect();
+    assert!(Rc::strong_count(&counter) == 201);
+
+    for id in ids.iter().take(30) {
+        pool.deallocate(*id);
+    }
+    assert!(Rc::strong_count(&counter) == 141);
+
+    drop(pool);
+    assert!(Rc::strong_count(&counter) == 1);
+}
+
+#[test]
+fn test_fields_named_like_locals() {
+    #[derive(SoaPool)]
+    struct Awkward {
+        id: usize,
+        item: usize,
+        r#type: u8,
+    }
+
+    let mut pool: AwkwardPool = AwkwardPool::new();
+    let id: usize = pool.allocate(Awkward{ id: 7, item: 8, r#type: 9 });
+    let awkward: AwkwardRef = pool.get(id);
+    assert!(*awkward.id == 7);
+    assert!(*awkward.item == 8);
+    assert!(*awkward.r#type == 9);
+    assert!(pool.iter_type().eq([9].iter()));
+    for item in pool.iter_mut() {
+        *item.id += 1;
+    }
+    assert!(pool.iter_id_mut().map(|id: &mut usize| *id).eq([8]));
+}
@@ -0,0 +1,280 @@
+use std::alloc::{self, GlobalAlloc, Layout, System};
+use std::ptr::NonNull;
+use std::hint::black_box;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use pool_party::{Pool, Trace, TraceOp};
+use pool_party::{Simple, FreeList, Stacks, NotSafe, BitFlags, BoolFlags, HierarchicalFlags, Paged, SparseSet, Reference};
+
+/*
+    Replays a trace written by a Recording against every backend, and reports
+    how long each one took, the most heap it held at once and what iterating
+    it cost per item.
+
+    Usage: pool_party-replay <trace file>
+
+    Traces don't keep the items themselves, so every backend is given zeroed
+    items of the recorded size and alignment. The layouts pools are most often
+    used with, anything up to 16 bytes and anything aligned to 8 or more up to
+    128 bytes, are replayed with items of that layout stored in place. Giving
+    every other layout an Item type of its own would mean compiling every
+    backend again for each one, so those items are each given a heap block of
+    the recorded layout instead. Live items are counted exactly
+    either way, but a slot of the second kind only takes up a pointer and a
+    layout, so a backend's empty slots are undercounted.
+
+    Peak memory is measured by the global allocator, so it counts everything
+    the backend allocated, including Reference's map, and nothing from before
+    the replay started.
+*/
+
+static BYTES_IN_USE: AtomicUsize = AtomicUsize::new(0);
+static PEAK_BYTES_IN_USE: AtomicUsize = AtomicUsize::new(0);
+
+struct PeakTrackingAlloc;
+
+impl PeakTrackingAlloc {
+    fn allocated(num_bytes: usize) {
+        let bytes_in_use: usize = BYTES_IN_USE.fetch_add(num_bytes, Ordering::Relaxed) + num_bytes;
+        PEAK_BYTES_IN_USE.fetch_max(bytes_in_use, Ordering::Relaxed);
+    }
+
+    fn deallocated(num_bytes: usize) {
+        BYTES_IN_USE.fetch_sub(num_bytes, Ordering::Relaxed);
+    }
+}
+
+unsafe impl GlobalAlloc for PeakTrackingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr: *mut u8 = System.alloc(layout);
+        if !ptr.is_null() {
+            Self::allocated(layout.size());
+        }
+        return ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        Self::deallocated(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr: *mut u8 = System.realloc(ptr, layout, new_size);
+        // Both blocks exist while realloc() copies from one to the other, so that's counted towards the peak
+        if !new_ptr.is_null() {
+            Self::allocated(new_size);
+            Self::deallocated(layout.size());
+        }
+        return new_ptr
+    }
+}
+
+#[global_allocator]
+static GLOBAL: PeakTrackingAlloc = PeakTrackingAlloc;
+
+// A zero-length array of one of these gives an Item its alignment without taking any room
+#[derive(Clone, Copy)]
+#[repr(align(1))]
+struct Align1;
+#[derive(Clone, Copy)]
+#[repr(align(2))]
+struct Align2;
+#[derive(Clone, Copy)]
+#[repr(align(4))]
+struct Align4;
+#[derive(Clone, Copy)]
+#[repr(align(8))]
+struct Align8;
+#[derive(Clone, Copy)]
+#[repr(align(16))]
+struct Align16;
+#[derive(Clone, Copy)]
+#[repr(align(32))]
+struct Align32;
+#[derive(Clone, Copy)]
+#[repr(align(64))]
+struct Align64;
+
+#[derive(Clone, Copy)]
+struct Item<const N: usize, A: Copy> {
+    _bytes: [u8; N],
+    _align: [A; 0],
+}
+
+impl <const N: usize, A: Copy> Item<N, A> {
+    fn zeroed() -> Self {
+        return Self { _bytes: [0; N], _align: [] }
+    }
+}
+
+// An item of a layout without an Item type, in a heap block of its own
+struct HeapItem {
+    bytes: NonNull<u8>, // dangling for a zero sized layout, which isn't allocated
+    layout: Layout,
+}
+
+impl HeapItem {
+    fn zeroed(layout: Layout) -> Self {
+        if layout.size() == 0 {
+            return Self { bytes: NonNull::dangling(), layout }
+        }
+
+        let bytes: *mut u8 = unsafe { alloc::alloc_zeroed(layout) };
+        match NonNull::new(bytes) {
+            Some(bytes) => return Self { bytes, layout },
+            None => alloc::handle_alloc_error(layout),
+        }
+    }
+}
+
+// Every item is zeroed, so a clone only needs a block of its own
+impl Clone for HeapItem {
+    fn clone(&self) -> Self {
+        return Self::zeroed(self.layout)
+    }
+}
+
+impl Drop for HeapItem {
+    fn drop(&mut self) {
+        if self.layout.size() != 0 {
+            unsafe { alloc::dealloc(self.bytes.as_ptr(), self.layout) };
+        }
+    }
+}
+
+struct Report {
+    backend: &'static str,
+    total_time: Duration,
+    peak_bytes: usize,
+    iter_time: Duration,
+    num_items_iterated: usize,
+}
+
+fn replay<T, P: Pool<T>, F: Fn() -> T>(backend: &'static str, trace: &Trace, new_item: &F) -> Report {
+    let mut handles: Vec<Option<usize>> = vec![None; trace.num_allocations()];
+    let mut next_handle: usize = 0;
+    let mut iter_time: Duration = Duration::ZERO;
+    let mut num_items_iterated: usize = 0;
+
+    let baseline_bytes: usize = BYTES_IN_USE.load(Ordering::Relaxed);
+    PEAK_BYTES_IN_USE.store(baseline_bytes, Ordering::Relaxed);
+    let start: Instant = Instant::now();
+    let mut pool: P = P::new();
+    for op in trace.ops() {
+        match op {
+            TraceOp::Allocate => {
+                handles[next_handle] = Some( pool.allocate(new_item()) );
+                next_handle += 1;
+            }
+            TraceOp::Deallocate(handle) => pool.deallocate(handles[handle].take().unwrap()),
+            TraceOp::Get(handle) => {
+                black_box(pool.get(handles[handle].unwrap()));
+            }
+            TraceOp::GetMut(handle) => {
+                black_box(pool.get_mut(handles[handle].unwrap()));
+            }
+            TraceOp::Iter => {
+                let iter_start: Instant = Instant::now();
+                for item in pool.iter() {
+                    black_box(item);
+                    num_items_iterated += 1;
+                }
+                iter_time += iter_start.elapsed();
+            }
+        }
+    }
+    drop(pool);
+    let total_time: Duration = start.elapsed();
+
+    return Report {
+        backend,
+        total_time,
+        peak_bytes: PEAK_BYTES_IN_USE.load(Ordering::Relaxed) - baseline_bytes,
+        iter_time,
+        num_items_iterated,
+    }
+}
+
+fn replay_all<T: Clone + Unpin, F: Fn() -> T>(trace: &Trace, new_item: F) -> Vec<Report> {
+    return vec![
+        replay::<T, Simple<T>, F>("Simple", trace, &new_item),
+        replay::<T, FreeList<T>, F>("FreeList", trace, &new_item),
+        replay::<T, Stacks<T>, F>("Stacks", trace, &new_item),
+        replay::<T, NotSafe<T>, F>("NotSafe", trace, &new_item),
+        replay::<T, BitFlags<T>, F>("BitFlags", trace, &new_item),
+        replay::<T, BoolFlags<T>, F>("BoolFlags", trace, &new_item),
+        replay::<T, HierarchicalFlags<T>, F>("HierarchicalFlags", trace, &new_item),
+        replay::<T, Paged<T>, F>("Paged", trace, &new_item),
+        replay::<T, SparseSet<T>, F>("SparseSet", trace, &new_item),
+        replay::<T, Reference<T>, F>("Reference", trace, &new_item),
+    ]
+}
+
+// Replays with items stored in place when the trace's size is one of the sizes given for its alignment
+macro_rules! replay_in_place {
+    ($trace:expr, $align_type:ty; $($size:literal),+) => {
+        match $trace.item_size() {
+            $( $size => Some(replay_all($trace, Item::<$size, $align_type>::zeroed)), )+
+            _ => None,
+        }
+    };
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() != 2 {
+        eprintln!("usage: {} <trace file>", args[0]);
+        return ExitCode::FAILURE
+    }
+
+    let bytes: Vec<u8> = match std::fs::read(&args[1]) {
+        Ok(bytes) => bytes,
+        Err(error) => {
+            eprintln!("couldn't read {}: {}", args[1], error);
+            return ExitCode::FAILURE
+        }
+    };
+    let trace: Trace = match Trace::from_bytes(&bytes) {
+        Ok(trace) => trace,
+        Err(error) => {
+            eprintln!("{} isn't a valid trace: {:?}", args[1], error);
+            return ExitCode::FAILURE
+        }
+    };
+
+    // Zero sized items would all share an address, which Reference can't give out as ids
+    let in_place: Option<Vec<Report>> = match trace.item_align() {
+        _ if trace.item_size() == 0 => None,
+        1 => replay_in_place!(&trace, Align1; 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16),
+        2 => replay_in_place!(&trace, Align2; 2, 4, 6, 8, 10, 12, 14, 16),
+        4 => replay_in_place!(&trace, Align4; 4, 8, 12, 16),
+        8 => replay_in_place!(&trace, Align8; 8, 16, 24, 32, 40, 48, 56, 64, 72, 80, 88, 96, 104, 112, 120, 128),
+        16 => replay_in_place!(&trace, Align16; 16, 32, 48, 64, 80, 96, 112, 128),
+        32 => replay_in_place!(&trace, Align32; 32, 64, 96, 128),
+        64 => replay_in_place!(&trace, Align64; 64, 128),
+        _ => None,
+    };
+    let (storage, reports): (&str, Vec<Report>) = match in_place {
+        Some(reports) => ("in place", reports),
+        None => {
+            let layout: Layout = match Layout::from_size_align(trace.item_size(), trace.item_align()) {
+                Ok(layout) => layout,
+                Err(_) => {
+                    eprintln!("items of {} bytes aligned to {} aren't a valid layout", trace.item_size(), trace.item_align());
+                    return ExitCode::FAILURE
+                }
+            };
+            ("in heap blocks", replay_all(&trace, || HeapItem::zeroed(layout)))
+        }
+    };
+
+    println!("{} ops, {} allocations, {} byte items aligned to {} (replayed {})", trace.ops().count(), trace.num_allocations(), trace.item_size(), trace.item_align(), storage);
+    println!("{:<20}{:>14}{:>16}{:>18}", "backend", "total (ms)", "peak (KiB)", "iter (ns/item)");
+    for report in reports.iter() {
+        let iter_ns_per_item: f64 = if report.num_items_iterated == 0 { 0.0 } else { report.iter_time.as_nanos() as f64 / report.num_items_iterated as f64 };
+        println!("{:<20}{:>14.3}{:>16.1}{:>18.2}", report.backend, report.total_time.as_secs_f64() * 1000.0, report.peak_bytes as f64 / 1024.0, iter_ns_per_item);
+    }
+    return ExitCode::SUCCESS
+}
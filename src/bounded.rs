@@ -0,0 +1,131 @@
+use core::marker::PhantomData;
+use core::ops::Range;
+
+use super::{PreallocatedPool, OrderedPool, PoolStats, HeapUsage, InvariantError};
+#[cfg(feature = "rayon")]
+use super::ParallelPool;
+
+/*
+    A pool whose storage is allocated once, in with_capacity(), and then never
+    grows. When it's full, try_allocate() hands the item back instead of resizing.
+
+    Only backends that size their storage up front in with_capacity() can be
+    wrapped, so as long as the pool never holds more than that many items,
+    allocating, deallocating, accessing and iterating items never touches the
+    heap. Reference boxes every item it's given, so it isn't one of them.
+*/
+pub struct Bounded<T, P: PreallocatedPool<T>> {
+    pool: P,
+    capacity: usize,
+    _items: PhantomData<T>,
+}
+
+impl <T, P: PreallocatedPool<T>> Bounded<T, P> {
+    pub fn with_capacity(capacity: usize) -> Self {
+        return Self {
+            pool: P::with_capacity(capacity),
+            capacity,
+            _items: PhantomData,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        return self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        return self.pool.len()
+    }
+
+    pub fn is_full(&self) -> bool {
+        return self.pool.len() >= self.capacity
+    }
+
+    pub fn remaining(&self) -> usize {
+        return self.capacity - self.pool.len()
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        return self.pool.stats()
+    }
+
+    pub fn heap_size_bytes(&self) -> HeapUsage {
+        return self.pool.heap_size_bytes()
+    }
+
+    pub fn heap_size_bytes_with<F: FnMut(&T) -> usize>(&self, item_heap_size_bytes: F) -> HeapUsage {
+        return self.pool.heap_size_bytes_with(item_heap_size_bytes)
+    }
+
+    pub fn check_invariants(&self) -> Result<(), InvariantError> {
+        return self.pool.check_invariants()
+    }
+
+    #[cfg_attr(feature = "debug-checks", track_caller)]
+    pub fn get(&self, id: usize) -> &T {
+        return self.pool.get(id)
+    }
+
+    #[cfg_attr(feature = "debug-checks", track_caller)]
+    pub fn get_mut(&mut self, id: usize) -> &mut T {
+        return self.pool.get_mut(id)
+    }
+
+    #[cfg_attr(feature = "debug-checks", track_caller)]
+    pub fn try_allocate(&mut self, item: T) -> Result<usize, T> {
+        if self.is_full() {
+            return Err(item)
+        }
+
+        return Ok(self.pool.allocate(item))
+    }
+
+    #[cfg_attr(feature = "debug-checks", track_caller)]
+    pub fn deallocate(&mut self, id: usize) {
+        self.pool.deallocate(id);
+    }
+
+    pub fn iter<'a>(&'a self) -> P::Iter<'a> where T: 'a {
+        return self.pool.iter()
+    }
+
+    pub fn sorted_iter<'a>(&'a self) -> P::SortedIter<'a> where P: OrderedPool<T>, T: 'a {
+        return self.pool.sorted_iter()
+    }
+
+    pub fn iter_range<'a>(&'a self, range: Range<usize>) -> P::RangeIter<'a> where P: OrderedPool<T>, T: 'a {
+        return self.pool.iter_range(range)
+    }
+
+    #[cfg(feature = "rayon")]
+    pub fn par_iter<'a>(&'a self) -> P::ParIter<'a> where P: ParallelPool<T>, T: Send + Sync + 'a {
+        return self.pool.par_iter()
+    }
+
+    #[cfg(feature = "rayon")]
+    pub fn par_iter_mut<'a>(&'a mut self) -> P::ParIterMut<'a> where P: ParallelPool<T>, T: Send + Sync + 'a {
+        return self.pool.par_iter_mut()
+    }
+
+    pub fn into_inner(self) -> P {
+        return self.pool
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Bounded;
+    use crate::testing;
+    use crate::testing::Item;
+    use crate::{FreeList};
+
+    testing::test_every_backend!(test_bounded_refuses_to_grow, test_bounded_never_allocates; except Reference);
+
+    #[test]
+    fn test_zero_capacity() {
+        let mut pool: Bounded<Item, FreeList<Item>> = Bounded::with_capacity(0);
+        assert!(pool.is_full());
+        assert!(pool.remaining() == 0);
+        assert!(pool.try_allocate(5) == Err(5));
+    }
+}
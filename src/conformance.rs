@@ -0,0 +1,932 @@
+use std::any::{type_name, Any};
+use std::hash::{Hash, Hasher};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{Debug, Write};
+use std::mem::discriminant;
+use std::panic::{self, AssertUnwindSafe, PanicHookInfo};
+use std::rc::Rc;
+use std::cell::Cell;
+use std::sync::Once;
+use rand::Rng;
+use rand::SeedableRng;
+use rand_xoshiro::Xoshiro256StarStar;
+use super::{Pool, InvariantError};
+use super::reference::Reference;
+
+/*
+    The suite every backend in this crate is tested with, exported behind the
+    conformance feature so that Pool implementations outside of it can be held
+    to the same standard. The fuzzers run the pool side by side with a
+    Reference pool, checking after every mutation that both hold the same
+    items and that check_invariants() still passes.
+
+    conformance_tests!() writes the whole suite out as #[test] functions:
+
+        #[cfg(test)]
+        mod conformance {
+            use super::MyPool;
+            pool_party::conformance_tests!(MyPool);
+        }
+
+    The pool is named by an identifier in scope, and has to take the item type
+    as its only generic parameter without a default, since the suite needs it
+    with i32 items and with Rc<()> items. Another item type and RNG seed for
+    the rest of the suite can be given with conformance_tests!(MyPool, u64, 7).
+*/
+
+// An item type the suite can make up values of. Different values should give different items, as far as the type has room for them.
+pub trait ConformanceItem: Clone + Ord + Debug {
+    fn from_u64(value: u64) -> Self;
+}
+
+macro_rules! impl_conformance_item_for_ints {
+    ($($int:ty),*) => {
+        $(
+            impl ConformanceItem for $int {
+                fn from_u64(value: u64) -> Self {
+                    return value as $int
+                }
+            }
+        )*
+    };
+}
+
+impl_conformance_item_for_ints!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+// Owns heap memory, so a pool that drops or moves its items wrongly shows up under Miri
+impl ConformanceItem for String {
+    fn from_u64(value: u64) -> Self {
+        return value.to_string()
+    }
+}
+
+pub const DEFAULT_RNG_SEED: u64 = 2049;
+
+#[macro_export]
+macro_rules! conformance_tests {
+    ($pool:ident) => {
+        $crate::conformance_tests!($pool, i32, $crate::conformance::DEFAULT_RNG_SEED);
+    };
+
+    ($pool:ident, $item:ty, $rng_seed:expr) => {
+        #[test]
+        #[should_panic]
+        fn test_invalid_get_to_empty_pool() {
+            $crate::conformance::test_invalid_get_to_empty_pool::<$item, $pool<$item>>();
+        }
+
+        #[test]
+        #[should_panic]
+        fn test_invalid_get_to_nonempty_pool() {
+            $crate::conformance::test_invalid_get_to_nonempty_pool::<$item, $pool<$item>>();
+        }
+
+        #[test]
+        fn test_one_item() {
+            $crate::conformance::test_one_item::<$item, $pool<$item>>();
+        }
+
+        #[test]
+        fn test_many_items() {
+            $crate::conformance::test_many_items::<$item, $pool<$item>>();
+        }
+
+        #[test]
+        fn test_items_are_dropped_exactly_once() {
+            $crate::conformance::test_items_are_dropped_exactly_once::<$pool<::std::rc::Rc<()>>>();
+        }
+
+        #[test]
+        fn fuzz_many_pools_few_mutations() {
+            $crate::conformance::fuzz_many_pools_few_mutations::<$item, $pool<$item>>($rng_seed);
+        }
+
+        #[test]
+        fn fuzz_few_pools_many_mutations() {
+            $crate::conformance::fuzz_few_pools_many_mutations::<$item, $pool<$item>>($rng_seed);
+        }
+    };
+}
+
+// Should panic
+pub fn test_invalid_get_to_empty_pool<I: ConformanceItem, P: Pool<I>>() {
+    let pool: P = Pool::new();
+    pool.get(3);
+}
+
+// Should panic
+pub fn test_invalid_get_to_nonempty_pool<I: ConformanceItem, P: Pool<I>>() {
+    let mut pool: P = Pool::new();
+    let mut ids: Vec<usize> = Vec::new();
+    for _ in 0..128 {
+        let id: usize = pool.allocate(I::from_u64(0));
+        ids.push(id);
+    }
+
+    pool.deallocate(ids[0]);
+    pool.get(ids[0]);
+}
+
+pub fn test_one_item<I: ConformanceItem, P: Pool<I>>() {
+    let mut pool: P = Pool::new();
+
+    let item: I = I::from_u64(72);
+    let id: usize = pool.allocate(item.clone());
+    assert!(pool.len() == 1);
+    assert!(pool.iter().cloned().collect::<Vec<I>>() == vec![item]);
+
+    pool.deallocate(id);
+    assert!(pool.len() == 0);
+    assert!(pool.iter().next().is_none());
+}
+
+pub fn test_many_items<I: ConformanceItem, P: Pool<I>>() {
+    let mut pool: P = Pool::new();
+    let mut map: HashMap<usize, I> = HashMap::new();
+    for i in 0..3827 {
+        let item: I = I::from_u64(i);
+        let id: usize = pool.allocate(item.clone());
+        map.insert(id, item);
+    }
+
+    for id in map.clone().keys() {
+        if id/2 % 3 == 0 {
+            pool.deallocate(*id);
+            map.remove(id);
+        }
+    }
+
+    assert!(pool.len() == map.len());
+    
+    for id in map.keys() {
+        assert!(pool.get(*id) == map.get(id).unwrap());
+    }
+
+    let mut pool_items: Vec<I> = pool.iter().cloned().collect();
+    let mut map_items: Vec<I> = map.values().cloned().collect();
+    pool_items.sort();
+    map_items.sort();
+    assert!(pool_items == map_items);
+}
+
+/*
+    Pools that keep their items in MaybeUninit slots are responsible for dropping
+    them by hand. Each item here holds a reference to the same Rc, so its strong 
+    count says exactly how many items are alive. Run this under Miri to also 
+    catch reads of uninitialized slots.
+*/
+pub fn test_items_are_dropped_exactly_once<T: Pool<Rc<()>>>() {
+    let counter: Rc<()> = Rc::new(());
+    let mut pool: T = Pool::new();
+    let mut ids: Vec<usize> = Vec::new();
+    for _ in 0..100 {
+        ids.push( pool.allocate(counter.clone()) );
+    }
+    assert!(Rc::strong_count(&counter) == 101);
+
+    for id in ids.iter().step_by(2) {
+        pool.deallocate(*id);
+    }
+    assert!(Rc::strong_count(&counter) == 51);
+
+    *pool.get_mut(ids[1]) = counter.clone(); // drops the item being overwritten
+    assert!(Rc::strong_count(&counter) == 51);
+    assert!(pool.iter().count() == 50);
+
+    for _ in 0..10 {
+        pool.allocate(counter.clone());
+    }
+    assert!(Rc::strong_count(&counter) == 61);
+
+    drop(pool);
+    assert!(Rc::strong_count(&counter) == 1);
+}
+
+pub fn fuzz_many_pools_few_mutations<I: ConformanceItem, P: Pool<I>>(rng_seed: u64) {
+    const NUM_POOLS_TO_FUZZ: usize = 10_000;
+    const MAX_NUM_MUTATIONS: usize = 10;
+    fuzz_many_item_pools::<I, P>(rng_seed, NUM_POOLS_TO_FUZZ, MAX_NUM_MUTATIONS);
+}
+
+pub fn fuzz_few_pools_many_mutations<I: ConformanceItem, P: Pool<I>>(rng_seed: u64) {
+    const NUM_POOLS_TO_FUZZ: usize = 100;
+    const MAX_NUM_MUTATIONS: usize = 1000;
+    fuzz_many_item_pools::<I, P>(rng_seed, NUM_POOLS_TO_FUZZ, MAX_NUM_MUTATIONS);
+}
+
+// Hey, it's like that Leetcode problem
+// https://leetcode.com/problems/insert-delete-getrandom-o1/
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct PairOfIds {
+    id_in_test: usize,
+    id_in_reference: usize,
+}
+
+impl Hash for PairOfIds {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_usize(self.id_in_test);
+        state.write_usize(self.id_in_reference);
+    }
+}
+
+struct Allocations {
+    map: HashMap<PairOfIds, usize>, // ids -> index in vec that contains ids
+    vec: Vec<PairOfIds>,
+}
+
+impl Allocations {
+    pub fn new() -> Self {
+        return Self {
+            map: HashMap::default(),
+            vec: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, ids: PairOfIds) {
+        self.map.insert(ids, self.vec.len());
+        self.vec.push(ids);
+    }
+
+    // This function right here is the reason for this data structure to exist- it
+    // removes ids in O(1) time. It's a lot faster to run tests with this vs something 
+    // like a Vec with O(n) removal 
+    pub fn remove(&mut self, removed_ids: PairOfIds) {        
+        let index_of_removed_ids: usize = *self.map.get(&removed_ids).unwrap();
+        let index_of_last_ids: usize = self.vec.len()-1;
+        if index_of_removed_ids != index_of_last_ids {
+            let last_ids: PairOfIds = self.vec[ index_of_last_ids ];
+            self.vec[ index_of_removed_ids ] = last_ids;
+            self.map.insert(last_ids, index_of_removed_ids);
+        }
+        self.vec.pop().unwrap();
+        self.map.remove(&removed_ids);
+    }
+
+    pub fn get_random_pair_of_ids<T: Rng>(&self, rng: &mut T) -> Option<PairOfIds> {
+        if self.vec.is_empty() {
+            return None
+        }
+
+        let index: usize = rng.gen_range(0..self.len());
+        return Some( self.vec[index] )
+    }
+
+    pub fn len(&self) -> usize {
+        return self.map.len()
+    }
+
+    pub fn pairs_of_ids(&self) -> impl Iterator<Item=&PairOfIds> {
+        return self.vec.iter()
+    }
+}
+
+// Constructions
+const NEW: usize = 0;
+const WITH_CAPACITY: usize = 1;
+
+#[derive(Clone, Copy, Debug)]
+enum Construction {
+    New,
+    WithCapacity{capacity: usize},
+}
+
+fn generate_random_construction<T: Rng>(rng: &mut T) -> Construction {
+    const MAX_CAPACITY: usize = 10_000;
+    let construction: usize = rng.gen_range(NEW..=WITH_CAPACITY);
+    match construction {
+        NEW => {
+            return Construction::New
+        },
+
+        WITH_CAPACITY => {
+            let capacity: usize = rng.gen_range(0..MAX_CAPACITY);
+            return Construction::WithCapacity{ capacity }
+        },
+
+        _ => unreachable!(),
+    }
+}
+
+// Mutations
+const SET: usize = 0;
+const ALLOCATE: usize = 1;
+const DEALLOCATE: usize = 2;
+const NUM_MUTATIONS: usize = 3;
+
+// Items are logged as the values they're made from with ConformanceItem::from_u64(), which is also how a minimized failure writes them out
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+enum Mutation {
+    Set{id_in_test: usize, id_in_reference: usize, value: u64},
+    Allocate{value: u64},
+    Deallocate{id_in_test: usize, id_in_reference: usize},
+}
+
+struct MutationGenerator {
+    tokens: [usize; NUM_MUTATIONS],
+    total_num_tokens: usize,
+}
+
+impl MutationGenerator {    
+    const MAX_NUM_TOKENS: usize = 10;
+    
+    pub fn new<T: Rng>(rng: &mut T) -> Self {
+        let mut tokens: [usize; NUM_MUTATIONS] = [0; NUM_MUTATIONS];
+        let mut total_num_tokens: usize = 0;
+        for mutation in 0..NUM_MUTATIONS {
+            let num_tokens: usize = rng.gen_range(1..=Self::MAX_NUM_TOKENS);
+            tokens[mutation] = num_tokens;
+            total_num_tokens += num_tokens;
+        }
+
+        assert!(tokens.iter().cloned().all(|num_tokens| num_tokens > 0));
+
+        return Self { 
+            tokens,
+            total_num_tokens 
+        }
+    }
+
+    pub fn generate<T: Rng>(&mut self, rng: &mut T) -> usize {
+        let token: usize = rng.gen_range(1..=self.total_num_tokens);
+        let mut num_tokens_so_far: usize = 0;
+        for mutation in 0..self.tokens.len() {
+            assert!(token > num_tokens_so_far);
+            let token: usize = token - num_tokens_so_far;
+            if token <= self.tokens[mutation] {
+                return mutation
+            }
+            num_tokens_so_far += self.tokens[mutation];
+        }
+        unreachable!();
+    }
+
+    pub fn _get_probability(&self, mutation: usize) -> f64 {
+        return self.tokens[mutation] as f64 / self.total_num_tokens as f64
+    }
+
+    pub fn _relative_probabilities(&self) -> impl Iterator<Item=&usize> {
+        return self.tokens.iter()
+    }
+}
+
+#[derive(Debug)]
+enum EqualityError {
+    NumItemsDontMatch,
+    ItemsDontMatch,
+    GetsDontMatch,
+}
+
+fn compare_for_equality<I: ConformanceItem, P: Pool<I>> (
+    test: &P, 
+    reference: &Reference<I>,
+    allocations: &Allocations,
+) 
+-> Result<(), EqualityError>
+{
+    if test.len() != reference.len() {
+        return Err( EqualityError::NumItemsDontMatch )
+    }
+
+    let mut items_in_test: Vec<&I> = test.iter().collect();
+    let mut items_in_reference: Vec<&I> = reference.iter().collect();
+    items_in_test.sort();
+    items_in_reference.sort();
+    if items_in_test != items_in_reference {
+        return Err( EqualityError::ItemsDontMatch )
+    }
+
+    for pair in allocations.pairs_of_ids() {
+        if *test.get( pair.id_in_test ) != *reference.get( pair.id_in_reference ) {
+            return Err( EqualityError::GetsDontMatch )
+        }
+    }
+
+    return Ok(())
+}
+
+#[derive(Clone, Copy, Debug)]
+enum LogEntry {
+    Construction(Construction),
+    Mutation(Mutation),
+}
+
+// Whatever made a fuzzed pool stop agreeing with the reference pool, or fall over on its own
+#[allow(dead_code)]
+#[derive(Debug)]
+enum Failure {
+    NotEqual(EqualityError),
+    BrokenInvariant(InvariantError),
+    Panic(String),
+}
+
+impl Failure {
+    // Going by the kind of failure only, since the ids and counts in it shift as mutations are taken out
+    fn is_like(&self, other: &Failure) -> bool {
+        match (self, other) {
+            (Failure::NotEqual(error), Failure::NotEqual(other_error)) => return discriminant(error) == discriminant(other_error),
+            (Failure::BrokenInvariant(error), Failure::BrokenInvariant(other_error)) => return discriminant(error) == discriminant(other_error),
+            (Failure::Panic(_), Failure::Panic(_)) => return true,
+            _ => return false,
+        }
+    }
+
+    fn from_panic(payload: Box<dyn Any + Send>) -> Self {
+        if let Some(message) = payload.downcast_ref::<&str>() {
+            return Failure::Panic(message.to_string())
+        }
+        if let Some(message) = payload.downcast_ref::<String>() {
+            return Failure::Panic(message.clone())
+        }
+        return Failure::Panic(String::new())
+    }
+}
+
+fn check<I: ConformanceItem, P: Pool<I>>(test: &P, reference: &Reference<I>, allocations: &Allocations) -> Result<(), Failure> {
+    compare_for_equality(test, reference, allocations).map_err(Failure::NotEqual)?;
+    test.check_invariants().map_err(Failure::BrokenInvariant)?;
+    return Ok(())
+}
+
+fn construct<I, P: Pool<I>>(construction: Construction) -> P {
+    match construction {
+        Construction::New => return P::new(),
+        Construction::WithCapacity{ capacity } => return P::with_capacity(capacity),
+    }
+}
+
+fn fuzz<R: Rng, I: ConformanceItem, P: Pool<I>>(rng: &mut R, num_mutations_to_try: usize) {
+    let mut log: Vec<LogEntry> = Vec::new();
+    let failure: Failure = match panic::catch_unwind(AssertUnwindSafe(|| fuzz_and_log::<R, I, P>(rng, num_mutations_to_try, &mut log))) {
+        Ok(Ok(())) => return,
+        Ok(Err(failure)) => failure,
+        Err(payload) => Failure::from_panic(payload),
+    };
+
+    let (construction, steps): (Construction, Vec<Step>) = minimize::<I, P>(&log, &failure);
+
+    panic!("{:?} after {} mutations, which shrink down to:\n\n{}", failure, log.len() - 1, write_test::<I, P>(construction, &steps));
+}
+
+/*
+    Comparing against the reference pool checks the behavior each impl shows
+    from the outside, and check_invariants() checks the internal state behind
+    it, after every single mutation. Each mutation is logged before it's made,
+    so that the log still ends with the culprit when the pool panics.
+*/
+fn fuzz_and_log<R: Rng, I: ConformanceItem, P: Pool<I>>(rng: &mut R, num_mutations_to_try: usize, log: &mut Vec<LogEntry>) -> Result<(), Failure> {
+    let mut allocations: Allocations = Allocations::new();
+
+    let construction: Construction = generate_random_construction(rng);
+    log.push( LogEntry::Construction(construction) );
+    let mut test: P = construct(construction);
+    let mut reference: Reference<I> = construct(construction);
+    check(&test, &reference, &allocations)?;
+
+    const MAX_NUM_ITEMS_TO_ALLOCATE: usize = 100_000; // don't want to OoM
+    let mut generator: MutationGenerator = MutationGenerator::new(rng);
+    for _ in 0..num_mutations_to_try {
+        match generator.generate(rng) {
+            SET => {
+                if reference.len() == 0 {
+                    continue;
+                }
+                
+                let pair: PairOfIds = allocations.get_random_pair_of_ids(rng).unwrap();
+                let id_in_test: usize = pair.id_in_test;
+                let id_in_reference: usize = pair.id_in_reference;
+                let value: u64 = rng.gen();
+                log.push( 
+                    LogEntry::Mutation( 
+                        Mutation::Set {
+                            id_in_test,
+                            id_in_reference, 
+                            value
+                        } 
+                    ) 
+                );  
+
+                *test.get_mut(id_in_test) = I::from_u64(value);
+                *reference.get_mut(id_in_reference) = I::from_u64(value);
+            },
+
+            ALLOCATE => {
+                if reference.len() >= MAX_NUM_ITEMS_TO_ALLOCATE {
+                    continue;
+                }
+
+                let value: u64 = rng.gen();
+                log.push(
+                    LogEntry::Mutation( 
+                        Mutation::Allocate { 
+                            value
+                        } 
+                    ) 
+                );
+
+                let id_in_test: usize = test.allocate(I::from_u64(value));
+                let id_in_reference: usize = reference.allocate(I::from_u64(value));
+                allocations.add( 
+                    PairOfIds {
+                        id_in_test, 
+                        id_in_reference
+                    }
+                );
+            },
+
+            DEALLOCATE => {
+                if reference.len() == 0 {
+                    continue;
+                }
+
+                let pair: PairOfIds = allocations.get_random_pair_of_ids(rng).unwrap();
+                let id_in_test: usize = pair.id_in_test;
+                let id_in_reference: usize = pair.id_in_reference;
+                log.push( 
+                    LogEntry::Mutation(
+                        Mutation::Deallocate {
+                            id_in_test, 
+                            id_in_reference
+                        } 
+                    ) 
+                );
+
+                test.deallocate(id_in_test);
+                reference.deallocate(id_in_reference);
+                allocations.remove(pair);
+            },
+
+            _ => unreachable!(),
+        }
+
+        check(&test, &reference, &allocations)?;
+    }
+    return Ok(())
+}
+
+fn fuzz_many_item_pools<I: ConformanceItem, P: Pool<I>>(rng_seed: u64, num_pools_to_fuzz: usize, max_num_mutations: usize) {
+    /*
+        Xoshiro256StarStar is a deterministic PRNG, so a given seed makes these tests
+        consistently reproducible (you get exactly the same sequence of operations 
+        every single time fuzz_many_item_pools() is called with it)
+    */
+    let mut rng: Xoshiro256StarStar = Xoshiro256StarStar::seed_from_u64(rng_seed);
+
+    for _ in 0..num_pools_to_fuzz {
+        fuzz::<_, I, P>(&mut rng, max_num_mutations);
+    }
+}
+
+/*
+    Shrinking a failing log
+
+    Taking a mutation out of the log changes the ids every later allocation
+    gets, so the log is first turned into steps that refer to items by handle,
+    the number of allocations made before the item's own. Replaying the steps
+    hands out fresh ids, and the handles are mapped onto those as it goes.
+
+    This relies on the pool being deterministic, handing out the same ids when
+    it's made to do the same things.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Step {
+    Set{handle: usize, value: u64},
+    Allocate{handle: usize, value: u64},
+    Deallocate{handle: usize},
+}
+
+/*
+    Makes the log's allocations and deallocations again on a fresh pool, to
+    find out which item each id in it was referring to at the time. The last
+    mutation isn't made, since it may be the one that panicked, and nothing
+    after it needs the ids it would hand out.
+*/
+fn steps_of<I: ConformanceItem, P: Pool<I>>(log: &[LogEntry]) -> (Construction, Vec<Step>) {
+    let construction: Construction = match log[0] {
+        LogEntry::Construction(construction) => construction,
+        LogEntry::Mutation(_) => unreachable!(),
+    };
+
+    let mut test: P = construct(construction);
+    let mut handles: HashMap<usize, usize> = HashMap::new(); // from the id of every live item in test to its handle
+    let mut num_allocations: usize = 0;
+    let mut steps: Vec<Step> = Vec::new();
+    for (i, entry) in log[1..].iter().enumerate() {
+        let is_last: bool = i == log.len() - 2;
+        match *entry {
+            LogEntry::Mutation(Mutation::Set{ id_in_test, value, .. }) => {
+                steps.push( Step::Set{ handle: handles[&id_in_test], value } );
+            },
+
+            LogEntry::Mutation(Mutation::Allocate{ value }) => {
+                steps.push( Step::Allocate{ handle: num_allocations, value } );
+                if !is_last {
+                    handles.insert(test.allocate(I::from_u64(value)), num_allocations);
+                }
+                num_allocations += 1;
+            },
+
+            LogEntry::Mutation(Mutation::Deallocate{ id_in_test, .. }) => {
+                steps.push( Step::Deallocate{ handle: handles[&id_in_test] } );
+                if !is_last {
+                    test.deallocate(id_in_test);
+                    handles.remove(&id_in_test);
+                }
+            },
+
+            LogEntry::Construction(_) => unreachable!(),
+        }
+    }
+    return (construction, steps)
+}
+
+/*
+    Minimizing can make thousands of replays that fail by panicking, and every
+    one of them would print its message. Swapping the panic hook out while it
+    runs would silence the tests running on other threads too, so a hook is
+    installed once and for all that only skips the panics of threads in the
+    middle of a replay, and hands the rest to the hook that was there before.
+*/
+thread_local! {
+    static IS_REPLAYING: Cell<bool> = const { Cell::new(false) };
+}
+
+static INSTALL_QUIET_HOOK: Once = Once::new();
+
+fn install_quiet_hook() {
+    INSTALL_QUIET_HOOK.call_once(|| {
+        let previous_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info: &PanicHookInfo| {
+            if !IS_REPLAYING.try_with(Cell::get).unwrap_or(false) {
+                previous_hook(info);
+            }
+        }));
+    });
+}
+
+// Makes the steps on fresh pools, checking them after every one the way fuzz_and_log() does
+fn replay<I: ConformanceItem, P: Pool<I>>(construction: Construction, steps: &[Step]) -> Result<(), Failure> {
+    install_quiet_hook();
+    let was_replaying: bool = IS_REPLAYING.replace(true);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| -> Result<(), Failure> {
+        let mut allocations: Allocations = Allocations::new();
+        let mut ids: HashMap<usize, PairOfIds> = HashMap::new(); // from the handle of every live item to the ids it got this time around
+        let mut test: P = construct(construction);
+        let mut reference: Reference<I> = construct(construction);
+        check(&test, &reference, &allocations)?;
+
+        for step in steps.iter() {
+            match *step {
+                Step::Set{ handle, value } => {
+                    let pair: PairOfIds = ids[&handle];
+                    *test.get_mut(pair.id_in_test) = I::from_u64(value);
+                    *reference.get_mut(pair.id_in_reference) = I::from_u64(value);
+                },
+
+                Step::Allocate{ handle, value } => {
+                    let pair: PairOfIds = PairOfIds {
+                        id_in_test: test.allocate(I::from_u64(value)),
+                        id_in_reference: reference.allocate(I::from_u64(value)),
+                    };
+                    allocations.add(pair);
+                    ids.insert(handle, pair);
+                },
+
+                Step::Deallocate{ handle } => {
+                    let pair: PairOfIds = ids.remove(&handle).unwrap();
+                    test.deallocate(pair.id_in_test);
+                    reference.deallocate(pair.id_in_reference);
+                    allocations.remove(pair);
+                },
+            }
+
+            check(&test, &reference, &allocations)?;
+        }
+        return Ok(())
+    }));
+    IS_REPLAYING.set(was_replaying);
+
+    match result {
+        Ok(result) => return result,
+        Err(payload) => return Err(Failure::from_panic(payload)),
+    }
+}
+
+// Takes out the steps on items whose allocation was taken out
+fn without_orphans(steps: &[Step]) -> Vec<Step> {
+    let mut live: HashSet<usize> = HashSet::new();
+    return steps.iter().copied().filter(|step: &Step| {
+        match *step {
+            Step::Set{ handle, .. } => return live.contains(&handle),
+            Step::Allocate{ handle, .. } => return live.insert(handle),
+            Step::Deallocate{ handle } => return live.remove(&handle),
+        }
+    }).collect()
+}
+
+/*
+    Delta debugging. Chunks of steps are taken out for as long as the failure
+    still happens without them, and the chunks are halved whenever none of
+    them can go, until not even a single step can be taken out. A pool made
+    with_capacity() is swapped for one made with new() first, if that fails
+    the same way.
+*/
+fn minimize<I: ConformanceItem, P: Pool<I>>(log: &[LogEntry], failure: &Failure) -> (Construction, Vec<Step>) {
+    let (mut construction, mut steps): (Construction, Vec<Step>) = steps_of::<I, P>(log);
+    let fails_the_same_way = |construction: Construction, steps: &[Step]| -> bool {
+        match replay::<I, P>(construction, steps) {
+            Ok(()) => return false,
+            Err(other_failure) => return other_failure.is_like(failure),
+        }
+    };
+
+    if let Construction::WithCapacity{ .. } = construction {
+        if fails_the_same_way(Construction::New, &steps) {
+            construction = Construction::New;
+        }
+    }
+
+    let mut num_chunks: usize = 2;
+    while !steps.is_empty() {
+        let chunk_len: usize = steps.len().div_ceil(num_chunks);
+        let mut took_any_out: bool = false;
+        let mut start: usize = 0;
+        while start < steps.len() {
+            let end: usize = usize::min(start + chunk_len, steps.len());
+            let candidate: Vec<Step> = without_orphans(&[&steps[..start], &steps[end..]].concat());
+            if fails_the_same_way(construction, &candidate) {
+                steps = candidate;
+                took_any_out = true;
+            } else {
+                start = end;
+            }
+        }
+
+        if took_any_out {
+            num_chunks = usize::max(num_chunks - 1, 2);
+        } else if chunk_len == 1 {
+            break;
+        } else {
+            num_chunks = usize::min(num_chunks * 2, steps.len());
+        }
+    }
+    return (construction, steps)
+}
+
+/*
+    Writes the steps out as a test that fails the same way, checking what
+    compare_for_equality() and check_invariants() would have after the last
+    one. It's meant to go next to the pool's own tests, with the pool type in
+    scope.
+*/
+fn write_test<I: ConformanceItem, P: Pool<I>>(construction: Construction, steps: &[Step]) -> String {
+    let mut test: String = String::new();
+    writeln!(test, "#[test]").unwrap();
+    writeln!(test, "fn test_minimized_fuzz_failure() {{").unwrap();
+    writeln!(test, "    use pool_party::Pool;").unwrap();
+    writeln!(test, "    use pool_party::conformance::ConformanceItem;").unwrap();
+    writeln!(test, "    type I = {};", short_type_name::<I>()).unwrap();
+    writeln!(test, "    type P = {};", short_type_name::<P>()).unwrap();
+    writeln!(test).unwrap();
+    match construction {
+        Construction::New => writeln!(test, "    let mut pool: P = Pool::new();").unwrap(),
+        Construction::WithCapacity{ capacity } => writeln!(test, "    let mut pool: P = Pool::with_capacity({});", capacity).unwrap(),
+    }
+
+    let mut values: BTreeMap<usize, u64> = BTreeMap::new(); // from the handle of every live item to what it holds
+    for step in steps.iter() {
+        match *step {
+            Step::Set{ handle, value } => {
+                writeln!(test, "    *pool.get_mut(id_{}) = I::from_u64({});", handle, value).unwrap();
+                values.insert(handle, value);
+            },
+
+            Step::Allocate{ handle, value } => {
+                writeln!(test, "    let id_{}: usize = pool.allocate(I::from_u64({}));", handle, value).unwrap();
+                values.insert(handle, value);
+            },
+
+            Step::Deallocate{ handle } => {
+                writeln!(test, "    pool.deallocate(id_{});", handle).unwrap();
+                values.remove(&handle);
+            },
+        }
+    }
+
+    writeln!(test).unwrap();
+    writeln!(test, "    assert!(pool.len() == {});", values.len()).unwrap();
+    writeln!(test, "    let mut items: Vec<I> = pool.iter().cloned().collect();").unwrap();
+    write!(test, "    let mut expected_items: Vec<I> = vec![").unwrap();
+    for (i, value) in values.values().enumerate() {
+        if i > 0 {
+            write!(test, ", ").unwrap();
+        }
+        write!(test, "I::from_u64({})", value).unwrap();
+    }
+    writeln!(test, "];").unwrap();
+    writeln!(test, "    items.sort();").unwrap();
+    writeln!(test, "    expected_items.sort();").unwrap();
+    writeln!(test, "    assert!(items == expected_items);").unwrap();
+    for (handle, value) in values.iter() {
+        writeln!(test, "    assert!(*pool.get(id_{}) == I::from_u64({}));", handle, value).unwrap();
+    }
+    writeln!(test, "    pool.check_invariants().unwrap();").unwrap();
+    writeln!(test, "}}").unwrap();
+    return test
+}
+
+// type_name() gives the full path of every type in the name, which mostly goes through private modules
+fn short_type_name<T>() -> String {
+    let mut name: String = String::new();
+    for piece in type_name::<T>().split_inclusive(['<', '>', ',', ' ', '(', ')', '[', ']', ';', '&']) {
+        name.push_str(piece.rsplit("::").next().unwrap());
+    }
+    return name
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_xoshiro::Xoshiro256StarStar;
+    use crate::{Pool, FreeList, Simple, PoolStats, HeapUsage, InvariantError};
+    use super::{Construction, LogEntry, Failure, Step, fuzz_and_log, minimize, write_test};
+
+    // Everything in the crate is already run with i32 items, so this checks the macro with ones that own heap memory
+    crate::conformance_tests!(FreeList, String, 44);
+
+    // Simple, but with a bug that takes three items at once to show up
+    struct ThreeIsACrowd<T: Clone>(Simple<T>);
+
+    impl <T: Clone> Pool<T> for ThreeIsACrowd<T> {
+        type Iter<'a> = <Simple<T> as Pool<T>>::Iter<'a> where T: 'a;
+
+        fn new() -> Self {
+            return Self(Simple::new())
+        }
+
+        fn with_capacity(num_items: usize) -> Self {
+            return Self(Simple::with_capacity(num_items))
+        }
+
+        fn len(&self) -> usize {
+            return self.0.len()
+        }
+
+        fn capacity(&self) -> usize {
+            return self.0.capacity()
+        }
+
+        fn stats(&self) -> PoolStats {
+            return self.0.stats()
+        }
+
+        fn heap_size_bytes_with<F: FnMut(&T) -> usize>(&self, item_heap_size_bytes: F) -> HeapUsage {
+            return self.0.heap_size_bytes_with(item_heap_size_bytes)
+        }
+
+        fn check_invariants(&self) -> Result<(), InvariantError> {
+            if self.0.len() == 3 {
+                return Err(InvariantError::NumItemsDoesntMatchSlots{ num_items: 3, num_allocated_slots: 2 })
+            }
+            return self.0.check_invariants()
+        }
+
+        fn get(&self, id: usize) -> &T {
+            return self.0.get(id)
+        }
+
+        fn get_mut(&mut self, id: usize) -> &mut T {
+            return self.0.get_mut(id)
+        }
+
+        fn allocate(&mut self, item: T) -> usize {
+            return self.0.allocate(item)
+        }
+
+        fn deallocate(&mut self, id: usize) {
+            self.0.deallocate(id);
+        }
+
+        fn iter<'a>(&'a self) -> Self::Iter<'a> {
+            return self.0.iter()
+        }
+    }
+
+    #[test]
+    fn test_failures_are_minimized() {
+        let mut rng: Xoshiro256StarStar = Xoshiro256StarStar::seed_from_u64(45);
+        let mut log: Vec<LogEntry> = Vec::new();
+        let failure: Failure = fuzz_and_log::<_, i32, ThreeIsACrowd<i32>>(&mut rng, 1000, &mut log).unwrap_err();
+        assert!(matches!(failure, Failure::BrokenInvariant(_)));
+
+        let (construction, steps): (Construction, Vec<Step>) = minimize::<i32, ThreeIsACrowd<i32>>(&log, &failure);
+        assert!(matches!(construction, Construction::New));
+        assert!(steps.len() == 3);
+        assert!(steps.iter().all(|step: &Step| matches!(step, Step::Allocate{ .. })));
+
+        let test: String = write_test::<i32, ThreeIsACrowd<i32>>(construction, &steps);
+        assert!(test.contains("type P = ThreeIsACrowd<i32>;"));
+        assert!(test.matches("pool.allocate(").count() == 3);
+        assert!(test.contains("pool.check_invariants().unwrap();"));
+    }
+}
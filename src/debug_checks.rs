@@ -0,0 +1,262 @@
+#[cfg(feature = "debug-checks")]
+use alloc::collections::BTreeMap;
+#[cfg(not(feature = "debug-checks"))]
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+#[cfg(feature = "debug-checks")]
+use core::mem::size_of;
+use core::panic::Location;
+use allocator_api2::alloc::Allocator;
+#[cfg(feature = "debug-checks")]
+use allocator_api2::vec::Vec;
+
+/*
+    With the debug-checks feature, every backend remembers where each of its
+    free slots was last freed from, so that using an id after it's been freed
+    panics with where it happened:
+
+        id 42 was freed at src/foo.rs:10, accessed at src/bar.rs:20
+
+    Pool's get(), get_mut(), allocate() and deallocate() are #[track_caller]
+    with it on, which makes the locations the ones in the code calling the
+    pool. The generation is how many times the slot has been freed, for telling
+    apart the items an id has held over time when it's reused. Where each live
+    item was allocated from is kept as well, for leak reports.
+
+    The histories are a Vec indexed by id, in the pool's own allocator. Backends
+    size it along with their storage, so it's counted by heap_size_bytes() and
+    only grows when the pool does. Reference's ids are addresses, which can't
+    index anything, so it keeps AddressHistories in a map on the global heap
+    instead, next to its own.
+
+    Without the feature both are empty and all of it compiles away.
+*/
+#[cfg(feature = "debug-checks")]
+#[derive(Clone, Copy, Debug)]
+struct SlotHistory {
+    generation: usize,
+    allocated_at: Option<&'static Location<'static>>,
+    freed_at: Option<&'static Location<'static>>, // None while the slot holds an item
+}
+
+#[cfg(feature = "debug-checks")]
+impl SlotHistory {
+    const NEVER_USED: Self = Self { generation: 0, allocated_at: None, freed_at: None };
+
+    #[track_caller]
+    fn freed(&mut self) {
+        self.generation += 1;
+        self.freed_at = Some(Location::caller());
+    }
+
+    #[track_caller]
+    fn allocated(&mut self) {
+        self.allocated_at = Some(Location::caller());
+        self.freed_at = None;
+    }
+
+    #[track_caller]
+    fn check(&self, id: usize, what_happened: &str) {
+        if let Some(freed_at) = self.freed_at {
+            panic!("id {} was freed at {}, {} at {} (generation {})", id, freed_at, what_happened, Location::caller(), self.generation);
+        }
+    }
+}
+
+#[cfg(feature = "debug-checks")]
+pub(crate) struct SlotHistories<A: Allocator> {
+    slots: Vec<SlotHistory, A>,
+}
+
+#[cfg(not(feature = "debug-checks"))]
+pub(crate) struct SlotHistories<A: Allocator> {
+    _alloc: PhantomData<A>,
+}
+
+#[cfg(feature = "debug-checks")]
+#[derive(Clone, Debug, Default)]
+pub(crate) struct AddressHistories {
+    slots: BTreeMap<usize, SlotHistory>,
+}
+
+#[cfg(not(feature = "debug-checks"))]
+#[derive(Clone, Debug, Default)]
+pub(crate) struct AddressHistories;
+
+#[cfg(feature = "debug-checks")]
+impl <A: Allocator> SlotHistories<A> {
+    pub(crate) fn new_in(alloc: A) -> Self {
+        return Self { slots: Vec::new_in(alloc) }
+    }
+
+    pub(crate) fn with_capacity_in(num_slots: usize, alloc: A) -> Self {
+        let mut slots: Vec<SlotHistory, A> = Vec::with_capacity_in(num_slots, alloc);
+        slots.resize(num_slots, SlotHistory::NEVER_USED);
+        return Self { slots }
+    }
+
+    // Called when the pool's storage grows, so that the histories grow with it rather than one id at a time
+    pub(crate) fn grow_to(&mut self, num_slots: usize) {
+        if num_slots > self.slots.len() {
+            self.slots.resize(num_slots, SlotHistory::NEVER_USED);
+        }
+    }
+
+    pub(crate) fn heap_size_bytes(&self) -> usize {
+        return self.slots.capacity() * size_of::<SlotHistory>()
+    }
+
+    #[track_caller]
+    pub(crate) fn check_access(&self, id: usize) {
+        if let Some(history) = self.slots.get(id) {
+            history.check(id, "accessed");
+        }
+    }
+
+    #[track_caller]
+    pub(crate) fn check_deallocate(&self, id: usize) {
+        if let Some(history) = self.slots.get(id) {
+            history.check(id, "freed again");
+        }
+    }
+
+    #[track_caller]
+    pub(crate) fn freed(&mut self, id: usize) {
+        self.grow_to(id+1);
+        self.slots[id].freed();
+    }
+
+    #[track_caller]
+    pub(crate) fn allocated(&mut self, id: usize) {
+        self.grow_to(id+1);
+        self.slots[id].allocated();
+    }
+
+    pub(crate) fn allocated_at(&self, id: usize) -> Option<&'static Location<'static>> {
+        return self.slots.get(id).and_then(|history: &SlotHistory| history.allocated_at)
+    }
+}
+
+#[cfg(not(feature = "debug-checks"))]
+impl <A: Allocator> SlotHistories<A> {
+    pub(crate) fn new_in(_alloc: A) -> Self {
+        return Self { _alloc: PhantomData }
+    }
+
+    pub(crate) fn with_capacity_in(_num_slots: usize, _alloc: A) -> Self {
+        return Self { _alloc: PhantomData }
+    }
+
+    #[inline(always)]
+    pub(crate) fn grow_to(&mut self, _num_slots: usize) {}
+
+    #[inline(always)]
+    pub(crate) fn heap_size_bytes(&self) -> usize {
+        return 0
+    }
+
+    #[inline(always)]
+    pub(crate) fn check_access(&self, _id: usize) {}
+
+    #[inline(always)]
+    pub(crate) fn check_deallocate(&self, _id: usize) {}
+
+    #[inline(always)]
+    pub(crate) fn freed(&mut self, _id: usize) {}
+
+    #[inline(always)]
+    pub(crate) fn allocated(&mut self, _id: usize) {}
+
+    #[inline(always)]
+    pub(crate) fn allocated_at(&self, _id: usize) -> Option<&'static Location<'static>> {
+        return None
+    }
+}
+
+#[cfg(feature = "debug-checks")]
+impl AddressHistories {
+    pub(crate) fn new() -> Self {
+        return Self::default()
+    }
+
+    #[track_caller]
+    pub(crate) fn check_access(&self, id: usize) {
+        if let Some(history) = self.slots.get(&id) {
+            history.check(id, "accessed");
+        }
+    }
+
+    #[track_caller]
+    pub(crate) fn check_deallocate(&self, id: usize) {
+        if let Some(history) = self.slots.get(&id) {
+            history.check(id, "freed again");
+        }
+    }
+
+    #[track_caller]
+    pub(crate) fn freed(&mut self, id: usize) {
+        self.slots.entry(id).or_insert(SlotHistory::NEVER_USED).freed();
+    }
+
+    #[track_caller]
+    pub(crate) fn allocated(&mut self, id: usize) {
+        self.slots.entry(id).or_insert(SlotHistory::NEVER_USED).allocated();
+    }
+
+    pub(crate) fn allocated_at(&self, id: usize) -> Option<&'static Location<'static>> {
+        return self.slots.get(&id).and_then(|history: &SlotHistory| history.allocated_at)
+    }
+}
+
+#[cfg(not(feature = "debug-checks"))]
+impl AddressHistories {
+    pub(crate) fn new() -> Self {
+        return Self
+    }
+
+    #[inline(always)]
+    pub(crate) fn check_access(&self, _id: usize) {}
+
+    #[inline(always)]
+    pub(crate) fn check_deallocate(&self, _id: usize) {}
+
+    #[inline(always)]
+    pub(crate) fn freed(&mut self, _id: usize) {}
+
+    #[inline(always)]
+    pub(crate) fn allocated(&mut self, _id: usize) {}
+
+    #[inline(always)]
+    pub(crate) fn allocated_at(&self, _id: usize) -> Option<&'static Location<'static>> {
+        return None
+    }
+}
+
+#[cfg(feature = "debug-checks")]
+const POISON: u8 = 0xdd;
+
+/*
+    Overwrites a slot whose item has just been dropped, so that anything still
+    reading it through a stale pointer gets obvious garbage instead of an item
+    that looks alive.
+*/
+pub(crate) fn poison<T>(slot: &mut MaybeUninit<T>) {
+    #[cfg(feature = "debug-checks")]
+    unsafe { slot.as_mut_ptr().write_bytes(POISON, 1) };
+    #[cfg(not(feature = "debug-checks"))]
+    let _ = slot;
+}
+
+#[cfg(all(test, feature = "debug-checks"))]
+mod tests {
+    use crate::testing;
+
+    testing::test_every_backend!(test_use_after_free_is_reported);
+
+    // Reference's ids are addresses, which only come back if the global allocator happens to hand them out again
+    mod reuse {
+        use crate::testing;
+
+        testing::test_every_backend!(test_reused_ids_start_a_new_generation; except Reference);
+    }
+}
@@ -1,28 +1,50 @@
 use super::FlagVec;
-use std::mem::size_of;
+use crate::InvariantError;
+use crate::heap_size::vec_heap_size_bytes;
+use core::mem::size_of;
+use core::ops::Range;
+use allocator_api2::alloc::{Allocator, Global};
+use allocator_api2::vec;
+use allocator_api2::vec::Vec;
 
 pub type Block = u32;
 pub const BITS_PER_BYTE: usize = 8;
 pub const BITS_PER_BLOCK: usize = size_of::<Block>() * BITS_PER_BYTE;
 
 #[derive(Clone, Debug)]
-pub struct BitVec {
-    pub flags: Vec<Block>,
+pub struct BitVec<A: Allocator = Global> {
+    pub flags: Vec<Block, A>,
     pub num_bits: usize,
 }
 
 impl BitVec {
     pub fn new() -> Self {
+        return Self::new_in(Global)
+    }
+
+    pub fn with_bits(num_bits: usize, value: bool) -> Self {
+        return Self::with_bits_in(num_bits, value, Global)
+    }
+}
+
+impl Default for BitVec {
+    fn default() -> Self {
+        return Self::new()
+    }
+}
+
+impl <A: Allocator + Clone> BitVec<A> {
+    pub fn new_in(alloc: A) -> Self {
         return Self {
-            flags: Vec::new(),
+            flags: Vec::new_in(alloc),
             num_bits: 0,
         }
     }
 
-    pub fn with_bits(num_bits: usize, value: bool) -> Self {
+    pub fn with_bits_in(num_bits: usize, value: bool, alloc: A) -> Self {
         if num_bits == 0 {
             return Self{
-                flags: Vec::new(),
+                flags: Vec::new_in(alloc),
                 num_bits
             }
         }
@@ -30,7 +52,7 @@ impl BitVec {
         let value: Block = if value { Block::MAX } else { 0 };
         let num_blocks: usize = ((num_bits-1)/BITS_PER_BLOCK)+1;
         return Self {
-            flags: vec![value; num_blocks],
+            flags: vec![in alloc; value; num_blocks],
             num_bits
         }
     }
@@ -89,7 +111,7 @@ impl BitVec {
         }
 
         if self.num_bits == 0 {
-            *self = Self::with_bits(num_bits_to_add, value_of_bits);
+            *self = Self::with_bits_in(num_bits_to_add, value_of_bits, self.flags.allocator().clone());
             return
         }
 
@@ -146,6 +168,48 @@ impl BitVec {
         return self.flags[idx_of_block]
     }
 
+    // The same as get_block(), but with the bits that are false set instead
+    pub fn get_false_block(&self, idx_of_block: usize) -> Block {
+        if self.flags.is_empty() {
+            return 0
+        }
+        return !self.get_block(idx_of_block) & self.mask_of_bits_in_block(idx_of_block)
+    }
+
+    // Only the last block can have bits past num_bits in it
+    fn mask_of_bits_in_block(&self, idx_of_block: usize) -> Block {
+        let num_bits_in_block: usize = (self.num_bits - idx_of_block*BITS_PER_BLOCK).min(BITS_PER_BLOCK);
+        if num_bits_in_block == BITS_PER_BLOCK {
+            return Block::MAX
+        }
+        return ((1 as Block) << num_bits_in_block) - 1
+    }
+
+    pub fn heap_size_bytes(&self) -> usize {
+        return vec_heap_size_bytes(&self.flags)
+    }
+
+    pub fn check_invariants(&self) -> Result<(), InvariantError> {
+        let num_blocks_needed: usize = self.num_bits.div_ceil(BITS_PER_BLOCK);
+        if self.flags.len() != num_blocks_needed {
+            return Err(InvariantError::WrongNumberOfBlocks{ num_bits: self.num_bits, num_blocks: self.flags.len() })
+        }
+        return Ok(())
+    }
+
+    // The last block only counts as full if every bit up to num_bits is set
+    pub fn num_partial_blocks(&self) -> usize {
+        let mut num_partial_blocks: usize = 0;
+        for idx_of_block in 0..self.flags.len() {
+            let full_block: Block = self.mask_of_bits_in_block(idx_of_block);
+            let block: Block = self.get_block(idx_of_block);
+            if block != 0 && block != full_block {
+                num_partial_blocks += 1;
+            }
+        }
+        return num_partial_blocks
+    }
+
     pub fn _get_num_blocks(&self) -> usize {
         return self.flags.len()
     }
@@ -165,31 +229,54 @@ impl BitVec {
         return None
     }
 
-    pub fn true_bits<'a>(&'a self) -> TrueBitsIterator<'a> {
+    pub fn find_a_false_bit(&self) -> Option<usize> {
+        for block in 0..self.flags.len() {
+            let false_bits: Block = self.get_false_block(block);
+            if false_bits != 0 {
+                let local_bit: usize = false_bits.trailing_zeros() as usize;
+                let bit: usize = block*BITS_PER_BLOCK + local_bit;
+                return Some(bit)
+            }
+        }
+        return None
+    }
+
+    pub fn true_bits<'a>(&'a self) -> TrueBitsIterator<'a, A> {
         return TrueBitsIterator::new(self)
     }
+
+    pub fn true_bits_in<'a>(&'a self, range: Range<usize>) -> TrueBitsIterator<'a, A> {
+        let end_bit: usize = range.end.min(self.num_bits);
+        return TrueBitsIterator {
+            bits: self,
+            bit: range.start.min(end_bit),
+            end_bit,
+        }
+    }
 }
 
-pub struct TrueBitsIterator<'a> {
-    bits: &'a BitVec,
+pub struct TrueBitsIterator<'a, A: Allocator + Clone = Global> {
+    bits: &'a BitVec<A>,
     bit: usize,
+    end_bit: usize, // one past the last bit that hasn't been looked at from the back
 }
 
-impl <'a> TrueBitsIterator<'a> {
-    fn new(bits: &'a BitVec) -> Self {
+impl <'a, A: Allocator + Clone> TrueBitsIterator<'a, A> {
+    fn new(bits: &'a BitVec<A>) -> Self {
         return Self {
             bits,
             bit: 0,
+            end_bit: bits.num_bits(),
         }
     }
 }
 
-impl <'a> Iterator for TrueBitsIterator<'a> {
+impl <'a, A: Allocator + Clone> Iterator for TrueBitsIterator<'a, A> {
     type Item = usize;
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
-            if self.bit >= self.bits.num_bits() {
+            if self.bit >= self.end_bit {
                 return None
             }
 
@@ -207,15 +294,31 @@ impl <'a> Iterator for TrueBitsIterator<'a> {
     }
 }
 
-impl FlagVec for BitVec {
-    type TrueFlagsIter<'a> = TrueBitsIterator<'a>;
+impl <'a, A: Allocator + Clone> DoubleEndedIterator for TrueBitsIterator<'a, A> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.end_bit <= self.bit {
+                return None
+            }
 
-    fn new() -> Self {
-        return Self::new()
+            self.end_bit -= 1;
+            if self.bits.get_bit(self.end_bit) == true {
+                return Some(self.end_bit)
+            }
+        }
+    }
+}
+
+impl <A: Allocator + Clone> FlagVec<A> for BitVec<A> {
+    type TrueFlagsIter<'a> = TrueBitsIterator<'a, A> where A: 'a;
+    const FLAGS_PER_BLOCK: usize = BITS_PER_BLOCK;
+
+    fn new_in(alloc: A) -> Self {
+        return Self::new_in(alloc)
     }
 
-    fn with_flags(num_flags: usize, value: bool) -> Self {
-        return Self::with_bits(num_flags, value)
+    fn with_flags_in(num_flags: usize, value: bool, alloc: A) -> Self {
+        return Self::with_bits_in(num_flags, value, alloc)
     }
     
     fn num_flags(&self) -> usize {
@@ -238,7 +341,27 @@ impl FlagVec for BitVec {
         return self.find_a_true_bit()
     }
 
+    fn find_a_false_flag(&self) -> Option<usize> {
+        return self.find_a_false_bit()
+    }
+
     fn true_flags<'a>(&'a self) -> Self::TrueFlagsIter<'a> {
         return self.true_bits()
     }
+
+    fn true_flags_in<'a>(&'a self, range: Range<usize>) -> Self::TrueFlagsIter<'a> {
+        return self.true_bits_in(range)
+    }
+
+    fn num_partial_blocks(&self) -> usize {
+        return self.num_partial_blocks()
+    }
+
+    fn heap_size_bytes(&self) -> usize {
+        return self.heap_size_bytes()
+    }
+
+    fn check_invariants(&self) -> Result<(), InvariantError> {
+        return self.check_invariants()
+    }
 }
\ No newline at end of file
@@ -1,18 +1,25 @@
 use super::FlagVec;
-
-pub struct BoolVec {
-    flags: Vec<bool>
+use crate::InvariantError;
+use crate::heap_size::vec_heap_size_bytes;
+use core::ops::Range;
+use allocator_api2::alloc::{Allocator, Global};
+use allocator_api2::vec;
+use allocator_api2::vec::Vec;
+
+pub struct BoolVec<A: Allocator = Global> {
+    flags: Vec<bool, A>
 }
 
-impl FlagVec for BoolVec {
-    type TrueFlagsIter<'a> = TrueFlagsIterator<'a>;
+impl <A: Allocator> FlagVec<A> for BoolVec<A> {
+    type TrueFlagsIter<'a> = TrueFlagsIterator<'a> where A: 'a;
+    const FLAGS_PER_BLOCK: usize = 1; // every flag is its own bool, so no block is ever partially true
 
-    fn new() -> Self {
-        return Self{ flags: Vec::new() }
+    fn new_in(alloc: A) -> Self {
+        return Self{ flags: Vec::new_in(alloc) }
     }
 
-    fn with_flags(num_flags: usize, value: bool) -> Self {
-        return Self{ flags: vec![value; num_flags] }
+    fn with_flags_in(num_flags: usize, value: bool, alloc: A) -> Self {
+        return Self{ flags: vec![in alloc; value; num_flags] }
     }
 
     fn num_flags(&self) -> usize {
@@ -42,21 +49,54 @@ impl FlagVec for BoolVec {
         return None
     }
 
+    fn find_a_false_flag(&self) -> Option<usize> {
+        for flag in 0..self.flags.len() {
+            if self.flags[flag] == false {
+                return Some(flag)
+            }
+        }
+
+        return None
+    }
+
     fn true_flags<'a>(&'a self) -> TrueFlagsIterator<'a> {
         return TrueFlagsIterator::new(&self.flags)
     }
+
+    fn num_partial_blocks(&self) -> usize {
+        return 0
+    }
+
+    fn heap_size_bytes(&self) -> usize {
+        return vec_heap_size_bytes(&self.flags)
+    }
+
+    fn check_invariants(&self) -> Result<(), InvariantError> {
+        return Ok(()) // every flag is its own bool, so there's nothing that can disagree
+    }
+
+    fn true_flags_in<'a>(&'a self, range: Range<usize>) -> TrueFlagsIterator<'a> {
+        let end_bit: usize = range.end.min(self.flags.len());
+        return TrueFlagsIterator {
+            bits: &self.flags,
+            curr_bit: range.start.min(end_bit),
+            end_bit,
+        }
+    }
 }
 
 pub struct TrueFlagsIterator<'a> {
-    bits: &'a Vec<bool>,
+    bits: &'a [bool],
     curr_bit: usize,
+    end_bit: usize, // one past the last bit that hasn't been looked at from the back
 }
 
 impl <'a> TrueFlagsIterator<'a> {
-    pub fn new(bits: &'a Vec<bool>) -> Self {
+    pub fn new(bits: &'a [bool]) -> Self {
         return Self { 
             bits,
             curr_bit: 0,
+            end_bit: bits.len(),
         } 
     }
 }
@@ -66,7 +106,7 @@ impl <'a> Iterator for TrueFlagsIterator<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
-            if self.curr_bit >= self.bits.len() {
+            if self.curr_bit >= self.end_bit {
                 return None
             }
             
@@ -79,4 +119,19 @@ impl <'a> Iterator for TrueFlagsIterator<'a> {
             self.curr_bit += 1;
         }
     }
+}
+
+impl <'a> DoubleEndedIterator for TrueFlagsIterator<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.end_bit <= self.curr_bit {
+                return None
+            }
+
+            self.end_bit -= 1;
+            if self.bits[self.end_bit] == true {
+                return Some(self.end_bit)
+            }
+        }
+    }
 }
\ No newline at end of file
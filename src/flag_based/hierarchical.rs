@@ -1,5 +1,10 @@
 use super::FlagVec;
+use crate::InvariantError;
+use crate::heap_size::vec_heap_size_bytes;
 use super::bit::{BITS_PER_BLOCK, Block, BitVec};
+use allocator_api2::alloc::{Allocator, Global};
+use allocator_api2::vec::Vec;
+use core::ops::Range;
 
 /*
     This struct is a bunch of BitVecs stacked on top of each other.    
@@ -10,37 +15,72 @@ use super::bit::{BITS_PER_BLOCK, Block, BitVec};
     that block are zeros, then the bit's value is zero.
 
     https://imgur.com/a/NYLXp8m
+
+    Alongside every level above the lowest is a second one summarizing the
+    same bits the other way around, with a one wherever any bit below it is
+    zero. That lets find_a_false_bit() walk down to a zero the same way
+    find_a_true_bit() walks down to a one, so a pool only needs the one set of
+    flags to find both items and free slots. The summaries are a
+    BITS_PER_BLOCK'th the size of the bits they summarize, so the second
+    stack costs far less than a second HierarchicalBitVec would.
 */
-pub struct HierarchicalBitVec {
-    levels: Vec<BitVec>
+pub struct HierarchicalBitVec<A: Allocator = Global> {
+    levels: Vec<BitVec<A>, A>,
+    false_summaries: Vec<BitVec<A>, A>, // false_summaries[i] summarizes the zeros of what levels[i+1] summarizes the ones of
 }
 
 impl HierarchicalBitVec {
     pub fn new() -> Self {
+        return Self::new_in(Global)
+    }
+
+    pub fn with_bits(num_bits: usize, value_of_bits: bool) -> Self {
+        return Self::with_bits_in(num_bits, value_of_bits, Global)
+    }
+}
+
+impl Default for HierarchicalBitVec {
+    fn default() -> Self {
+        return Self::new()
+    }
+}
+
+impl <A: Allocator + Clone> HierarchicalBitVec<A> {
+    pub fn new_in(alloc: A) -> Self {
         return Self {
-            levels: Vec::new()
+            levels: Vec::new_in(alloc.clone()),
+            false_summaries: Vec::new_in(alloc),
         }
     }
     
-    pub fn with_bits(num_bits: usize, value_of_bits: bool) -> Self {
+    pub fn with_bits_in(num_bits: usize, value_of_bits: bool, alloc: A) -> Self {
         if num_bits == 0 {
-            return Self {
-                levels: Vec::new()
-            }
+            return Self::new_in(alloc)
         }
 
-        let mut levels: Vec<BitVec> = Vec::new();
+        let mut levels: Vec<BitVec<A>, A> = Vec::new_in(alloc.clone());
         let mut num_bits_per_bit_at_level: usize = 1;
         loop {
             let num_bits_needed_at_level: usize = ((num_bits-1) / num_bits_per_bit_at_level) + 1;
-            levels.push( BitVec::with_bits(num_bits_needed_at_level, value_of_bits) );
+            levels.push( BitVec::with_bits_in(num_bits_needed_at_level, value_of_bits, alloc.clone()) );
             if num_bits_needed_at_level <= BITS_PER_BLOCK {
                 break;
             }
             num_bits_per_bit_at_level *= BITS_PER_BLOCK;
         }
+
+        // Every bit is the same, so each summary has a one above every block exactly when the bits are zeros
+        let mut false_summaries: Vec<BitVec<A>, A> = Vec::with_capacity_in(levels.len()-1, alloc.clone());
+        for level in levels.iter().skip(1) {
+            false_summaries.push( BitVec::with_bits_in(level.num_bits(), !value_of_bits, alloc.clone()) );
+        }
     
-        return Self{ levels }
+        return Self{ levels, false_summaries }
+    }
+
+    pub fn heap_size_bytes(&self) -> usize {
+        return vec_heap_size_bytes(&self.levels) + self.levels.iter().map(|level: &BitVec<A>| level.heap_size_bytes()).sum::<usize>()
+            + vec_heap_size_bytes(&self.false_summaries) + self.false_summaries.iter().map(|level: &BitVec<A>| level.heap_size_bytes()).sum::<usize>()
     }
 
     pub fn num_bits(&self) -> usize {
@@ -70,6 +110,52 @@ impl HierarchicalBitVec {
             level += 1;
             idx_of_parent_bit = idx_of_parent_bit / BITS_PER_BLOCK;
         }
+
+        let mut level: usize = 1;
+        let mut idx_of_parent_bit: usize = idx / BITS_PER_BLOCK;
+        while level < self.levels.len() {
+            let children: Block = self.false_children(level, idx_of_parent_bit);
+            self.false_summaries[level-1].set_bit(idx_of_parent_bit, children != 0);
+
+            level += 1;
+            idx_of_parent_bit = idx_of_parent_bit / BITS_PER_BLOCK;
+        }
+    }
+
+    // The block of bits that says which children of a bit in level's false summary have a zero under them
+    fn false_children(&self, level: usize, idx_of_child_flags: usize) -> Block {
+        if level == 1 {
+            return self.levels[0].get_false_block(idx_of_child_flags)
+        }
+        return self.false_summaries[level-2].get_block(idx_of_child_flags)
+    }
+
+    /*
+        Brings the false summaries up to date with add_bits(), once the levels
+        themselves are. Only the bits above the new ones can have changed, and
+        any level that's new needs working out from scratch.
+    */
+    fn update_false_summaries(&mut self, idx_of_first_new_bit: usize) {
+        let mut num_bits_per_bit_at_level: usize = BITS_PER_BLOCK;
+        for level in 1..self.levels.len() {
+            let num_bits_at_level: usize = self.levels[level].num_bits();
+            let idx_of_first_changed_bit: usize;
+            if level-1 == self.false_summaries.len() {
+                self.false_summaries.push( BitVec::with_bits_in(num_bits_at_level, false, self.levels.allocator().clone()) );
+                idx_of_first_changed_bit = 0;
+            }
+            else {
+                let num_new_bits: usize = num_bits_at_level - self.false_summaries[level-1].num_bits();
+                self.false_summaries[level-1].add_bits(num_new_bits, false);
+                idx_of_first_changed_bit = idx_of_first_new_bit / num_bits_per_bit_at_level;
+            }
+
+            for idx_of_parent_bit in idx_of_first_changed_bit..num_bits_at_level {
+                let children: Block = self.false_children(level, idx_of_parent_bit);
+                self.false_summaries[level-1].set_bit(idx_of_parent_bit, children != 0);
+            }
+            num_bits_per_bit_at_level = num_bits_per_bit_at_level.saturating_mul(BITS_PER_BLOCK);
+        }
     }
 
     pub fn add_bits(&mut self, num_bits: usize, value: bool) {
@@ -78,7 +164,7 @@ impl HierarchicalBitVec {
         }
 
         if self.levels.is_empty() {
-            *self = Self::with_bits(num_bits, value);
+            *self = Self::with_bits_in(num_bits, value, self.levels.allocator().clone());
             return
         }
 
@@ -116,7 +202,7 @@ impl HierarchicalBitVec {
             }
         }
 
-        let top_level: &BitVec = &self.levels[self.levels.len()-1];
+        let top_level: &BitVec<A> = &self.levels[self.levels.len()-1];
         if top_level.num_blocks() > 1 {
             let mut num_bits_per_bit_at_level: usize = 1;
             for _ in 0..self.levels.len() {
@@ -127,7 +213,7 @@ impl HierarchicalBitVec {
             loop {
                 let num_bits_needed_to_fit_items: usize = ((new_num_bits-1) / num_bits_per_bit_at_level) + 1;
                 assert!(num_bits_needed_to_fit_items > 0);
-                let mut new_level: BitVec = BitVec::with_bits(num_bits_needed_to_fit_items, value);
+                let mut new_level: BitVec<A> = BitVec::with_bits_in(num_bits_needed_to_fit_items, value, self.levels.allocator().clone());
                 for idx_of_parent_bit in 0..num_bits_needed_to_fit_items {
                     let idx_of_child_flags: usize = idx_of_parent_bit;
                     let do_child_flags_have_a_one: bool = self.levels[level-1].get_block(idx_of_child_flags) != 0;
@@ -144,6 +230,7 @@ impl HierarchicalBitVec {
             }
         }
         assert!(self.levels[self.levels.len()-1].num_blocks() == 1);
+        self.update_false_summaries(idx_of_first_new_bit_at_level_0);
     }
 
     pub fn find_a_true_bit(&self) -> Option<usize> {
@@ -151,7 +238,7 @@ impl HierarchicalBitVec {
             return None
         }
 
-        let top_level: &BitVec = &self.levels[ self.levels.len()-1 ];
+        let top_level: &BitVec<A> = &self.levels[ self.levels.len()-1 ];
         assert!(top_level.flags.len() == 1);
         if top_level.get_block(0) == 0 {
             return None
@@ -172,6 +259,29 @@ impl HierarchicalBitVec {
         }
     }
 
+    // The mirror image of find_a_true_bit(), walking down the false summaries instead
+    pub fn find_a_false_bit(&self) -> Option<usize> {
+        if self.levels.is_empty() {
+            return None
+        }
+
+        let mut level: usize = self.levels.len()-1;
+        let top_block: Block = if level == 0 { self.levels[0].get_false_block(0) } else { self.false_summaries[level-1].get_block(0) };
+        if top_block == 0 {
+            return None
+        }
+
+        let mut idx_of_parent_bit: usize = top_block.trailing_zeros() as usize;
+        while level > 0 {
+            let idx_of_child_flags: usize = idx_of_parent_bit;
+            idx_of_parent_bit =
+                idx_of_child_flags * BITS_PER_BLOCK +
+                self.false_children(level, idx_of_child_flags).trailing_zeros() as usize;
+            level -= 1;
+        }
+        return Some(idx_of_parent_bit)
+    }
+
     /*
         todo: find_nearest_true_bit(&self, bit: usize) -> Option<usize>
 
@@ -205,84 +315,200 @@ impl HierarchicalBitVec {
             thing3_bit = pool.allocate_with_hint(0)
     */
 
-    pub fn true_bits(&self) -> TrueBitsIterator {
-        return TrueBitsIterator::new(self)
+    pub fn true_bits(&self) -> TrueBitsIterator<'_, A> {
+        return TrueBitsIterator::new(self, 0..self.num_bits())
     }
+
+    // Only walks down into the parts of the levels that overlap the range
+    pub fn true_bits_in(&self, range: Range<usize>) -> TrueBitsIterator<'_, A> {
+        return TrueBitsIterator::new(self, range)
+    }
+}
+
+/*
+    Every level has a BITS_PER_BLOCK'th as many bits as the one below it, so no
+    HierarchicalBitVec can have more levels than this, and walking down one
+    only ever needs a cursor per level. Keeping them in an array rather than a
+    Vec means iterating never touches the heap.
+*/
+const MAX_NUM_LEVELS: usize = (usize::BITS as usize).div_ceil(BITS_PER_BLOCK.trailing_zeros() as usize);
+
+/*
+    A block of flags the iterator is part way through, at some level above 0,
+    with the bits it hasn't walked down into yet. The cursor at the bottom of
+    a stack stands for an imaginary level above the top one, with a single bit
+    for the top level's only block.
+*/
+#[derive(Clone, Copy)]
+struct Cursor {
+    idx_of_flags: usize,
+    unvisited: Block,
 }
 
-pub struct TrueBitsIterator<'a> {
-    levels: &'a Vec<BitVec>,
-    stack: Vec<(usize, usize)>,
+pub struct TrueBitsIterator<'a, A: Allocator + Clone = Global> {
+    levels: &'a [BitVec<A>],
+    stack: [Cursor; MAX_NUM_LEVELS + 1],
+    stack_len: usize,
     flags: Block,
     base_global_idx_of_flags: usize,
+
+    // The same again for walking down from the back
+    back_stack: [Cursor; MAX_NUM_LEVELS + 1],
+    back_stack_len: usize,
+    back_flags: Block,
+    back_base_global_idx_of_flags: usize,
+
+    // Both ends can load the same block of flags, so these keep them from returning the same bit twice
+    next_idx: usize, // every true bit below this has been returned from the front
+    end_idx: usize,  // every true bit at or above this has been returned from the back
 }
 
-impl <'a> TrueBitsIterator<'a> {
-    fn new(bits: &'a HierarchicalBitVec) -> Self {
-        let mut stack: Vec<(usize, usize)> = Vec::new();
-        if !bits.levels.is_empty() && bits.levels.last().unwrap().get_block(0) != 0 {
-            stack.push( (bits.levels.len()-1, 0) );
+impl <'a, A: Allocator + Clone> TrueBitsIterator<'a, A> {
+    fn new(bits: &'a HierarchicalBitVec<A>, range: Range<usize>) -> Self {
+        let end_idx: usize = range.end.min(bits.num_bits());
+        let start_idx: usize = range.start.min(end_idx);
+        let mut stack: [Cursor; MAX_NUM_LEVELS + 1] = [Cursor{ idx_of_flags: 0, unvisited: 0 }; MAX_NUM_LEVELS + 1];
+        let mut stack_len: usize = 0;
+        if start_idx < end_idx {
+            stack[0] = Cursor{ idx_of_flags: 0, unvisited: 1 };
+            stack_len = 1;
         }
 
         return Self {
             levels: &bits.levels,
             stack,
+            stack_len,
             flags: 0,
             base_global_idx_of_flags: 0,
+
+            back_stack: stack,
+            back_stack_len: stack_len,
+            back_flags: 0,
+            back_base_global_idx_of_flags: 0,
+
+            next_idx: start_idx,
+            end_idx,
         }
     }
-}
 
-impl <'a> Iterator for TrueBitsIterator<'a> {
-    type Item = usize;
+    // Whether any of the level 0 bits under a block of flags are between next_idx and end_idx, since there's no point walking down into it otherwise
+    fn is_in_range(level: usize, idx_of_flags: usize, next_idx: usize, end_idx: usize) -> bool {
+        let num_bits_under_flags: usize = BITS_PER_BLOCK.saturating_pow(level as u32 + 1);
+        let first_bit_under_flags: usize = idx_of_flags.saturating_mul(num_bits_under_flags); // saturating since the top block can cover far more bits than usize holds
+        return first_bit_under_flags < end_idx && first_bit_under_flags.saturating_add(num_bits_under_flags) > next_idx
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.flags == 0 {
-            if self.stack.is_empty() {
-                return None
+    /*
+        Walks down to the next level 0 block with any true bits above it that
+        are in range, lowest first from the front and highest first from the
+        back, and returns its index.
+    */
+    fn next_block(&mut self, from_back: bool) -> Option<usize> {
+        let levels: &'a [BitVec<A>] = self.levels;
+        let (next_idx, end_idx): (usize, usize) = (self.next_idx, self.end_idx);
+        let (stack, stack_len): (&mut [Cursor; MAX_NUM_LEVELS + 1], &mut usize) =
+            if from_back { (&mut self.back_stack, &mut self.back_stack_len) } else { (&mut self.stack, &mut self.stack_len) };
+
+        while *stack_len > 0 {
+            let cursor: &mut Cursor = &mut stack[*stack_len-1];
+            if cursor.unvisited == 0 {
+                *stack_len -= 1;
+                continue;
             }
 
-            while !self.stack.is_empty() {
-                let (level, idx_of_flags): (usize, usize) = self.stack.pop().unwrap();
-                if level == 0 {
-                    self.flags = self.levels[0].get_block(idx_of_flags);
-                    self.base_global_idx_of_flags = idx_of_flags * BITS_PER_BLOCK;
-                    break;
-                }
-    
-                let flags: Block = self.levels[level].get_block(idx_of_flags);
-                assert!(flags != 0); // flag has at least one true bit
-                let base_idx: usize = idx_of_flags * BITS_PER_BLOCK;
-
-                // rev() because we're pushing to a stack and want lower bits to be the first
-                // ones iterated over, traversing left to right
-                for bit in (0..BITS_PER_BLOCK).rev() {
-                    if (flags & (1 << bit)) != 0 {
-                        let level: usize = level-1;
-                        let idx_of_child_flags: usize = base_idx + bit;
-                        self.stack.push( (level, idx_of_child_flags) );
-                    }
-                }
+            let bit: usize = if from_back { BITS_PER_BLOCK - 1 - cursor.unvisited.leading_zeros() as usize } else { cursor.unvisited.trailing_zeros() as usize };
+            cursor.unvisited &= !(1 << bit);
+            let idx_of_child_flags: usize = cursor.idx_of_flags * BITS_PER_BLOCK + bit;
+            let child_level: usize = levels.len() - *stack_len;
+            if !Self::is_in_range(child_level, idx_of_child_flags, next_idx, end_idx) {
+                continue;
+            }
+            if child_level == 0 {
+                return Some(idx_of_child_flags)
             }
+
+            stack[*stack_len] = Cursor{ idx_of_flags: idx_of_child_flags, unvisited: levels[child_level].get_block(idx_of_child_flags) };
+            *stack_len += 1;
+        }
+        return None
+    }
+
+    // Masks for the bits of a level 0 block that come at or after, or before, a global index
+    fn mask_of_bits_at_or_after(idx: usize, base_global_idx_of_flags: usize) -> Block {
+        if idx <= base_global_idx_of_flags {
+            return Block::MAX
+        }
+        if idx - base_global_idx_of_flags >= BITS_PER_BLOCK {
+            return 0
+        }
+        return Block::MAX << (idx - base_global_idx_of_flags)
+    }
+
+    fn mask_of_bits_before(idx: usize, base_global_idx_of_flags: usize) -> Block {
+        return !Self::mask_of_bits_at_or_after(idx, base_global_idx_of_flags)
+    }
+
+    fn finish(&mut self) -> Option<usize> {
+        self.stack_len = 0;
+        self.back_stack_len = 0;
+        self.flags = 0;
+        self.back_flags = 0;
+        return None
+    }
+}
+
+impl <'a, A: Allocator + Clone> Iterator for TrueBitsIterator<'a, A> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // A block at the edge of the range can turn out to have no true bits in it, so this keeps going until one does
+        while self.flags == 0 {
+            let idx_of_flags: usize = self.next_block(false)?;
+            self.base_global_idx_of_flags = idx_of_flags * BITS_PER_BLOCK;
+            self.flags = self.levels[0].get_block(idx_of_flags) & Self::mask_of_bits_at_or_after(self.next_idx, self.base_global_idx_of_flags);
         }
         
         assert!(self.flags != 0);
         let flags_idx_of_next_true_bit: usize = self.flags.trailing_zeros() as usize;
         self.flags &= !(1 << flags_idx_of_next_true_bit);
         let global_idx_of_next_true_bit: usize = self.base_global_idx_of_flags + flags_idx_of_next_true_bit;
+        if global_idx_of_next_true_bit >= self.end_idx {
+            return self.finish()
+        }
+        self.next_idx = global_idx_of_next_true_bit + 1;
         return Some(global_idx_of_next_true_bit)
     }
 }
 
-impl FlagVec for HierarchicalBitVec {
-    type TrueFlagsIter<'a> = TrueBitsIterator<'a>;
+impl <'a, A: Allocator + Clone> DoubleEndedIterator for TrueBitsIterator<'a, A> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        while self.back_flags == 0 {
+            let idx_of_flags: usize = self.next_block(true)?;
+            self.back_base_global_idx_of_flags = idx_of_flags * BITS_PER_BLOCK;
+            self.back_flags = self.levels[0].get_block(idx_of_flags) & Self::mask_of_bits_before(self.end_idx, self.back_base_global_idx_of_flags);
+        }
 
-    fn new() -> Self {
-        return Self::new()
+        let flags_idx_of_prev_true_bit: usize = BITS_PER_BLOCK - 1 - self.back_flags.leading_zeros() as usize;
+        self.back_flags &= !(1 << flags_idx_of_prev_true_bit);
+        let global_idx_of_prev_true_bit: usize = self.back_base_global_idx_of_flags + flags_idx_of_prev_true_bit;
+        if global_idx_of_prev_true_bit < self.next_idx {
+            return self.finish()
+        }
+        self.end_idx = global_idx_of_prev_true_bit;
+        return Some(global_idx_of_prev_true_bit)
+    }
+}
+
+impl <A: Allocator + Clone> FlagVec<A> for HierarchicalBitVec<A> {
+    type TrueFlagsIter<'a> = TrueBitsIterator<'a, A> where A: 'a;
+    const FLAGS_PER_BLOCK: usize = BITS_PER_BLOCK;
+
+    fn new_in(alloc: A) -> Self {
+        return Self::new_in(alloc)
     }
 
-    fn with_flags(num_flags: usize, value: bool) -> Self {
-        return Self::with_bits(num_flags, value)
+    fn with_flags_in(num_flags: usize, value: bool, alloc: A) -> Self {
+        return Self::with_bits_in(num_flags, value, alloc)
     }
     
     fn num_flags(&self) -> usize {
@@ -305,24 +531,52 @@ impl FlagVec for HierarchicalBitVec {
         return self.find_a_true_bit()
     }
 
+    fn find_a_false_flag(&self) -> Option<usize> {
+        return self.find_a_false_bit()
+    }
+
     fn true_flags<'a>(&'a self) -> Self::TrueFlagsIter<'a> {
         return self.true_bits()
     }
+
+    fn true_flags_in<'a>(&'a self, range: Range<usize>) -> Self::TrueFlagsIter<'a> {
+        return self.true_bits_in(range)
+    }
+
+    // Only the bottom level, which is the one holding the actual flags
+    fn num_partial_blocks(&self) -> usize {
+        if self.levels.is_empty() {
+            return 0
+        }
+        return self.levels[0].num_partial_blocks()
+    }
+
+    fn heap_size_bytes(&self) -> usize {
+        return self.heap_size_bytes()
+    }
+
+    fn check_invariants(&self) -> Result<(), InvariantError> {
+        return self.check_invariants()
+    }
 }
 
-impl HierarchicalBitVec {
+impl <A: Allocator + Clone> HierarchicalBitVec<A> {
     /*
-        This was carried over from another project where both BitVec and HierarchicalBitVec 
-        implemented a get_error() function, each with their own InternalStateErrors.
+        Every level has to have exactly as many bits as it takes to summarize the
+        one below it, ending in a single block at the top, and every bit above
+        level 0 has to be set exactly when the block it summarizes has any bits
+        set.
     */
-    fn _get_error(&self) -> Result<(), _InternalStateError> {
-        use _InternalStateError::*;
+    pub fn check_invariants(&self) -> Result<(), InvariantError> {
+        use InvariantError::*;
         
         if self.levels.len() == 0 {
             return Ok(())
         }
 
-        // Call get_error() on each BitVec in self.levels
+        for level in self.levels.iter() {
+            level.check_invariants()?;
+        }
 
         if !( self.levels[self.levels.len()-1].num_blocks() == 1 ) {
             return Err( TopmostLevelHasMoreThanOneFlag )
@@ -379,34 +633,30 @@ impl HierarchicalBitVec {
             level += 1;
         }
 
+        if self.false_summaries.len() != self.levels.len()-1 {
+            return Err( FalseSummariesDontMatchLevels{ num_levels: self.levels.len(), num_false_summaries: self.false_summaries.len() } )
+        }
+        for level in 1..self.levels.len() {
+            let false_summary: &BitVec<A> = &self.false_summaries[level-1];
+            false_summary.check_invariants()?;
+            if false_summary.num_bits() != self.levels[level].num_bits() {
+                return Err( FalseSummariesDontMatchLevels{ num_levels: self.levels.len(), num_false_summaries: self.false_summaries.len() } )
+            }
+            for idx_of_parent_bit in 0..false_summary.num_bits() {
+                let idx_of_child_flags: usize = idx_of_parent_bit;
+                let child_flags: Block = self.false_children(level, idx_of_child_flags);
+                let parent_bit: Block = false_summary.get_bit(idx_of_parent_bit) as Block;
+                if !( (parent_bit == 0) == (child_flags == 0) ) {
+                    return Err(
+                        FalseSummaryBitDoesntMatchChildBlock {
+                            idx_of_parent_bit, parent_bit, parent_level: level,
+                            idx_of_child_flags, child_flags, child_level: level-1,
+                        }
+                    )
+                }
+            }
+        }
+
         return Ok(())
     }
 }
-
-#[derive(Clone, Copy, Debug)]
-enum _InternalStateError {
-    LevelsIsAllocatedButLevelZeroHasZeroBits,
-
-    TopmostLevelHasMoreThanOneFlag,
-
-    LevelContainsLessBitsThanLevelZero {
-        level: usize, 
-        max_num_bits_level_can_encompass: usize,
-        num_bits_level_zero_encompasses: usize, 
-    },
-    
-    LevelEncompassesMoreBitsThanAreNeededToFitNumBits {
-        level: usize, 
-        min_num_bits_needed_to_fit_num_bits_at_level: usize,
-        num_bits_level_zero_encompasses: usize,
-    },
-
-    ParentBitDoesntMatchChildBlock {
-        idx_of_parent_bit: usize,
-        parent_bit: Block, 
-        parent_level: usize,
-        idx_of_child_flags: usize, 
-        child_flags: Block, 
-        child_level: usize,
-    },
-}
\ No newline at end of file
@@ -1,108 +1,225 @@
+use core::any::type_name;
 mod bit;
 mod bool;
 mod hierarchical;
 
-use crate::Pool;
+use core::mem::MaybeUninit;
+use core::ops::Range;
+#[cfg(feature = "rayon")]
+use core::marker::PhantomData;
+use crate::{Pool, OrderedPool, PreallocatedPool};
+use crate::stats::{PoolStats, Watermarks};
+use crate::debug_checks::{self, SlotHistories};
+use crate::leaks::{find_leaks, PendingLeakReport};
+use crate::heap_size::{HeapUsage, vec_heap_size_bytes};
+use crate::InvariantError;
+use allocator_api2::alloc::{Allocator, Global};
+use allocator_api2::vec::Vec;
+#[cfg(feature = "rayon")]
+use crate::parallel::{ParallelPool, ParIter, ParIterMut, Slots};
+
+pub use hierarchical::HierarchicalBitVec;
+pub(crate) use bit::Block;
+pub use bit::BitVec;
+pub use bool::BoolVec;
 
 #[allow(dead_code)]
-pub type BitFlags<T> = FlagsBasedPool<T, bit::BitVec>;
+pub type BitFlags<T, A = Global> = FlagsBasedPool<T, bit::BitVec<A>, A>;
 
 #[allow(dead_code)]
-pub type BoolFlags<T> = FlagsBasedPool<T, bool::BoolVec>;
+pub type BoolFlags<T, A = Global> = FlagsBasedPool<T, bool::BoolVec<A>, A>;
 
 #[allow(dead_code)]
-pub type HierarchicalFlags<T> = FlagsBasedPool<T, hierarchical::HierarchicalBitVec>;
+pub type HierarchicalFlags<T, A = Global> = FlagsBasedPool<T, hierarchical::HierarchicalBitVec<A>, A>;
 
-pub trait FlagVec {
-    type TrueFlagsIter<'a>: Iterator<Item=usize> where Self: 'a;
+pub trait FlagVec<A: Allocator = Global> {
+    type TrueFlagsIter<'a>: DoubleEndedIterator<Item=usize> where Self: 'a;
+    const FLAGS_PER_BLOCK: usize; // how many flags num_partial_blocks() counts together
 
-    fn new() -> Self;
-    fn with_flags(num_flags: usize, value: bool) -> Self;
+    fn new_in(alloc: A) -> Self;
+    fn with_flags_in(num_flags: usize, value: bool, alloc: A) -> Self;
     fn num_flags(&self) -> usize;
     fn get_flag(&self, flag: usize) -> bool;
     fn set_flag(&mut self, flag: usize, value: bool);
     fn add_flags(&mut self, num_flags: usize, value: bool);
     fn find_a_true_flag(&self) -> Option<usize>;
-    fn true_flags<'a>(&'a self) -> Self::TrueFlagsIter<'a>;
+    fn find_a_false_flag(&self) -> Option<usize>;
+    fn true_flags<'a>(&'a self) -> Self::TrueFlagsIter<'a>; // in ascending order
+    fn true_flags_in<'a>(&'a self, range: Range<usize>) -> Self::TrueFlagsIter<'a>; // only flags in range, which can go past num_flags()
+    fn num_partial_blocks(&self) -> usize; // blocks with both true and false flags in them
+    fn heap_size_bytes(&self) -> usize; // everything the flags own, including any summary levels
+    fn check_invariants(&self) -> Result<(), InvariantError>;
 }
 
-pub struct FlagsBasedPool<T: Clone, U: FlagVec> {
-    alloc: U, // flags indicating an item is allocated (0 for deallocated, 1 for allocated)
-    free: U, // flags indicating an item is deallocated (0 for deallocated, 1 for allocated)
-    items: Vec<Option<T>>,
+pub struct FlagsBasedPool<T: Clone, U: FlagVec<A>, A: Allocator = Global> {
+    alloc: U, // flags indicating an item is allocated (0 for deallocated, 1 for allocated), which are searched for zeros to find free slots
+    items: Vec<MaybeUninit<T>, A>, // only initialized where the item's alloc flag is set
     num_items: usize,
+    watermarks: Watermarks, // for stats(), since neither can be worked out afterwards
+    history: SlotHistories<A>, // where each slot was last allocated and freed from, with debug-checks
 }
 
-impl <T: Clone, U: FlagVec> Pool<T> for FlagsBasedPool<T, U> {
-    type Iter<'a> = Iter<'a, T, U> where Self: 'a, T: 'a;
+impl <T: Clone, U: FlagVec<A>, A: Allocator + Clone + Default> Pool<T> for FlagsBasedPool<T, U, A> {
+    type Iter<'a> = Iter<'a, T, U, A> where Self: 'a, T: 'a;
 
     fn new() -> Self {
-        return Self {
-            alloc: FlagVec::new(),
-            free: FlagVec::new(),
-            items: Vec::new(),
-            num_items: 0,
-        }
+        return Self::new_in(A::default())
     }
 
     fn with_capacity(num_items: usize) -> Self {
-        return Self {
-            alloc: FlagVec::with_flags(num_items, false),
-            free: FlagVec::with_flags(num_items, true),
-            items: vec![None; num_items],
-            num_items: 0,
-        }
+        return Self::with_capacity_in(num_items, A::default())
     }
 
     fn len(&self) -> usize {
         return self.num_items
     }
 
+    fn capacity(&self) -> usize {
+        return self.items.len()
+    }
+
+    fn stats(&self) -> PoolStats {
+        return PoolStats::new(self.num_items, self.items.len(), self.watermarks, U::FLAGS_PER_BLOCK, self.alloc.num_partial_blocks(), self.sorted_iter().next_back().map(|(id, _): (usize, &T)| id))
+    }
+
+    fn heap_size_bytes_with<F: FnMut(&T) -> usize>(&self, item_heap_size_bytes: F) -> HeapUsage {
+        return HeapUsage::new()
+            .with("items", vec_heap_size_bytes(&self.items))
+            .with("alloc_flags", self.alloc.heap_size_bytes())
+            .with("history", self.history.heap_size_bytes())
+            .with("item_heap", self.iter().map(item_heap_size_bytes).sum())
+    }
+
+    fn check_invariants(&self) -> Result<(), InvariantError> {
+        self.alloc.check_invariants()?;
+        if self.alloc.num_flags() != self.items.len() {
+            return Err(InvariantError::StorageLengthsDisagree{ num_slots: self.items.len(), num_flags: self.alloc.num_flags() })
+        }
+
+        let num_allocated_slots: usize = self.alloc.true_flags().count();
+        if num_allocated_slots != self.num_items {
+            return Err(InvariantError::NumItemsDoesntMatchSlots{ num_items: self.num_items, num_allocated_slots })
+        }
+        return Ok(())
+    }
+
     fn get(&self, id: usize) -> &T {
-        return self.items[id].as_ref().unwrap()
+        self.history.check_access(id);
+        assert!(self.alloc.get_flag(id) == true);
+        return unsafe { self.items[id].assume_init_ref() }
     }
 
     fn get_mut(&mut self, id: usize) -> &mut T {
-        return self.items[id].as_mut().unwrap()
+        self.history.check_access(id);
+        assert!(self.alloc.get_flag(id) == true);
+        return unsafe { self.items[id].assume_init_mut() }
     }
 
     fn allocate(&mut self, item: T) -> usize {
         self.expand_if_needed();
 
-        let id: usize = self.free.find_a_true_flag().unwrap();
+        let id: usize = self.alloc.find_a_false_flag().unwrap();
         assert!(self.alloc.get_flag(id) == false);
-        assert!(self.free.get_flag(id) == true);
 
         self.alloc.set_flag(id, true);
-        self.free.set_flag(id, false);
-        assert!(self.items[id].is_none());
-        self.items[id] = Some(item);
+        self.items[id].write(item);
+        self.history.allocated(id);
         self.num_items += 1;
+        self.watermarks.allocated(self.num_items);
         return id
     }
 
     fn deallocate(&mut self, id: usize) {
-        assert!(self.items[id].is_some());
+        self.history.check_deallocate(id);
         assert!(self.alloc.get_flag(id) == true);
-        assert!(self.free.get_flag(id) == false);
 
         self.alloc.set_flag(id, false);
-        self.free.set_flag(id, true);
-        self.items[id] = None; // calls Drop on the item
+        self.history.freed(id);
         self.num_items -= 1;
+        unsafe { self.items[id].assume_init_drop() };
+        debug_checks::poison(&mut self.items[id]);
+    }
+
+    fn iter<'a>(&'a self) -> Iter<'a, T, U, A> {
+        return Iter::new(&self.items, &self.alloc, self.num_items)
+    }
+}
+
+impl <T: Clone, U: FlagVec<A>, A: Allocator + Clone + Default> PreallocatedPool<T> for FlagsBasedPool<T, U, A> {
+    type Alloc = A;
+
+    fn allocator(&self) -> &A {
+        return self.items.allocator()
+    }
+}
+
+impl <T: Clone, U: FlagVec<A>, A: Allocator + Clone + Default> OrderedPool<T> for FlagsBasedPool<T, U, A> {
+    type SortedIter<'a> = SortedIter<'a, T, U, A> where Self: 'a, T: 'a;
+    type RangeIter<'a> = RangeIter<'a, T, U, A> where Self: 'a, T: 'a;
+
+    // iter() already goes in id order, since true_flags() does
+    fn sorted_iter<'a>(&'a self) -> SortedIter<'a, T, U, A> {
+        return SortedIter::new(&self.items, &self.alloc, self.num_items)
+    }
+
+    fn iter_range<'a>(&'a self, range: Range<usize>) -> RangeIter<'a, T, U, A> {
+        return RangeIter {
+            items: &self.items,
+            true_flags_iter: self.alloc.true_flags_in(range),
+        }
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl <T: Clone + Send + Sync, U: FlagVec<A> + Sync, A: Allocator + Clone + Default> ParallelPool<T> for FlagsBasedPool<T, U, A> {
+    type ParIter<'a> = ParIter<'a, T, ParSlots<T, U, A>> where Self: 'a, T: 'a;
+    type ParIterMut<'a> = ParIterMut<'a, T, ParSlots<T, U, A>> where Self: 'a, T: 'a;
+
+    // Every thread reads the same alloc flags, which is why U has to be Sync
+    fn par_iter<'a>(&'a self) -> Self::ParIter<'a> {
+        let slots: ParSlots<T, U, A> = ParSlots{ items: self.items.as_ptr() as *mut MaybeUninit<T>, alloc: &self.alloc, num_slots: self.items.len(), _alloc: PhantomData };
+        return ParIter::new(slots, self.num_items)
     }
 
-    fn iter<'a>(&'a self) -> Iter<'a, T, U> {
-        return Iter::new(&self.items, &self.alloc)
+    fn par_iter_mut<'a>(&'a mut self) -> Self::ParIterMut<'a> {
+        let slots: ParSlots<T, U, A> = ParSlots{ items: self.items.as_mut_ptr(), alloc: &self.alloc, num_slots: self.items.len(), _alloc: PhantomData };
+        return ParIterMut::new(slots, self.num_items)
     }
 }
 
-impl <T: Clone, U: FlagVec> FlagsBasedPool<T, U> {
+impl <T: Clone, U: FlagVec<A>, A: Allocator + Clone> FlagsBasedPool<T, U, A> {
+    pub fn new_in(alloc: A) -> Self {
+        return Self {
+            alloc: FlagVec::new_in(alloc.clone()),
+            items: Vec::new_in(alloc.clone()),
+            num_items: 0,
+            watermarks: Watermarks::new(),
+            history: SlotHistories::new_in(alloc),
+        }
+    }
+
+    pub fn with_capacity_in(num_items: usize, alloc: A) -> Self {
+        return Self {
+            alloc: FlagVec::with_flags_in(num_items, false, alloc.clone()),
+            items: Self::uninit_items(num_items, alloc.clone()),
+            num_items: 0,
+            watermarks: Watermarks::new(),
+            history: SlotHistories::with_capacity_in(num_items, alloc),
+        }
+    }
+
+    fn uninit_items(num_items: usize, alloc: A) -> Vec<MaybeUninit<T>, A> {
+        let mut items: Vec<MaybeUninit<T>, A> = Vec::with_capacity_in(num_items, alloc);
+        items.resize_with(num_items, MaybeUninit::uninit);
+        return items
+    }
+
     fn expand_if_needed(&mut self) {
         if self.num_items < self.alloc.num_flags() {
             return
         }
         
+        self.watermarks.grew();
         const GROWTH_FACTOR: usize = 2;
         let new_num_items: usize =
             if self.num_items == 0 {
@@ -113,39 +230,259 @@ impl <T: Clone, U: FlagVec> FlagsBasedPool<T, U> {
             };
         let num_new_items: usize = new_num_items - self.num_items;
         self.alloc.add_flags(num_new_items, false);
-        self.free.add_flags(num_new_items, true);
-        self.items.resize(new_num_items, None);
+        self.items.resize_with(new_num_items, MaybeUninit::uninit);
+        self.history.grow_to(new_num_items);
     }
 }
 
-pub struct Iter<'a, T: Clone, U: 'a + FlagVec> {
-    items: &'a Vec<Option<T>>,
-    true_flags_iter: <U as FlagVec>::TrueFlagsIter<'a>,
+impl <T: Clone, U: FlagVec<A>, A: Allocator> Drop for FlagsBasedPool<T, U, A> {
+    fn drop(&mut self) {
+        let leaks: PendingLeakReport = find_leaks(type_name::<Self>(), self.num_items, self.alloc.true_flags(), |id: usize| self.history.allocated_at(id));
+
+        for id in self.alloc.true_flags() {
+            unsafe { self.items[id].assume_init_drop() };
+        }
+
+        leaks.report();
+    }
 }
 
-impl <'a, T: Clone, U: FlagVec> Iter<'a, T, U> {
-    fn new(items: &'a Vec<Option<T>>, alloc: &'a U) -> Self {
-        return Self { items, true_flags_iter: alloc.true_flags() }
+#[cfg(feature = "rayon")]
+pub struct ParSlots<T, U: FlagVec<A>, A: Allocator> {
+    items: *mut MaybeUninit<T>,
+    alloc: *const U,
+    num_slots: usize,
+    _alloc: PhantomData<A>,
+}
+
+#[cfg(feature = "rayon")]
+impl <T, U: FlagVec<A>, A: Allocator> Clone for ParSlots<T, U, A> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl <T, U: FlagVec<A>, A: Allocator> Copy for ParSlots<T, U, A> {}
+
+#[cfg(feature = "rayon")]
+unsafe impl <T, U: FlagVec<A>, A: Allocator> Slots<T> for ParSlots<T, U, A> {
+    fn num_slots(&self) -> usize {
+        return self.num_slots
+    }
+
+    fn is_live(&self, id: usize) -> bool {
+        return unsafe { (*self.alloc).get_flag(id) }
+    }
+
+    fn item(&self, id: usize) -> *const T {
+        return unsafe { (*self.items.add(id)).as_ptr() }
+    }
+
+    fn item_mut(&self, id: usize) -> *mut T {
+        return unsafe { (*self.items.add(id)).as_mut_ptr() }
+    }
+
+    // Only visits the live ids, which a HierarchicalBitVec finds without walking the empty stretches between them
+    fn split_point(&self, start: usize, end: usize, num_items_wanted: usize) -> (usize, usize) {
+        let alloc: &U = unsafe { &*self.alloc };
+        match alloc.true_flags_in(start..end).nth(num_items_wanted-1) {
+            Some(id) => return (id+1, num_items_wanted),
+            None     => return (end, alloc.true_flags_in(start..end).count()),
+        }
     }
 }
 
-impl <'a, T: 'a + Clone, U: 'a + FlagVec> Iterator for Iter<'a, T, U> {
+pub struct Iter<'a, T: Clone, U: 'a + FlagVec<A>, A: Allocator = Global> {
+    items: &'a [MaybeUninit<T>],
+    true_flags_iter: <U as FlagVec<A>>::TrueFlagsIter<'a>,
+    num_items_left: usize,
+}
+
+impl <'a, T: Clone, U: FlagVec<A>, A: Allocator> Iter<'a, T, U, A> {
+    fn new(items: &'a [MaybeUninit<T>], alloc: &'a U, num_items: usize) -> Self {
+        return Self { items, true_flags_iter: alloc.true_flags(), num_items_left: num_items }
+    }
+}
+
+impl <'a, T: 'a + Clone, U: 'a + FlagVec<A>, A: Allocator> Iterator for Iter<'a, T, U, A> {
     type Item = &'a T;
 
     fn next(&mut self) -> Option<Self::Item> {
         match self.true_flags_iter.next() {
-            Some(id) => return Some( self.items[id].as_ref().unwrap() ),
+            Some(id) => {
+                self.num_items_left -= 1;
+                return Some( unsafe { self.items[id].assume_init_ref() } )
+            },
             None => return None
         }
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        return (self.num_items_left, Some(self.num_items_left))
+    }
+}
+
+impl <'a, T: 'a + Clone, U: 'a + FlagVec<A>, A: Allocator> DoubleEndedIterator for Iter<'a, T, U, A> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        match self.true_flags_iter.next_back() {
+            Some(id) => {
+                self.num_items_left -= 1;
+                return Some( unsafe { self.items[id].assume_init_ref() } )
+            },
+            None => return None
+        }
+    }
+}
+
+impl <'a, T: 'a + Clone, U: 'a + FlagVec<A>, A: Allocator> ExactSizeIterator for Iter<'a, T, U, A> {}
+
+pub struct SortedIter<'a, T: Clone, U: 'a + FlagVec<A>, A: Allocator = Global> {
+    items: &'a [MaybeUninit<T>],
+    true_flags_iter: <U as FlagVec<A>>::TrueFlagsIter<'a>,
+    num_items_left: usize,
+}
+
+impl <'a, T: Clone, U: FlagVec<A>, A: Allocator> SortedIter<'a, T, U, A> {
+    fn new(items: &'a [MaybeUninit<T>], alloc: &'a U, num_items: usize) -> Self {
+        return Self { items, true_flags_iter: alloc.true_flags(), num_items_left: num_items }
+    }
+}
+
+impl <'a, T: 'a + Clone, U: 'a + FlagVec<A>, A: Allocator> Iterator for SortedIter<'a, T, U, A> {
+    type Item = (usize, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.true_flags_iter.next() {
+            Some(id) => {
+                self.num_items_left -= 1;
+                return Some( (id, unsafe { self.items[id].assume_init_ref() }) )
+            },
+            None => return None
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        return (self.num_items_left, Some(self.num_items_left))
+    }
+}
+
+impl <'a, T: 'a + Clone, U: 'a + FlagVec<A>, A: Allocator> DoubleEndedIterator for SortedIter<'a, T, U, A> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        match self.true_flags_iter.next_back() {
+            Some(id) => {
+                self.num_items_left -= 1;
+                return Some( (id, unsafe { self.items[id].assume_init_ref() }) )
+            },
+            None => return None
+        }
+    }
+}
+
+impl <'a, T: 'a + Clone, U: 'a + FlagVec<A>, A: Allocator> ExactSizeIterator for SortedIter<'a, T, U, A> {}
+
+pub struct RangeIter<'a, T: Clone, U: 'a + FlagVec<A>, A: Allocator = Global> {
+    items: &'a [MaybeUninit<T>],
+    true_flags_iter: <U as FlagVec<A>>::TrueFlagsIter<'a>,
+}
+
+impl <'a, T: 'a + Clone, U: 'a + FlagVec<A>, A: Allocator> Iterator for RangeIter<'a, T, U, A> {
+    type Item = (usize, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let id: usize = self.true_flags_iter.next()?;
+        return Some((id, unsafe { self.items[id].assume_init_ref() }))
+    }
+}
+
+impl <'a, T: 'a + Clone, U: 'a + FlagVec<A>, A: Allocator> DoubleEndedIterator for RangeIter<'a, T, U, A> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let id: usize = self.true_flags_iter.next_back()?;
+        return Some((id, unsafe { self.items[id].assume_init_ref() }))
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    mod flag_vecs {
+        use super::super::FlagVec;
+        use super::super::bit::BitVec;
+        use super::super::bool::BoolVec;
+        use super::super::HierarchicalBitVec;
+        use rand::Rng;
+        use rand::SeedableRng;
+        use rand_xoshiro::Xoshiro256StarStar;
+        use allocator_api2::alloc::Global;
+
+        // Sets, clears and adds random flags, checking after each change that both searches agree with a plain Vec<bool>
+        fn test_find_flags<U: FlagVec>() {
+            const RNG_SEED: u64 = 50;
+            let mut rng: Xoshiro256StarStar = Xoshiro256StarStar::seed_from_u64(RNG_SEED);
+            let mut flags: U = U::new_in(Global);
+            let mut expected: Vec<bool> = Vec::new();
+            for step in 0..20000 {
+                let chance_of_true: f64 = if (step / 2000) % 2 == 0 { 0.95 } else { 0.05 }; // so that the flags spend time nearly full and nearly empty
+                if expected.is_empty() || rng.gen_ratio(1, 100) {
+                    let num_new_flags: usize = rng.gen_range(1..500);
+                    let value: bool = rng.gen_bool(chance_of_true);
+                    flags.add_flags(num_new_flags, value);
+                    expected.resize(expected.len() + num_new_flags, value);
+                }
+                else {
+                    let flag: usize = rng.gen_range(0..expected.len());
+                    let value: bool = rng.gen_bool(chance_of_true);
+                    flags.set_flag(flag, value);
+                    expected[flag] = value;
+                }
+
+                assert!(flags.check_invariants().is_ok());
+                match flags.find_a_false_flag() {
+                    Some(flag) => assert!(expected[flag] == false),
+                    None => assert!(expected.iter().all(|value: &bool| *value == true)),
+                }
+                match flags.find_a_true_flag() {
+                    Some(flag) => assert!(expected[flag] == true),
+                    None => assert!(expected.iter().all(|value: &bool| *value == false)),
+                }
+            }
+
+            // Every flag has to be found as the only one of its kind, including ones in a partly used last block
+            for flag in 0..expected.len() {
+                flags.set_flag(flag, true);
+            }
+            assert!(flags.find_a_false_flag().is_none());
+            for flag in [0, expected.len()/2, expected.len()-1] {
+                flags.set_flag(flag, false);
+                assert!(flags.find_a_false_flag() == Some(flag));
+                assert!(flags.check_invariants().is_ok());
+                flags.set_flag(flag, true);
+            }
+        }
+
+        #[test]
+        fn test_bool_vec() {
+            test_find_flags::<BoolVec>();
+        }
+
+        #[test]
+        fn test_bit_vec() {
+            test_find_flags::<BitVec>();
+        }
+
+        #[test]
+        #[cfg_attr(miri, ignore)] // far too slow to interpret, and only exercises safe code
+        fn test_hierarchical_bit_vec() {
+            test_find_flags::<HierarchicalBitVec>();
+        }
+    }
+
     mod bool {
         use super::super::BoolFlags;
         use crate::testing;
         use crate::testing::Item; 
+        use crate::testing::Growth;
+        use crate::testing::CountingAlloc;
+        use std::rc::Rc;
     
         type Pool = BoolFlags<Item>;
     
@@ -172,20 +509,83 @@ mod tests {
         }
     
         #[test]
+        #[cfg_attr(miri, ignore)] // far too slow to interpret
         fn fuzz_many_pools_few_mutations() {
             testing::fuzz_many_pools_few_mutations::<Pool>();
         }
     
         #[test]
+        #[cfg_attr(miri, ignore)] // far too slow to interpret
         fn fuzz_few_pools_many_mutations() {
             testing::fuzz_few_pools_many_mutations::<Pool>();
         }
+
+        #[test]
+        fn test_sorted_iter() {
+            testing::test_sorted_iter::<Pool>();
+        }
+
+        #[test]
+        fn test_iterators_are_double_ended_and_exact_size() {
+            testing::test_iterators_are_double_ended_and_exact_size::<Pool>();
+        }
+
+        #[test]
+        fn test_iter_range() {
+            testing::test_iter_range::<Pool>();
+        }
+
+        #[test]
+        fn test_stats() {
+            testing::test_stats::<Pool>(Growth::Doubling{ first_capacity: 1 }, true);
+        }
+
+        #[test]
+        #[cfg(feature = "rayon")]
+        fn test_par_iter() {
+            testing::test_par_iter::<Pool>();
+        }
+
+        #[test]
+        fn test_all_memory_goes_through_allocator() {
+            testing::test_all_memory_goes_through_allocator::<BoolFlags<Item, CountingAlloc>>();
+        }
+
+        #[test]
+        fn test_heap_size_matches_allocator() {
+            testing::test_heap_size_matches_allocator::<BoolFlags<Item, CountingAlloc>>();
+        }
+
+        #[test]
+        fn test_items_are_dropped_exactly_once() {
+            testing::test_items_are_dropped_exactly_once::<BoolFlags<Rc<()>>>();
+        }
+
+        #[test]
+        fn test_slots_dont_store_a_discriminant() {
+            testing::test_slots_dont_store_a_discriminant::<BoolFlags<u8, CountingAlloc>, BoolFlags<[u64; 3], CountingAlloc>>();
+        }
+
+        #[cfg(feature = "debug-checks")]
+        #[test]
+        fn test_freed_slots_are_poisoned() {
+            use crate::Pool as _;
+            let mut pool: Pool = Pool::new();
+            let id: usize = pool.allocate(-1);
+            pool.allocate(2);
+            pool.deallocate(id);
+            let stale_item: Item = unsafe { pool.items[id].assume_init() }; // every byte was written by the poisoning
+            assert!(stale_item == Item::from_ne_bytes([0xdd; size_of::<Item>()]));
+        }
     }
 
     mod bit {
         use super::super::BitFlags;
         use crate::testing;
         use crate::testing::Item; 
+        use crate::testing::Growth;
+        use crate::testing::CountingAlloc;
+        use std::rc::Rc;
     
         type Pool = BitFlags<Item>;
     
@@ -212,20 +612,71 @@ mod tests {
         }
     
         #[test]
+        #[cfg_attr(miri, ignore)] // far too slow to interpret
         fn fuzz_many_pools_few_mutations() {
             testing::fuzz_many_pools_few_mutations::<Pool>();
         }
     
         #[test]
+        #[cfg_attr(miri, ignore)] // far too slow to interpret
         fn fuzz_few_pools_many_mutations() {
             testing::fuzz_few_pools_many_mutations::<Pool>();
         }
+
+        #[test]
+        fn test_sorted_iter() {
+            testing::test_sorted_iter::<Pool>();
+        }
+
+        #[test]
+        fn test_iterators_are_double_ended_and_exact_size() {
+            testing::test_iterators_are_double_ended_and_exact_size::<Pool>();
+        }
+
+        #[test]
+        fn test_iter_range() {
+            testing::test_iter_range::<Pool>();
+        }
+
+        #[test]
+        fn test_stats() {
+            testing::test_stats::<Pool>(Growth::Doubling{ first_capacity: 1 }, true);
+        }
+
+        #[test]
+        #[cfg(feature = "rayon")]
+        fn test_par_iter() {
+            testing::test_par_iter::<Pool>();
+        }
+
+        #[test]
+        fn test_all_memory_goes_through_allocator() {
+            testing::test_all_memory_goes_through_allocator::<BitFlags<Item, CountingAlloc>>();
+        }
+
+        #[test]
+        fn test_heap_size_matches_allocator() {
+            testing::test_heap_size_matches_allocator::<BitFlags<Item, CountingAlloc>>();
+        }
+
+        #[test]
+        fn test_items_are_dropped_exactly_once() {
+            testing::test_items_are_dropped_exactly_once::<BitFlags<Rc<()>>>();
+        }
+
+        #[test]
+        fn test_slots_dont_store_a_discriminant() {
+            testing::test_slots_dont_store_a_discriminant::<BitFlags<u8, CountingAlloc>, BitFlags<[u64; 3], CountingAlloc>>();
+        }
     }
 
     mod hierarchical {
         use super::super::HierarchicalFlags;
         use crate::testing;
         use crate::testing::Item; 
+        use crate::testing::Growth;
+        use crate::testing::CountingAlloc;
+        use std::rc::Rc;
     
         type Pool = HierarchicalFlags<Item>;
     
@@ -252,13 +703,61 @@ mod tests {
         }
     
         #[test]
+        #[cfg_attr(miri, ignore)] // far too slow to interpret
         fn fuzz_many_pools_few_mutations() {
             testing::fuzz_many_pools_few_mutations::<Pool>();
         }
     
         #[test]
+        #[cfg_attr(miri, ignore)] // far too slow to interpret
         fn fuzz_few_pools_many_mutations() {
             testing::fuzz_few_pools_many_mutations::<Pool>();
         }
+
+        #[test]
+        fn test_sorted_iter() {
+            testing::test_sorted_iter::<Pool>();
+        }
+
+        #[test]
+        fn test_iterators_are_double_ended_and_exact_size() {
+            testing::test_iterators_are_double_ended_and_exact_size::<Pool>();
+        }
+
+        #[test]
+        fn test_iter_range() {
+            testing::test_iter_range::<Pool>();
+        }
+
+        #[test]
+        fn test_stats() {
+            testing::test_stats::<Pool>(Growth::Doubling{ first_capacity: 1 }, true);
+        }
+
+        #[test]
+        #[cfg(feature = "rayon")]
+        fn test_par_iter() {
+            testing::test_par_iter::<Pool>();
+        }
+
+        #[test]
+        fn test_all_memory_goes_through_allocator() {
+            testing::test_all_memory_goes_through_allocator::<HierarchicalFlags<Item, CountingAlloc>>();
+        }
+
+        #[test]
+        fn test_heap_size_matches_allocator() {
+            testing::test_heap_size_matches_allocator::<HierarchicalFlags<Item, CountingAlloc>>();
+        }
+
+        #[test]
+        fn test_items_are_dropped_exactly_once() {
+            testing::test_items_are_dropped_exactly_once::<HierarchicalFlags<Rc<()>>>();
+        }
+
+        #[test]
+        fn test_slots_dont_store_a_discriminant() {
+            testing::test_slots_dont_store_a_discriminant::<HierarchicalFlags<u8, CountingAlloc>, HierarchicalFlags<[u64; 3], CountingAlloc>>();
+        }
     }
 }
\ No newline at end of file
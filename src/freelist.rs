@@ -1,5 +1,21 @@
-use std::slice;
-use super::Pool;
+use core::any::type_name;
+use core::iter::{Enumerate, Zip};
+use core::ops::Range;
+use core::slice;
+use core::marker::PhantomData;
+use allocator_api2::alloc::{Allocator, Global};
+use allocator_api2::vec;
+use allocator_api2::vec::Vec;
+use super::{Pool, OrderedPool, PreallocatedPool};
+use super::stats::{PoolStats, Watermarks};
+use super::debug_checks::SlotHistories;
+use super::leaks::find_leaks;
+use super::heap_size::{HeapUsage, vec_heap_size_bytes};
+use super::InvariantError;
+use super::flag_based::HierarchicalBitVec;
+use super::invariants::check_free_list;
+#[cfg(feature = "rayon")]
+use super::parallel::{ParallelPool, ParIter, ParIterMut, Slots};
 
 #[derive(Clone)]
 enum Slot<T> {
@@ -7,33 +23,224 @@ enum Slot<T> {
     Free{next_free_slot: Option<usize>},
 }
 
-pub struct FreeList<T: Clone> {
-    slots: Vec<Slot<T>>,
+/*
+    Which free slot allocate() hands out next. Lifo reuses the slot freed most
+    recently, which keeps the slots in use hot in cache, but also means a stale
+    id is very likely to point at a new item rather than a free slot. Fifo
+    hands out the slot that's been free the longest instead, so ids take as
+    long as they can to come back around. LowestIdFirst keeps items packed
+    towards the start of the pool, with fewer holes to iterate over, at the
+    cost of a HierarchicalBitVec of the free slots, which deallocate() uses
+    to find where in the free list the slot it frees goes.
+
+    All three are the same free list threaded through the free slots, only
+    differing in where deallocate() puts the slot it frees. The order is part
+    of the type so that Pool::new() gives the same pool as constructing it by
+    hand, and so that Lifo and Fifo don't carry the bit vec around.
+*/
+pub trait AllocationOrder {
+    #[doc(hidden)]
+    const ORDER: Order; // Order can't be named outside of the crate, so no other orders can be added
+}
+
+#[doc(hidden)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Order {
+    Lifo,
+    Fifo,
+    LowestIdFirst,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Lifo;
+impl AllocationOrder for Lifo {
+    const ORDER: Order = Order::Lifo;
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Fifo;
+impl AllocationOrder for Fifo {
+    const ORDER: Order = Order::Fifo;
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LowestIdFirst;
+impl AllocationOrder for LowestIdFirst {
+    const ORDER: Order = Order::LowestIdFirst;
+}
+
+pub struct FreeList<T: Clone, A: Allocator = Global, O: AllocationOrder = Lifo> {
+    slots: Vec<Slot<T>, A>,
     next_free_slot: Option<usize>,
+    last_free_slot: Option<usize>, // the end of the free list, where Fifo adds slots
+    free_slots: HierarchicalBitVec<A>, // a bit for each slot, set when it's free, with LowestIdFirst and empty otherwise
     num_items: usize,
+    watermarks: Watermarks, // for stats(), since neither can be worked out afterwards
+    history: SlotHistories<A>, // where each slot was last allocated and freed from, with debug-checks
+    _order: PhantomData<O>,
 }
 
-impl <T: Clone> Pool<T> for FreeList<T> {
+impl <T: Clone, A: Allocator + Clone + Default, O: AllocationOrder> Pool<T> for FreeList<T, A, O> {
     type Iter<'a> = Iter<'a, T> where Self: 'a, T: 'a;
 
     fn new() -> Self {
-        return Self { 
-            slots: Vec::new(),
-            next_free_slot: None,
-            num_items: 0,
-        }
+        return Self::new_in(A::default())
     }
     
     fn with_capacity(num_items: usize) -> Self {
+        return Self::with_capacity_in(num_items, A::default())
+    }
+    
+    fn len(&self) -> usize {
+        return FreeList::len(self)
+    }
+
+    fn capacity(&self) -> usize {
+        return self.slots.len()
+    }
+
+    fn stats(&self) -> PoolStats {
+        return PoolStats::new(self.num_items, self.slots.len(), self.watermarks, 1, 0, self.sorted_iter().next_back().map(|(id, _): (usize, &T)| id))
+    }
+
+    // The free list is threaded through the empty slots, so it only takes up anything of its own with LowestIdFirst
+    fn heap_size_bytes_with<F: FnMut(&T) -> usize>(&self, item_heap_size_bytes: F) -> HeapUsage {
+        return HeapUsage::new()
+            .with("slots", vec_heap_size_bytes(&self.slots))
+            .with("free_slots", self.free_slots.heap_size_bytes())
+            .with("history", self.history.heap_size_bytes())
+            .with("item_heap", self.iter().map(item_heap_size_bytes).sum())
+    }
+
+    fn check_invariants(&self) -> Result<(), InvariantError> {
+        let num_allocated_slots: usize = self.slots.iter().filter(|slot: &&Slot<T>| matches!(slot, Slot::Item(_))).count();
+        if num_allocated_slots != self.num_items {
+            return Err(InvariantError::NumItemsDoesntMatchSlots{ num_items: self.num_items, num_allocated_slots })
+        }
+
+        check_free_list(self.next_free_slot, self.slots.len(), self.num_items, |slot: usize| {
+            match &self.slots[slot] {
+                Slot::Item(_) => return None,
+                Slot::Free{ next_free_slot } => return Some(*next_free_slot),
+            }
+        })?;
+
+        // The list is known to be sound by now, so it can be walked without checking each step again
+        let mut last_free_slot: Option<usize> = None;
+        let mut slot: Option<usize> = self.next_free_slot;
+        while let Some(curr_slot) = slot {
+            if O::ORDER == Order::LowestIdFirst && last_free_slot.is_some_and(|prev_slot: usize| prev_slot > curr_slot) {
+                return Err(InvariantError::FreeListOutOfOrder{ slot: curr_slot })
+            }
+            last_free_slot = Some(curr_slot);
+            match &self.slots[curr_slot] {
+                Slot::Free{ next_free_slot } => slot = *next_free_slot,
+                Slot::Item(_) => panic!(),
+            }
+        }
+        if last_free_slot != self.last_free_slot {
+            return Err(InvariantError::LastFreeSlotIsntLast{ last_free_slot: self.last_free_slot })
+        }
+
+        if O::ORDER == Order::LowestIdFirst {
+            self.free_slots.check_invariants()?;
+            if self.free_slots.num_bits() != self.slots.len() {
+                return Err(InvariantError::StorageLengthsDisagree{ num_slots: self.slots.len(), num_flags: self.free_slots.num_bits() })
+            }
+            for (slot, contents) in self.slots.iter().enumerate() {
+                if self.free_slots.get_bit(slot) != matches!(contents, Slot::Free{..}) {
+                    return Err(InvariantError::FreeSlotsDisagreeWithFlags{ slot })
+                }
+            }
+        }
+        return Ok(())
+    }
+
+    fn get(&self, id: usize) -> &T {
+        return FreeList::get(self, id)
+    }
+
+    fn get_mut(&mut self, id: usize) -> &mut T {
+        return FreeList::get_mut(self, id)
+    }
+
+    fn allocate(&mut self, item: T) -> usize {
+        return FreeList::allocate(self, item)
+    }
+
+    fn deallocate(&mut self, item_id: usize) {
+        FreeList::deallocate(self, item_id);
+    }
+    
+    fn iter<'a>(&'a self) -> Iter<'a, T> {
+        return Iter::new(self.slots.iter(), self.num_items)
+    }
+}
+    
+impl <T: Clone, A: Allocator + Clone + Default, O: AllocationOrder> PreallocatedPool<T> for FreeList<T, A, O> {
+    type Alloc = A;
+
+    fn allocator(&self) -> &A {
+        return self.slots.allocator()
+    }
+}
+
+impl <T: Clone, A: Allocator + Clone + Default, O: AllocationOrder> OrderedPool<T> for FreeList<T, A, O> {
+    type SortedIter<'a> = SortedIter<'a, T> where Self: 'a, T: 'a;
+    type RangeIter<'a> = RangeIter<'a, T> where Self: 'a, T: 'a;
+
+    fn sorted_iter<'a>(&'a self) -> SortedIter<'a, T> {
+        return SortedIter::new(self.slots.iter().enumerate(), self.num_items)
+    }
+
+    fn iter_range<'a>(&'a self, range: Range<usize>) -> RangeIter<'a, T> {
+        return RangeIter::new(&self.slots, range)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl <T: Clone + Send + Sync, A: Allocator + Clone + Default, O: AllocationOrder> ParallelPool<T> for FreeList<T, A, O> {
+    type ParIter<'a> = ParIter<'a, T, ParSlots<T>> where Self: 'a, T: 'a;
+    type ParIterMut<'a> = ParIterMut<'a, T, ParSlots<T>> where Self: 'a, T: 'a;
+
+    fn par_iter<'a>(&'a self) -> Self::ParIter<'a> {
+        let slots: ParSlots<T> = ParSlots{ slots: self.slots.as_ptr() as *mut Slot<T>, num_slots: self.slots.len() };
+        return ParIter::new(slots, self.num_items)
+    }
+
+    fn par_iter_mut<'a>(&'a mut self) -> Self::ParIterMut<'a> {
+        let slots: ParSlots<T> = ParSlots{ slots: self.slots.as_mut_ptr(), num_slots: self.slots.len() };
+        return ParIterMut::new(slots, self.num_items)
+    }
+}
+
+impl <T: Clone, A: Allocator + Clone, O: AllocationOrder> FreeList<T, A, O> {
+    pub fn new_in(alloc: A) -> Self {
+        return Self::with_capacity_in(0, alloc)
+    }
+    
+    pub fn with_capacity_in(num_items: usize, alloc: A) -> Self {
+        let free_slots: HierarchicalBitVec<A> =
+            if O::ORDER == Order::LowestIdFirst {
+                HierarchicalBitVec::with_bits_in(num_items, true, alloc.clone())
+            }
+            else {
+                HierarchicalBitVec::new_in(alloc.clone())
+            };
         if num_items == 0 {
             return Self {
-                slots: Vec::new(),
+                slots: Vec::new_in(alloc.clone()),
                 next_free_slot: None,
+                last_free_slot: None,
+                free_slots,
                 num_items: 0,
+                watermarks: Watermarks::new(),
+                history: SlotHistories::new_in(alloc),
+                _order: PhantomData,
             }
         }
     
-        let mut slots: Vec<Slot<T>> = vec![Slot::Free{ next_free_slot: None }; num_items];
+        let mut slots: Vec<Slot<T>, A> = vec![in alloc.clone(); Slot::Free{ next_free_slot: None }; num_items];
         for i in 1..num_items {
             slots[i-1] = Slot::Free{ next_free_slot: Some(i) };
         }
@@ -42,29 +249,43 @@ impl <T: Clone> Pool<T> for FreeList<T> {
         return Self {
             slots,
             next_free_slot: Some(0),
+            last_free_slot: Some(num_items-1),
+            free_slots,
             num_items: 0,
+            watermarks: Watermarks::new(),
+            history: SlotHistories::with_capacity_in(num_items, alloc),
+            _order: PhantomData,
         }
     }
-    
-    fn len(&self) -> usize {
+
+    /*
+        What the Pool impl does, without needing a Default allocator, so that
+        SharedPool's handles can reach it with any allocator.
+    */
+    pub(crate) fn len(&self) -> usize {
         return self.num_items
     }
 
-    fn get(&self, id: usize) -> &T {
+    #[cfg_attr(feature = "debug-checks", track_caller)]
+    pub(crate) fn get(&self, id: usize) -> &T {
+        self.history.check_access(id);
         match &self.slots[id] {
             Slot::Item(item) => return item,
             Slot::Free{..} => panic!(),
         }
     }
 
-    fn get_mut(&mut self, id: usize) -> &mut T {
+    #[cfg_attr(feature = "debug-checks", track_caller)]
+    pub(crate) fn get_mut(&mut self, id: usize) -> &mut T {
+        self.history.check_access(id);
         match &mut self.slots[id] {
             Slot::Item(item) => return item,
             Slot::Free{..} => panic!(),
         }
     }
 
-    fn allocate(&mut self, item: T) -> usize {
+    #[cfg_attr(feature = "debug-checks", track_caller)]
+    pub(crate) fn allocate(&mut self, item: T) -> usize {
         self.expand_if_needed();
         let free_slot_for_item: usize = self.next_free_slot.unwrap();
         match self.slots[free_slot_for_item] {
@@ -74,31 +295,40 @@ impl <T: Clone> Pool<T> for FreeList<T> {
             
             Slot::Item(_) => panic!(),
         }
+        if self.next_free_slot.is_none() {
+            self.last_free_slot = None;
+        }
+        if O::ORDER == Order::LowestIdFirst {
+            self.free_slots.set_bit(free_slot_for_item, false);
+        }
         self.slots[free_slot_for_item] = Slot::Item(item);
+        self.history.allocated(free_slot_for_item);
         self.num_items += 1;
+        self.watermarks.allocated(self.num_items);
         return free_slot_for_item
     }
 
-    fn deallocate(&mut self, item_id: usize) {
+    #[cfg_attr(feature = "debug-checks", track_caller)]
+    pub(crate) fn deallocate(&mut self, item_id: usize) {
+        self.history.check_deallocate(item_id);
         if let Slot::Free{..} = self.slots[item_id] {
             assert!(false);
         }
-        self.slots[item_id] = Slot::Free{next_free_slot: self.next_free_slot}; // drops the item contained in the slot
-        self.next_free_slot = Some(item_id);
+        match O::ORDER {
+            Order::Lifo => self.push_free_slot_front(item_id),
+            Order::Fifo => self.push_free_slot_back(item_id),
+            Order::LowestIdFirst => self.insert_free_slot_sorted(item_id),
+        }
+        self.history.freed(item_id);
         self.num_items -= 1;
     }
-    
-    fn iter<'a>(&'a self) -> Iter<'a, T> {
-        return Iter::new(self.slots.iter())
-    }
-}
-    
-impl <T: Clone> FreeList<T> {
+
     fn expand_if_needed(&mut self) {
         if self.next_free_slot.is_some() {
             return
         }
 
+        self.watermarks.grew();
         const GROWTH_FACTOR: usize = 2;
         let old_num_items: usize = self.slots.len();
         let new_num_items: usize = 
@@ -109,6 +339,10 @@ impl <T: Clone> FreeList<T> {
                 old_num_items*GROWTH_FACTOR
             };
         self.slots.resize(new_num_items, Slot::Free{next_free_slot: None});
+        self.history.grow_to(new_num_items);
+        if O::ORDER == Order::LowestIdFirst {
+            self.free_slots.add_bits(new_num_items-old_num_items, true);
+        }
         for i in old_num_items..(new_num_items-1) {
             self.slots[i] = Slot::Free{next_free_slot: Some(i+1)};
         }
@@ -116,16 +350,71 @@ impl <T: Clone> FreeList<T> {
         let last: usize = self.slots.len()-1;
         self.slots[last] = Slot::Free{next_free_slot: None};
         self.next_free_slot = Some(old_num_items);
+        self.last_free_slot = Some(last);
+    }
+
+    fn push_free_slot_front(&mut self, slot: usize) {
+        self.slots[slot] = Slot::Free{next_free_slot: self.next_free_slot}; // drops the item contained in the slot
+        self.next_free_slot = Some(slot);
+        if self.last_free_slot.is_none() {
+            self.last_free_slot = Some(slot);
+        }
+    }
+
+    fn push_free_slot_back(&mut self, slot: usize) {
+        self.slots[slot] = Slot::Free{next_free_slot: None}; // drops the item contained in the slot
+        match self.last_free_slot {
+            Some(last_free_slot) => self.slots[last_free_slot] = Slot::Free{next_free_slot: Some(slot)},
+            None => self.next_free_slot = Some(slot),
+        }
+        self.last_free_slot = Some(slot);
+    }
+
+    /*
+        Keeps the free list in ascending order, by looking up the highest free
+        slot below the one being freed in free_slots and linking it in after
+        that, rather than walking the list to find it.
+    */
+    fn insert_free_slot_sorted(&mut self, slot: usize) {
+        let prev_slot: Option<usize> = self.free_slots.true_bits_in(0..slot).next_back();
+        self.free_slots.set_bit(slot, true);
+        match prev_slot {
+            None => return self.push_free_slot_front(slot),
+            Some(prev_slot) if Some(prev_slot) == self.last_free_slot => return self.push_free_slot_back(slot),
+            Some(prev_slot) => {
+                let next_slot: Option<usize> = match self.slots[prev_slot] {
+                    Slot::Free{ next_free_slot } => next_free_slot,
+                    Slot::Item(_) => panic!(),
+                };
+                self.slots[slot] = Slot::Free{next_free_slot: next_slot}; // drops the item contained in the slot
+                self.slots[prev_slot] = Slot::Free{next_free_slot: Some(slot)};
+            },
+        }
+    }
+}
+
+impl <T: Clone, A: Allocator, O: AllocationOrder> Drop for FreeList<T, A, O> {
+    fn drop(&mut self) {
+        find_leaks(
+            type_name::<Self>(),
+            self.num_items,
+            self.slots.iter()
+                .enumerate()
+                .filter(|(_, slot): &(usize, &Slot<T>)| matches!(slot, Slot::Item(_)))
+                .map(|(id, _): (usize, &Slot<T>)| id),
+            |id: usize| self.history.allocated_at(id),
+        ).report();
     }
 }
 
 pub struct Iter<'a, T> {
-    inner: slice::Iter<'a, Slot<T>>
+    inner: slice::Iter<'a, Slot<T>>,
+    num_items_left: usize,
 }
 
 impl <'a, T> Iter<'a, T> {
-    fn new(inner: slice::Iter<'a, Slot<T>>) -> Self {
-        return Self { inner }
+    fn new(inner: slice::Iter<'a, Slot<T>>, num_items: usize) -> Self {
+        return Self { inner, num_items_left: num_items }
     }
 }
 
@@ -137,6 +426,28 @@ impl <'a, T> Iterator for Iter<'a, T> {
             match self.inner.next() {
                 Some(slot) => {
                     if let Slot::Item(item) = slot {
+                        self.num_items_left -= 1;
+                        return Some(item)
+                    }
+                },
+
+                None => return None,
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        return (self.num_items_left, Some(self.num_items_left))
+    }
+}
+
+impl <'a, T> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.inner.next_back() {
+                Some(slot) => {
+                    if let Slot::Item(item) = slot {
+                        self.num_items_left -= 1;
                         return Some(item)
                     }
                 },
@@ -147,11 +458,145 @@ impl <'a, T> Iterator for Iter<'a, T> {
     }
 }
 
+impl <'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+pub struct SortedIter<'a, T> {
+    inner: Enumerate<slice::Iter<'a, Slot<T>>>,
+    num_items_left: usize,
+}
+
+impl <'a, T> SortedIter<'a, T> {
+    fn new(inner: Enumerate<slice::Iter<'a, Slot<T>>>, num_items: usize) -> Self {
+        return Self { inner, num_items_left: num_items }
+    }
+}
+
+impl <'a, T> Iterator for SortedIter<'a, T> {
+    type Item = (usize, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.inner.next() {
+                Some((id, Slot::Item(item))) => {
+                    self.num_items_left -= 1;
+                    return Some((id, item))
+                },
+                Some((_, Slot::Free{..})) => continue,
+                None => return None,
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        return (self.num_items_left, Some(self.num_items_left))
+    }
+}
+
+impl <'a, T> DoubleEndedIterator for SortedIter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.inner.next_back() {
+                Some((id, Slot::Item(item))) => {
+                    self.num_items_left -= 1;
+                    return Some((id, item))
+                },
+                Some((_, Slot::Free{..})) => continue,
+                None => return None,
+            }
+        }
+    }
+}
+
+impl <'a, T> ExactSizeIterator for SortedIter<'a, T> {}
+
+pub struct RangeIter<'a, T> {
+    inner: Zip<Range<usize>, slice::Iter<'a, Slot<T>>>,
+}
+
+impl <'a, T> RangeIter<'a, T> {
+    fn new(slots: &'a [Slot<T>], range: Range<usize>) -> Self {
+        let end: usize = range.end.min(slots.len());
+        let start: usize = range.start.min(end);
+        return Self { inner: (start..end).zip(slots[start..end].iter()) }
+    }
+}
+
+impl <'a, T> Iterator for RangeIter<'a, T> {
+    type Item = (usize, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.inner.next() {
+                Some((id, Slot::Item(item))) => return Some((id, item)),
+                Some((_, Slot::Free{..})) => continue,
+                None => return None,
+            }
+        }
+    }
+}
+
+impl <'a, T> DoubleEndedIterator for RangeIter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.inner.next_back() {
+                Some((id, Slot::Item(item))) => return Some((id, item)),
+                Some((_, Slot::Free{..})) => continue,
+                None => return None,
+            }
+        }
+    }
+}
+
+#[cfg(feature = "rayon")]
+pub struct ParSlots<T> {
+    slots: *mut Slot<T>,
+    num_slots: usize,
+}
+
+#[cfg(feature = "rayon")]
+impl <T> Clone for ParSlots<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl <T> Copy for ParSlots<T> {}
+
+#[cfg(feature = "rayon")]
+unsafe impl <T> Slots<T> for ParSlots<T> {
+    fn num_slots(&self) -> usize {
+        return self.num_slots
+    }
+
+    fn is_live(&self, id: usize) -> bool {
+        return unsafe { matches!(*self.slots.add(id), Slot::Item(_)) }
+    }
+
+    fn item(&self, id: usize) -> *const T {
+        match unsafe { &*self.slots.add(id) } {
+            Slot::Item(item) => return item,
+            Slot::Free{..} => panic!(),
+        }
+    }
+
+    fn item_mut(&self, id: usize) -> *mut T {
+        match unsafe { &mut *self.slots.add(id) } {
+            Slot::Item(item) => return item,
+            Slot::Free{..} => panic!(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::FreeList;
+    use allocator_api2::alloc::Global;
+    use super::{FreeList, Slot, AllocationOrder, Lifo, Fifo, LowestIdFirst};
     use crate::testing;
+    use crate::{InvariantError, Pool as _};
     use crate::testing::Item; 
+    use crate::testing::Growth;
+    use crate::testing::CountingAlloc;
 
     type Pool = FreeList<Item>;
 
@@ -178,12 +623,143 @@ mod tests {
     }
 
     #[test]
+    #[cfg_attr(miri, ignore)] // far too slow to interpret
     fn fuzz_many_pools_few_mutations() {
         testing::fuzz_many_pools_few_mutations::<Pool>();
     }
 
     #[test]
+    #[cfg_attr(miri, ignore)] // far too slow to interpret
     fn fuzz_few_pools_many_mutations() {
         testing::fuzz_few_pools_many_mutations::<Pool>();
     }
+
+    #[test]
+    fn test_sorted_iter() {
+        testing::test_sorted_iter::<Pool>();
+    }
+
+    #[test]
+    fn test_iterators_are_double_ended_and_exact_size() {
+        testing::test_iterators_are_double_ended_and_exact_size::<Pool>();
+    }
+
+    #[test]
+    fn test_iter_range() {
+        testing::test_iter_range::<Pool>();
+    }
+
+    #[test]
+    fn test_stats() {
+        testing::test_stats::<Pool>(Growth::Doubling{ first_capacity: 1 }, true);
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn test_par_iter() {
+        testing::test_par_iter::<Pool>();
+    }
+
+    #[test]
+    fn test_all_memory_goes_through_allocator() {
+        testing::test_all_memory_goes_through_allocator::<FreeList<Item, CountingAlloc>>();
+    }
+
+    #[test]
+    fn test_heap_size_matches_allocator() {
+        testing::test_heap_size_matches_allocator::<FreeList<Item, CountingAlloc>>();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // far too slow to interpret
+    fn fuzz_fifo() {
+        testing::test_many_items::<FreeList<Item, Global, Fifo>>();
+        testing::fuzz_many_pools_few_mutations::<FreeList<Item, Global, Fifo>>();
+        testing::fuzz_few_pools_many_mutations::<FreeList<Item, Global, Fifo>>();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // far too slow to interpret
+    fn fuzz_lowest_id_first() {
+        testing::test_many_items::<FreeList<Item, Global, LowestIdFirst>>();
+        testing::fuzz_many_pools_few_mutations::<FreeList<Item, Global, LowestIdFirst>>();
+        testing::fuzz_few_pools_many_mutations::<FreeList<Item, Global, LowestIdFirst>>();
+    }
+
+    #[test]
+    fn test_lowest_id_first_heap_size_matches_allocator() {
+        testing::test_heap_size_matches_allocator::<FreeList<Item, CountingAlloc, LowestIdFirst>>();
+    }
+
+    // Frees ids 5, 1 and 3 out of a full pool of 8, and returns the ids the next three allocations get
+    fn reused_ids<O: AllocationOrder>() -> [usize; 3] {
+        let mut pool: FreeList<Item, Global, O> = FreeList::with_capacity(8);
+        for i in 0..8 {
+            pool.allocate(i);
+        }
+        for id in [5, 1, 3] {
+            pool.deallocate(id);
+            assert!(pool.check_invariants().is_ok());
+        }
+        return [pool.allocate(0), pool.allocate(0), pool.allocate(0)]
+    }
+
+    #[test]
+    fn test_allocation_orders() {
+        assert!(reused_ids::<Lifo>() == [3, 1, 5]);
+        assert!(reused_ids::<Fifo>() == [5, 1, 3]);
+        assert!(reused_ids::<LowestIdFirst>() == [1, 3, 5]);
+    }
+
+    // Frees 2 then 0 out of a full pool of 4, then links the free list back to front
+    fn pool_with_reversed_free_list<O: AllocationOrder>() -> FreeList<Item, Global, O> {
+        let mut pool: FreeList<Item, Global, O> = FreeList::with_capacity(4);
+        for i in 0..4 {
+            pool.allocate(i);
+        }
+        pool.deallocate(2);
+        pool.deallocate(0);
+        assert!(pool.check_invariants().is_ok());
+
+        pool.slots[2] = Slot::Free{ next_free_slot: Some(0) };
+        pool.slots[0] = Slot::Free{ next_free_slot: None };
+        pool.next_free_slot = Some(2);
+        pool.last_free_slot = Some(0);
+        return pool
+    }
+
+    #[test]
+    fn test_check_invariants_finds_misordered_free_list() {
+        assert!(pool_with_reversed_free_list::<LowestIdFirst>().check_invariants() == Err(InvariantError::FreeListOutOfOrder{ slot: 0 }));
+        assert!(pool_with_reversed_free_list::<Lifo>().check_invariants().is_ok());
+
+        let mut pool: FreeList<Item, Global, Lifo> = pool_with_reversed_free_list();
+        pool.last_free_slot = Some(2);
+        assert!(pool.check_invariants() == Err(InvariantError::LastFreeSlotIsntLast{ last_free_slot: Some(2) }));
+    }
+
+    #[test]
+    fn test_check_invariants_finds_stale_free_slot_flags() {
+        let mut pool: FreeList<Item, Global, LowestIdFirst> = FreeList::with_capacity(4);
+        let id: usize = pool.allocate(1);
+        assert!(pool.check_invariants().is_ok());
+
+        pool.free_slots.set_bit(id, true);
+        assert!(pool.check_invariants() == Err(InvariantError::FreeSlotsDisagreeWithFlags{ slot: id }));
+    }
+
+    #[test]
+    fn test_check_invariants_finds_broken_free_list() {
+        let mut pool: Pool = Pool::with_capacity(4);
+        let id: usize = pool.allocate(1);
+        assert!(pool.check_invariants().is_ok());
+
+        let next_free_slot: usize = pool.next_free_slot.unwrap();
+        pool.slots[next_free_slot] = Slot::Free{ next_free_slot: Some(next_free_slot) };
+        assert!(pool.check_invariants() == Err(InvariantError::FreeListHasCycle{ slot: next_free_slot }));
+        pool.slots[next_free_slot] = Slot::Free{ next_free_slot: Some(id) };
+        assert!(pool.check_invariants() == Err(InvariantError::FreeListLeadsToItem{ slot: id }));
+        pool.slots[next_free_slot] = Slot::Free{ next_free_slot: None };
+        assert!(pool.check_invariants() == Err(InvariantError::FreeSlotsMissingFromFreeList{ num_free_slots: 3, num_slots_in_free_list: 1 }));
+    }
 }
\ No newline at end of file
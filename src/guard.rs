@@ -0,0 +1,66 @@
+use core::marker::PhantomData;
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+
+use super::Pool;
+
+/*
+    Allocates an item that's deallocated again when the returned guard goes out
+    of scope, so an early return or a panic between allocating an item and handing
+    its id off can't leak the slot. Once the item is safely stored somewhere,
+    into_id() disarms the guard and gives back the id.
+*/
+#[cfg_attr(feature = "debug-checks", track_caller)]
+pub fn allocate_scoped<T, P: Pool<T>>(pool: &mut P, item: T) -> SlotGuard<'_, T, P> {
+    let id: usize = pool.allocate(item);
+    return SlotGuard {
+        pool,
+        id,
+        _items: PhantomData,
+    }
+}
+
+pub struct SlotGuard<'a, T, P: Pool<T>> {
+    pool: &'a mut P,
+    id: usize,
+    _items: PhantomData<T>,
+}
+
+impl <'a, T, P: Pool<T>> SlotGuard<'a, T, P> {
+    pub fn id(&self) -> usize {
+        return self.id
+    }
+
+    // Keeps the item allocated and returns its id
+    pub fn into_id(self) -> usize {
+        let guard: ManuallyDrop<Self> = ManuallyDrop::new(self);
+        return guard.id
+    }
+}
+
+impl <'a, T, P: Pool<T>> Deref for SlotGuard<'a, T, P> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        return self.pool.get(self.id)
+    }
+}
+
+impl <'a, T, P: Pool<T>> DerefMut for SlotGuard<'a, T, P> {
+    fn deref_mut(&mut self) -> &mut T {
+        return self.pool.get_mut(self.id)
+    }
+}
+
+impl <'a, T, P: Pool<T>> Drop for SlotGuard<'a, T, P> {
+    fn drop(&mut self) {
+        self.pool.deallocate(self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing;
+
+    testing::test_every_backend!(test_slot_guard);
+}
@@ -0,0 +1,48 @@
+use alloc::vec::Vec;
+use core::mem::size_of;
+use allocator_api2::alloc::Allocator;
+
+/*
+    How many bytes of heap a pool owns, split up by what they're for, so a
+    memory budget can show where the bytes went and not just how many there
+    are. Storage is counted by capacity rather than length, since that's what
+    was actually allocated.
+
+    Items can own heap memory of their own, which a pool has no way of seeing.
+    heap_size_bytes_with() takes a function that reports it for a single item,
+    and it's added up over the live items as the "item_heap" component.
+    heap_size_bytes() counts it as 0.
+*/
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HeapUsage {
+    components: Vec<(&'static str, usize)>,
+}
+
+impl HeapUsage {
+    pub(crate) fn new() -> Self {
+        return Self::default()
+    }
+
+    pub(crate) fn with(mut self, component: &'static str, num_bytes: usize) -> Self {
+        self.components.push((component, num_bytes));
+        return self
+    }
+
+    pub fn total_bytes(&self) -> usize {
+        return self.components.iter().map(|(_, num_bytes): &(&'static str, usize)| num_bytes).sum()
+    }
+
+    pub fn components(&self) -> &[(&'static str, usize)] {
+        return &self.components
+    }
+
+    pub fn bytes_of(&self, component: &str) -> Option<usize> {
+        return self.components.iter()
+            .find(|(name, _): &&(&'static str, usize)| *name == component)
+            .map(|(_, num_bytes): &(&'static str, usize)| *num_bytes)
+    }
+}
+
+pub(crate) fn vec_heap_size_bytes<T, A: Allocator>(vec: &allocator_api2::vec::Vec<T, A>) -> usize {
+    return vec.capacity() * size_of::<T>()
+}
@@ -0,0 +1,148 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::flag_based::Block;
+
+/*
+    What check_invariants() found wrong with a pool's internal state. None of
+    these can happen through the public API, so any of them means a bug in
+    the backend, and they carry enough to find where its bookkeeping went
+    wrong.
+
+    New backends bring new ways to go wrong, so matching on it needs a
+    wildcard arm.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum InvariantError {
+    NumItemsDoesntMatchSlots {
+        num_items: usize,
+        num_allocated_slots: usize, // counted from the flags or slots themselves
+    },
+
+    StorageLengthsDisagree {
+        num_slots: usize,
+        num_flags: usize,
+    },
+
+    // FreeList and Paged
+    FreeListHasCycle { slot: usize },
+    FreeListLeadsToItem { slot: usize },
+    FreeListOutOfBounds { slot: usize },
+    FreeSlotsMissingFromFreeList {
+        num_free_slots: usize,
+        num_slots_in_free_list: usize,
+    },
+
+    // FreeList
+    LastFreeSlotIsntLast { last_free_slot: Option<usize> }, // what the pool has as the end of the list, which isn't where it ends
+    FreeListOutOfOrder { slot: usize }, // a slot lower than the one before it, with LowestIdFirst
+    FreeSlotsDisagreeWithFlags { slot: usize }, // a slot whose bit in LowestIdFirst's free_slots says otherwise
+
+    // Stacks and NotSafe
+    OpenBlocksDisagreeWithFlags { block: usize }, // a block with a free slot that isn't listed exactly once, or a full one that's listed
+    AllocBlocksDisagreeWithFlags { block: usize }, // the same for blocks with at least one item
+
+    // NotSafe
+    NodeBelongsToAnotherBlock { block: usize },
+    LinkedListBroken { block: usize }, // a link that isn't mirrored by the one coming back, or an end that isn't head or tail
+    LinkedListDisagreesWithFlags { block: usize },
+
+    // Reference
+    IdIsntItemAddress { id: usize },
+
+    // SparseSet
+    DenseArraysDisagree {
+        num_items: usize,
+        num_ids: usize,
+    },
+    DenseIndexOutOfBounds { id: usize },
+    DenseIndexPointsToAnotherId { id: usize }, // the item at the id's dense index belongs to a different id
+
+    // BitVec
+    WrongNumberOfBlocks {
+        num_bits: usize,
+        num_blocks: usize,
+    },
+
+    // HierarchicalBitVec
+    LevelsIsAllocatedButLevelZeroHasZeroBits,
+
+    TopmostLevelHasMoreThanOneFlag,
+
+    LevelContainsLessBitsThanLevelZero {
+        level: usize,
+        max_num_bits_level_can_encompass: usize,
+        num_bits_level_zero_encompasses: usize,
+    },
+
+    LevelEncompassesMoreBitsThanAreNeededToFitNumBits {
+        level: usize,
+        min_num_bits_needed_to_fit_num_bits_at_level: usize,
+        num_bits_level_zero_encompasses: usize,
+    },
+
+    ParentBitDoesntMatchChildBlock {
+        idx_of_parent_bit: usize,
+        parent_bit: Block,
+        parent_level: usize,
+        idx_of_child_flags: usize,
+        child_flags: Block,
+        child_level: usize,
+    },
+
+    FalseSummariesDontMatchLevels {
+        num_levels: usize,
+        num_false_summaries: usize,
+    },
+
+    FalseSummaryBitDoesntMatchChildBlock { // the same as above, with child_flags being the zeros below the parent bit
+        idx_of_parent_bit: usize,
+        parent_bit: Block,
+        parent_level: usize,
+        idx_of_child_flags: usize,
+        child_flags: Block,
+        child_level: usize,
+    },
+}
+
+// Walks a free list threaded through the free slots, the way FreeList and Paged keep theirs. next_free_slot() is None for a slot with an item in it.
+pub(crate) fn check_free_list<F: Fn(usize) -> Option<Option<usize>>>(first_free_slot: Option<usize>, num_slots: usize, num_items: usize, next_free_slot: F) -> Result<(), InvariantError> {
+    let mut visited: Vec<bool> = vec![false; num_slots];
+    let mut num_slots_in_free_list: usize = 0;
+    let mut slot: Option<usize> = first_free_slot;
+    while let Some(curr_slot) = slot {
+        if curr_slot >= num_slots {
+            return Err(InvariantError::FreeListOutOfBounds{ slot: curr_slot })
+        }
+        if visited[curr_slot] {
+            return Err(InvariantError::FreeListHasCycle{ slot: curr_slot })
+        }
+        visited[curr_slot] = true;
+        num_slots_in_free_list += 1;
+
+        match next_free_slot(curr_slot) {
+            Some(next_slot) => slot = next_slot,
+            None => return Err(InvariantError::FreeListLeadsToItem{ slot: curr_slot }),
+        }
+    }
+
+    let num_free_slots: usize = num_slots - num_items;
+    if num_slots_in_free_list != num_free_slots {
+        return Err(InvariantError::FreeSlotsMissingFromFreeList{ num_free_slots, num_slots_in_free_list })
+    }
+    return Ok(())
+}
+
+// Returns a block that's in listed when it shouldn't be, isn't when it should be, or is in there more than once
+pub(crate) fn find_block_listed_wrongly<F: Fn(usize) -> bool>(listed: &[usize], num_blocks: usize, should_be_listed: F) -> Option<usize> {
+    let mut num_times_listed: Vec<usize> = vec![0; num_blocks];
+    for block in listed.iter() {
+        if *block >= num_blocks {
+            return Some(*block)
+        }
+        num_times_listed[*block] += 1;
+    }
+
+    return (0..num_blocks).find(|block: &usize| num_times_listed[*block] != should_be_listed(*block) as usize)
+}
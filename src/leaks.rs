@@ -0,0 +1,170 @@
+use alloc::vec::Vec;
+use core::fmt;
+use core::panic::Location;
+#[cfg(feature = "std")]
+use std::cell::RefCell;
+#[cfg(feature = "std")]
+use std::rc::Rc;
+
+
+/*
+    Dropping a pool that still has items in it drops the items along with it,
+    which is fine, but in tests it usually means something forgot to
+    deallocate. Once a LeakHandler is set on a thread, every pool dropped on
+    that thread with items left in it reports their ids, along with where each
+    was allocated from when debug-checks is on.
+
+    The handler is per thread, so that tests running side by side don't see
+    each other's pools, and it's off until it's set. Setting it needs std for
+    the thread local, so without std pools never report.
+*/
+#[cfg(feature = "std")]
+#[derive(Clone)]
+pub enum LeakHandler {
+    Panic,
+    Log, // to stderr
+    Callback(Rc<dyn Fn(&LeakReport)>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Leak {
+    pub id: usize,
+    pub allocated_at: Option<&'static Location<'static>>, // only known with debug-checks
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LeakReport {
+    pool_type: &'static str,
+    leaks: Vec<Leak>, // in ascending id order
+}
+
+impl LeakReport {
+    pub fn pool_type(&self) -> &'static str {
+        return self.pool_type
+    }
+
+    pub fn leaks(&self) -> &[Leak] {
+        return &self.leaks
+    }
+}
+
+impl fmt::Display for LeakReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let items: &str = if self.leaks.len() == 1 { "item" } else { "items" };
+        write!(f, "{} was dropped with {} {} still allocated: ", self.pool_type, self.leaks.len(), items)?;
+        for (i, leak) in self.leaks.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "id {}", leak.id)?;
+            if let Some(allocated_at) = leak.allocated_at {
+                write!(f, " (allocated at {})", allocated_at)?;
+            }
+        }
+        return Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    static LEAK_HANDLER: RefCell<Option<LeakHandler>> = const { RefCell::new(None) };
+}
+
+// Sets the handler for pools dropped on this thread, returning the one it replaces. None turns reporting off again.
+#[cfg(feature = "std")]
+pub fn set_leak_handler(handler: Option<LeakHandler>) -> Option<LeakHandler> {
+    return LEAK_HANDLER.with(|current: &RefCell<Option<LeakHandler>>| current.replace(handler))
+}
+
+/*
+    Called by every backend at the start of its drop(), while the items are
+    still there, to find out what to report. live_ids is only walked if there's
+    a report to make, and doesn't have to give the ids in order.
+
+    The report is only made once the backend has dropped its items, by calling
+    report() on what this returns, so that a handler that panics doesn't skip
+    the items a backend drops by hand. Backends whose items are dropped along
+    with their fields can report straight away, since fields are dropped even
+    when drop() panics.
+
+    Nothing is reported while the thread is already panicking, since a second
+    panic from a drop would abort instead of letting the first one through.
+*/
+#[cfg(feature = "std")]
+pub(crate) fn find_leaks<I: Iterator<Item=usize>, F: Fn(usize) -> Option<&'static Location<'static>>>(pool_type: &'static str, num_items: usize, live_ids: I, allocated_at: F) -> PendingLeakReport {
+    if num_items == 0 || std::thread::panicking() {
+        return PendingLeakReport(None)
+    }
+    // Cloned out so that the handler can drop pools of its own
+    let handler: Option<LeakHandler> = LEAK_HANDLER.with(|current: &RefCell<Option<LeakHandler>>| current.borrow().clone());
+    let handler: LeakHandler = match handler {
+        Some(handler) => handler,
+        None => return PendingLeakReport(None),
+    };
+
+    let mut ids: Vec<usize> = live_ids.collect();
+    ids.sort_unstable();
+    let report: LeakReport = LeakReport {
+        pool_type,
+        leaks: ids.into_iter().map(|id: usize| Leak{ id, allocated_at: allocated_at(id) }).collect(),
+    };
+    return PendingLeakReport(Some((handler, report)))
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn find_leaks<I: Iterator<Item=usize>, F: Fn(usize) -> Option<&'static Location<'static>>>(_pool_type: &'static str, _num_items: usize, _live_ids: I, _allocated_at: F) -> PendingLeakReport {
+    return PendingLeakReport
+}
+
+#[cfg(feature = "std")]
+#[must_use = "nothing is reported until report() is called"]
+pub(crate) struct PendingLeakReport(Option<(LeakHandler, LeakReport)>);
+
+#[cfg(not(feature = "std"))]
+#[must_use = "nothing is reported until report() is called"]
+pub(crate) struct PendingLeakReport;
+
+impl PendingLeakReport {
+    #[cfg(feature = "std")]
+    pub(crate) fn report(self) {
+        let (handler, report): (LeakHandler, LeakReport) = match self.0 {
+            Some(pending) => pending,
+            None => return,
+        };
+        match handler {
+            LeakHandler::Panic => panic!("{}", report),
+            LeakHandler::Log => std::eprintln!("{}", report),
+            LeakHandler::Callback(callback) => callback(&report),
+        }
+    }
+
+    #[cfg(not(feature = "std"))]
+    #[inline(always)]
+    pub(crate) fn report(self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LeakHandler, set_leak_handler};
+    use crate::testing;
+    use crate::testing::Item;
+    use crate::{Pool, Simple};
+
+    testing::test_every_backend!(test_leaks_are_reported);
+
+    mod panic_handler {
+        use crate::testing;
+
+        testing::test_every_backend!(test_panicking_leak_handler_still_drops_items; for std::rc::Rc<()>);
+    }
+
+    #[test]
+    #[should_panic(expected = "was dropped with 1 item still allocated: id 1")]
+    fn test_panic_handler() {
+        set_leak_handler(Some(LeakHandler::Panic));
+        let mut pool: Simple<Item> = Simple::new();
+        let id: usize = pool.allocate(1);
+        pool.allocate(2);
+        pool.deallocate(id);
+    }
+}
@@ -1,22 +1,156 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+use core::ops::Range;
+use allocator_api2::alloc::Allocator;
+
+use stats::Watermarks;
+
 mod reference;
 mod simple;
 mod stacks;
 mod freelist;
 mod notsafe;
 mod flag_based;
+mod paged;
+mod sparse_set;
+mod bounded;
+mod shared;
+mod guard;
+mod tracked;
+mod observed;
+mod stats;
+mod heap_size;
+mod recording;
+mod invariants;
+mod debug_checks;
+mod leaks;
+#[cfg(feature = "rayon")]
+mod parallel;
 
 #[cfg(test)]
 mod testing;
+#[cfg(any(test, feature = "conformance"))]
+pub mod conformance;
+
+pub use reference::Reference;
+pub use simple::Simple;
+pub use stacks::Stacks;
+pub use freelist::{FreeList, AllocationOrder, Lifo, Fifo, LowestIdFirst};
+pub use notsafe::NotSafe;
+pub use flag_based::{FlagVec, FlagsBasedPool, BitFlags, BoolFlags, HierarchicalFlags, HierarchicalBitVec};
+pub use paged::Paged;
+pub use sparse_set::SparseSet;
+pub use bounded::Bounded;
+pub use shared::{SharedPool, PoolRc, PoolWeak, PoolRef, PoolRefMut};
+pub use guard::{allocate_scoped, SlotGuard};
+pub use tracked::{Tracked, Change};
+pub use observed::{Observed, PoolObserver};
+pub use stats::PoolStats;
+pub use heap_size::HeapUsage;
+pub use invariants::InvariantError;
+pub use leaks::{Leak, LeakReport};
+#[cfg(feature = "std")]
+pub use leaks::{LeakHandler, set_leak_handler};
+pub use recording::{Recording, Trace, TraceOp, TraceOps, TraceError};
+#[cfg(feature = "rayon")]
+pub use parallel::{ParallelPool, ParIter, ParIterMut};
+
+#[cfg(feature = "derive")]
+pub use pool_party_derive::SoaPool;
+
+// What the code generated by #[derive(SoaPool)] refers to, so that it works with and without std
+#[doc(hidden)]
+pub mod __private {
+    pub use alloc::vec::Vec;
+    pub use core::mem::{MaybeUninit, ManuallyDrop};
+    pub use core::ptr;
+    pub use crate::flag_based::HierarchicalBitVec;
+}
+
+// The flags BitFlags and BoolFlags keep, which benches/flags.rs measures on their own
+#[doc(hidden)]
+pub mod __bench {
+    pub use crate::flag_based::{BitVec, BoolVec};
+}
 
 pub trait Pool<T> {
-    type Iter<'a>: Iterator<Item=&'a T> where Self: 'a, T: 'a;
+    type Iter<'a>: DoubleEndedIterator<Item=&'a T> + ExactSizeIterator where Self: 'a, T: 'a;
 
     fn new() -> Self;
     fn with_capacity(num_items: usize) -> Self;
     fn len(&self) -> usize;
+    #[cfg_attr(feature = "debug-checks", track_caller)] // so that use-after-free reports point at the caller
     fn get(&self, id: usize) -> &T;
+    #[cfg_attr(feature = "debug-checks", track_caller)]
     fn get_mut(&mut self, id: usize) -> &mut T;
+    #[cfg_attr(feature = "debug-checks", track_caller)]
     fn allocate(&mut self, item: T) -> usize;
-    fn deallocate(&mut self, id: usize);
+    #[cfg_attr(feature = "debug-checks", track_caller)]
+    fn deallocate(&mut self, id: usize); // frees the slot before dropping the item, so a panicking drop can't drop it a second time
     fn iter<'a>(&'a self) -> Self::Iter<'a>;
-}
\ No newline at end of file
+
+    // How many items fit before the pool has to grow, which a pool that can't tell can leave at len()
+    fn capacity(&self) -> usize {
+        return self.len()
+    }
+
+    /*
+        Without a history to go on, this only describes the pool as it is now:
+        the peak is the current number of items, it never grew, and every slot
+        is a block of its own. Backends that keep Watermarks override it.
+    */
+    fn stats(&self) -> PoolStats {
+        let mut watermarks: Watermarks = Watermarks::new();
+        watermarks.allocated(self.len());
+        return PoolStats::new(self.len(), self.capacity(), watermarks, 1, 0, None)
+    }
+
+    // F reports the heap owned by a single item. A pool that doesn't know its own storage only reports the items' heap.
+    fn heap_size_bytes_with<F: FnMut(&T) -> usize>(&self, item_heap_size_bytes: F) -> HeapUsage {
+        return HeapUsage::new().with("item_heap", self.iter().map(item_heap_size_bytes).sum())
+    }
+
+    fn heap_size_bytes(&self) -> HeapUsage {
+        return self.heap_size_bytes_with(|_: &T| 0)
+    }
+
+    // Only fails if the pool has a bug, so a pool with nothing to check can leave it passing
+    fn check_invariants(&self) -> Result<(), InvariantError> {
+        return Ok(())
+    }
+}
+
+/*
+    A pool whose with_capacity() allocates all of the storage that many items
+    need up front, so that allocating, deallocating, accessing and iterating
+    never touch the heap as long as it holds no more than that. Bounded is
+    only available for these.
+
+    Their ids are also dense, counting up from 0 rather than being addresses,
+    and allocator() gives the allocator their storage is in, so Tracked can
+    keep a bit per id alongside it.
+*/
+pub trait PreallocatedPool<T>: Pool<T> {
+    type Alloc: Allocator + Clone;
+
+    fn allocator(&self) -> &Self::Alloc;
+}
+
+/*
+    A pool whose items can be iterated in ascending id order, whatever order
+    iter() happens to use. Two pools holding the same items at the same ids
+    always yield them the same way.
+
+    iter_range() does the same for only the items whose ids are in a range,
+    without walking the ids before it. The range is allowed to go past the
+    highest id the pool has handed out.
+*/
+pub trait OrderedPool<T>: Pool<T> {
+    type SortedIter<'a>: DoubleEndedIterator<Item=(usize, &'a T)> + ExactSizeIterator where Self: 'a, T: 'a;
+    type RangeIter<'a>: DoubleEndedIterator<Item=(usize, &'a T)> where Self: 'a, T: 'a;
+
+    fn sorted_iter<'a>(&'a self) -> Self::SortedIter<'a>;
+    fn iter_range<'a>(&'a self, range: Range<usize>) -> Self::RangeIter<'a>;
+}
@@ -1,7 +1,24 @@
-use super::Pool;
-use std::ptr::null;
-use std::ptr::null_mut;
-use std::mem::size_of;
+use core::any::type_name;
+use super::{Pool, OrderedPool, PreallocatedPool};
+use super::stats::{PoolStats, Watermarks};
+use super::debug_checks::SlotHistories;
+use super::leaks::{find_leaks, PendingLeakReport};
+use super::heap_size::{HeapUsage, vec_heap_size_bytes};
+use super::InvariantError;
+use super::invariants::find_block_listed_wrongly;
+#[cfg(feature = "rayon")]
+use super::parallel::{ParallelPool, ParIter, ParIterMut, Slots, split_point_by_blocks};
+use core::iter::Enumerate;
+use core::slice;
+use core::ops::Range;
+use core::ptr::null;
+use core::ptr::null_mut;
+use core::mem::size_of;
+use core::mem::MaybeUninit;
+use allocator_api2::alloc::{Allocator, Global};
+use allocator_api2::boxed::Box;
+use allocator_api2::vec;
+use allocator_api2::vec::Vec;
 
 type FlagBlock = u8;
 const BITS_PER_BYTE: usize = 8;
@@ -15,69 +32,126 @@ struct Node {
     next: *mut Node,
 }
 
-pub struct NotSafe<T: Clone> {
-    items: Vec<Option<T>>,
+impl Node {
+    // Nodes are allocated and freed through &A rather than A, so that NotSafe doesn't need to clone its allocator to drop
+    fn new_boxed<A: Allocator>(block: usize, alloc: &A) -> *mut Node {
+        let node: Node = Node {
+            block,
+            prev: null_mut(),
+            next: null_mut(),
+        };
+        return Box::into_raw(Box::new_in(node, alloc))
+    }
+}
+
+pub struct NotSafe<T: Clone, A: Allocator = Global> {
+    items: Vec<MaybeUninit<T>, A>, // only initialized where the item's flag is set
     num_items: usize,
+    watermarks: Watermarks, // for stats(), since neither can be worked out afterwards
+    history: SlotHistories<A>, // where each slot was last allocated and freed from, with debug-checks
 
-    flags: Vec<FlagBlock>, // item allocation flags for each block (0 for unallocated, 1 for allocated)
-    open_blocks: Vec<usize>, // stack containing indices of blocks which contain at least one unallocated item
-    nodes: Vec<*mut Node>, // map from a block's index to its entry in the linked list
+    flags: Vec<FlagBlock, A>, // item allocation flags for each block (0 for unallocated, 1 for allocated)
+    open_blocks: Vec<usize, A>, // stack containing indices of blocks which contain at least one unallocated item
+    nodes: Vec<*mut Node, A>, // map from a block's index to its entry in the linked list, boxed once per block and only freed on drop
     head: *mut Node, // linked list of blocks which have at least one item allocated
+    tail: *mut Node, // last node in that list, so it can be walked backwards
+    alloc: A, // what the nodes are boxed with
 }
 
-impl <T: Clone> Pool<T> for NotSafe<T> {
+impl <T: Clone, A: Allocator + Clone + Default> Pool<T> for NotSafe<T, A> {
     type Iter<'a> = Iter<'a, T> where Self: 'a, T: 'a;
     
     fn new() -> Self {
-        return Self {
-            items: Vec::new(),
-            num_items: 0,
-
-            flags: Vec::new(),
-            open_blocks: Vec::new(),
-            nodes: Vec::new(),
-            head: null_mut(),
-        }
+        return Self::new_in(A::default())
     }
 
     fn with_capacity(capacity: usize) -> Self {
-        let num_blocks: usize = 
-            if capacity == 0 {
-                0                                
-            } 
-            else {
-                ((capacity-1)/FLAGS_PER_BLOCK)+1
-            };
-    
-        let items: Vec<Option<T>> = vec![None; num_blocks*FLAGS_PER_BLOCK];
-        let num_items: usize = 0;
+        return Self::with_capacity_in(capacity, A::default())
+    }
 
-        let flags: Vec<FlagBlock> = vec![0; num_blocks];
-        let open_blocks: Vec<usize> = (0..num_blocks).rev().collect();
-        let nodes: Vec<*mut Node> = vec![null_mut(); num_blocks];
-        let head: *mut Node = null_mut();
+    fn len(&self) -> usize {
+        return self.num_items
+    }
 
-        return Self {
-            items,
-            num_items,
+    fn capacity(&self) -> usize {
+        return self.items.len()
+    }
 
-            flags,
-            open_blocks,
-            nodes,
-            head,
-        }
+    fn stats(&self) -> PoolStats {
+        let num_partial_blocks: usize = self.flags.iter()
+            .filter(|flags: &&FlagBlock| **flags != EMPTY_BLOCK && **flags != FULL_BLOCK)
+            .count();
+        return PoolStats::new(self.num_items, self.items.len(), self.watermarks, FLAGS_PER_BLOCK, num_partial_blocks, self.sorted_iter().next_back().map(|(id, _): (usize, &T)| id))
     }
 
-    fn len(&self) -> usize {
-        return self.num_items
+    fn heap_size_bytes_with<F: FnMut(&T) -> usize>(&self, item_heap_size_bytes: F) -> HeapUsage {
+        return HeapUsage::new()
+            .with("items", vec_heap_size_bytes(&self.items))
+            .with("flags", vec_heap_size_bytes(&self.flags))
+            .with("open_blocks", vec_heap_size_bytes(&self.open_blocks))
+            .with("node_map", vec_heap_size_bytes(&self.nodes))
+            .with("nodes", self.nodes.len() * size_of::<Node>()) // every block has its node boxed, whether or not it's in the list
+            .with("history", self.history.heap_size_bytes())
+            .with("item_heap", self.iter().map(item_heap_size_bytes).sum())
+    }
+
+    fn check_invariants(&self) -> Result<(), InvariantError> {
+        if self.items.len() != self.flags.len()*FLAGS_PER_BLOCK {
+            return Err(InvariantError::StorageLengthsDisagree{ num_slots: self.items.len(), num_flags: self.flags.len()*FLAGS_PER_BLOCK })
+        }
+        if self.nodes.len() != self.flags.len() {
+            return Err(InvariantError::StorageLengthsDisagree{ num_slots: self.nodes.len()*FLAGS_PER_BLOCK, num_flags: self.flags.len()*FLAGS_PER_BLOCK })
+        }
+
+        let num_allocated_slots: usize = self.flags.iter().map(|flags: &FlagBlock| flags.count_ones() as usize).sum();
+        if num_allocated_slots != self.num_items {
+            return Err(InvariantError::NumItemsDoesntMatchSlots{ num_items: self.num_items, num_allocated_slots })
+        }
+
+        if let Some(block) = find_block_listed_wrongly(&self.open_blocks, self.flags.len(), |block: usize| self.flags[block] != FULL_BLOCK) {
+            return Err(InvariantError::OpenBlocksDisagreeWithFlags{ block })
+        }
+
+        for (block, node) in self.nodes.iter().enumerate() {
+            if unsafe { (**node).block } != block {
+                return Err(InvariantError::NodeBelongsToAnotherBlock{ block })
+            }
+        }
+
+        // Every node has to point back at the one before it, and the list can't visit a block twice
+        let mut in_list: Vec<bool> = vec![false; self.flags.len()];
+        let mut prev_node: *mut Node = null_mut();
+        let mut node: *mut Node = self.head;
+        while node != null_mut() {
+            let block: usize = unsafe { (*node).block };
+            if unsafe { (*node).prev } != prev_node || in_list[block] {
+                return Err(InvariantError::LinkedListBroken{ block })
+            }
+            in_list[block] = true;
+            prev_node = node;
+            node = unsafe { (*node).next };
+        }
+        if self.tail != prev_node {
+            let block: usize = if self.tail != null_mut() { unsafe { (*self.tail).block } } else { unsafe { (*prev_node).block } };
+            return Err(InvariantError::LinkedListBroken{ block })
+        }
+
+        if let Some(block) = (0..self.flags.len()).find(|block: &usize| in_list[*block] != (self.flags[*block] != EMPTY_BLOCK)) {
+            return Err(InvariantError::LinkedListDisagreesWithFlags{ block })
+        }
+        return Ok(())
     }
 
     fn get(&self, id: usize) -> &T {
-        return self.items[id].as_ref().unwrap()
+        self.history.check_access(id);
+        assert!(self.is_allocated(id));
+        return unsafe { self.items[id].assume_init_ref() }
     }
 
     fn get_mut(&mut self, id: usize) -> &mut T {
-        return self.items[id].as_mut().unwrap()
+        self.history.check_access(id);
+        assert!(self.is_allocated(id));
+        return unsafe { self.items[id].assume_init_mut() }
     }
 
     fn allocate(&mut self, item: T) -> usize {
@@ -95,35 +169,34 @@ impl <T: Clone> Pool<T> for NotSafe<T> {
 
         let block_now_has_one_item_allocated: bool = self.flags[open_block].count_ones() == 1;
         if block_now_has_one_item_allocated {
-            let node: Node = Node { 
-                block: open_block,
-                prev: null_mut(),
-                next: self.head,
-            };
-            let node: *mut Node = Box::into_raw(Box::new(node));
-    
-            assert!(self.nodes[open_block] == null_mut());
-            self.nodes[open_block] = node;
-    
+            let node: *mut Node = self.nodes[open_block];
+            assert!(node != null_mut());
+
             unsafe {
+                assert!((*node).prev == null_mut() && (*node).next == null_mut());
+                (*node).next = self.head;
                 if self.head != null_mut() {
                     assert!((*self.head).prev == null_mut());
                     (*self.head).prev = node;
                 }
+                else {
+                    self.tail = node;
+                }
                 self.head = node;
             }
         }
 
         let global_bit: usize = open_block*FLAGS_PER_BLOCK + local_bit;
-        assert!(self.items[global_bit].is_none());
-        self.items[global_bit] = Some(item);
+        self.items[global_bit].write(item);
+        self.history.allocated(global_bit);
         self.num_items += 1;
+        self.watermarks.allocated(self.num_items);
 
         return global_bit
     }
 
     fn deallocate(&mut self, id: usize) {
-        assert!(self.items[id].is_some());
+        self.history.check_deallocate(id);
         let block: usize = id / FLAGS_PER_BLOCK;
         let local_bit: usize = id % FLAGS_PER_BLOCK;
         assert!( ((self.flags[block] & (1 << local_bit)) >> local_bit) == 1);
@@ -143,23 +216,25 @@ impl <T: Clone> Pool<T> for NotSafe<T> {
                 if node == self.head {
                     self.head = (*node).next;
                 }
+                if node == self.tail {
+                    self.tail = (*node).prev;
+                }
                 if (*node).prev != null_mut() {
                     (*(*node).prev).next = (*node).next;
                 }
                 if (*node).next != null_mut() {
                     (*(*node).next).prev = (*node).prev;
                 }
-                let _drop: Box<Node> = Box::from_raw(node);
-                self.nodes[block] = null_mut();
-                // _drop goes out of scope and is dropped
+                (*node).prev = null_mut();
+                (*node).next = null_mut();
+                // the node stays boxed so that the block can be relinked without allocating
             }
         }
 
         let global_bit: usize = block*FLAGS_PER_BLOCK + local_bit;
-        assert!(self.items[global_bit].is_some());
-        self.items[global_bit] = None;
-
+        self.history.freed(global_bit);
         self.num_items -= 1;
+        unsafe { self.items[global_bit].assume_init_drop() };
     }
 
     fn iter<'a>(&'a self) -> Iter<'a, T> {
@@ -167,11 +242,109 @@ impl <T: Clone> Pool<T> for NotSafe<T> {
             &self.items,
             &self.flags,
             self.head as *const Node,
+            self.tail as *const Node,
+            self.num_items,
         )
     }
 }
 
-impl <T: Clone> NotSafe<T> {
+impl <T: Clone, A: Allocator + Clone + Default> PreallocatedPool<T> for NotSafe<T, A> {
+    type Alloc = A;
+
+    fn allocator(&self) -> &A {
+        return self.items.allocator()
+    }
+}
+
+impl <T: Clone, A: Allocator + Clone + Default> OrderedPool<T> for NotSafe<T, A> {
+    type SortedIter<'a> = SortedIter<'a, T> where Self: 'a, T: 'a;
+    type RangeIter<'a> = SortedIter<'a, T> where Self: 'a, T: 'a; // can count the items in range a block at a time, so this stays exact-size
+
+    fn sorted_iter<'a>(&'a self) -> SortedIter<'a, T> {
+        return SortedIter::new(&self.items, &self.flags, self.num_items)
+    }
+
+    fn iter_range<'a>(&'a self, range: Range<usize>) -> SortedIter<'a, T> {
+        return SortedIter::in_range(&self.items, &self.flags, range)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl <T: Clone + Send + Sync, A: Allocator + Clone + Default> ParallelPool<T> for NotSafe<T, A> {
+    type ParIter<'a> = ParIter<'a, T, ParSlots<T>> where Self: 'a, T: 'a;
+    type ParIterMut<'a> = ParIterMut<'a, T, ParSlots<T>> where Self: 'a, T: 'a;
+
+    // Splits on block boundaries, so each thread only ever looks at whole blocks of flags
+    fn par_iter<'a>(&'a self) -> Self::ParIter<'a> {
+        let slots: ParSlots<T> = ParSlots{ items: self.items.as_ptr() as *mut MaybeUninit<T>, flags: self.flags.as_ptr(), num_slots: self.items.len(), head: self.head };
+        return ParIter::new(slots, self.num_items)
+    }
+
+    fn par_iter_mut<'a>(&'a mut self) -> Self::ParIterMut<'a> {
+        let slots: ParSlots<T> = ParSlots{ items: self.items.as_mut_ptr(), flags: self.flags.as_ptr(), num_slots: self.items.len(), head: self.head };
+        return ParIterMut::new(slots, self.num_items)
+    }
+}
+
+impl <T: Clone, A: Allocator + Clone> NotSafe<T, A> {
+    pub fn new_in(alloc: A) -> Self {
+        return Self {
+            items: Vec::new_in(alloc.clone()),
+            num_items: 0,
+            watermarks: Watermarks::new(),
+            history: SlotHistories::new_in(alloc.clone()),
+
+            flags: Vec::new_in(alloc.clone()),
+            open_blocks: Vec::new_in(alloc.clone()),
+            nodes: Vec::new_in(alloc.clone()),
+            head: null_mut(),
+            tail: null_mut(),
+            alloc,
+        }
+    }
+
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        let num_blocks: usize = 
+            if capacity == 0 {
+                0                                
+            } 
+            else {
+                ((capacity-1)/FLAGS_PER_BLOCK)+1
+            };
+    
+        let mut items: Vec<MaybeUninit<T>, A> = Vec::with_capacity_in(num_blocks*FLAGS_PER_BLOCK, alloc.clone());
+        items.resize_with(num_blocks*FLAGS_PER_BLOCK, MaybeUninit::uninit);
+        let num_items: usize = 0;
+
+        let flags: Vec<FlagBlock, A> = vec![in alloc.clone(); 0; num_blocks];
+        let mut open_blocks: Vec<usize, A> = Vec::with_capacity_in(num_blocks, alloc.clone());
+        open_blocks.extend((0..num_blocks).rev());
+        let mut nodes: Vec<*mut Node, A> = Vec::with_capacity_in(num_blocks, alloc.clone());
+        nodes.extend((0..num_blocks).map(|block: usize| Node::new_boxed(block, &alloc)));
+        let head: *mut Node = null_mut();
+        let tail: *mut Node = null_mut();
+
+        return Self {
+            items,
+            num_items,
+            watermarks: Watermarks::new(),
+            history: SlotHistories::with_capacity_in(num_blocks*FLAGS_PER_BLOCK, alloc.clone()),
+
+            flags,
+            open_blocks,
+            nodes,
+            head,
+            tail,
+            alloc,
+        }
+    }
+
+    fn is_allocated(&self, id: usize) -> bool {
+        let block: usize = id / FLAGS_PER_BLOCK;
+        let local_bit: usize = id % FLAGS_PER_BLOCK;
+        return (self.flags[block] & (1 << local_bit)) != 0
+    }
+
     fn expand_if_needed(&mut self) {
         if !self.open_blocks.is_empty() {
             return
@@ -180,6 +353,7 @@ impl <T: Clone> NotSafe<T> {
         assert!(self.flags.iter().all(|block: &FlagBlock| *block == FULL_BLOCK), "{:?}", self.flags);
         assert!(self.nodes.len() == self.flags.len());
 
+        self.watermarks.grew();
         const GROWTH_FACTOR: usize = 2;
         let old_num_blocks: usize = self.flags.len();
         let new_num_blocks: usize;
@@ -192,58 +366,91 @@ impl <T: Clone> NotSafe<T> {
         };
         let new_num_items: usize = new_num_blocks * FLAGS_PER_BLOCK;
 
-        self.items.resize(new_num_items, None);
+        self.items.resize_with(new_num_items, MaybeUninit::uninit);
+        self.history.grow_to(new_num_items);
         self.flags.resize(new_num_blocks, EMPTY_BLOCK);
-        self.open_blocks.append( &mut (old_num_blocks..new_num_blocks).rev().collect() );
-        self.nodes.resize(new_num_blocks, null_mut());
+        self.open_blocks.extend( (old_num_blocks..new_num_blocks).rev() );
+        self.nodes.extend((old_num_blocks..new_num_blocks).map(|block: usize| Node::new_boxed(block, &self.alloc)));
         assert!(self.items.len() == self.flags.len()*FLAGS_PER_BLOCK);
     }
 }
 
-impl <T: Clone> Drop for NotSafe<T> {
+impl <T: Clone, A: Allocator> Drop for NotSafe<T, A> {
     fn drop(&mut self) {
-        let mut curr: *mut Node = self.head;
-        while curr != null_mut() {
-            let next: *mut Node = unsafe{ (*curr).next };
-            let _drop: Box<Node> = unsafe{ Box::from_raw(curr) };
-            curr = next;
+        let leaks: PendingLeakReport = find_leaks(
+            type_name::<Self>(),
+            self.num_items,
+            (0..self.items.len()).filter(|id: &usize| self.flags[id / FLAGS_PER_BLOCK] & (1 << (id % FLAGS_PER_BLOCK)) != 0),
+            |id: usize| self.history.allocated_at(id),
+        );
+
+        for block in 0..self.flags.len() {
+            let mut flags: FlagBlock = self.flags[block];
+            while flags != EMPTY_BLOCK {
+                let local_bit: usize = flags.trailing_zeros() as usize;
+                flags &= !(1 << local_bit);
+                unsafe { self.items[block*FLAGS_PER_BLOCK + local_bit].assume_init_drop() };
+            }
+        }
+
+        for node in self.nodes.iter() {
+            let _drop: Box<Node, &A> = unsafe{ Box::from_raw_in(*node, &self.alloc) };
             // _drop goes out of scope and is dropped
         }
+
+        leaks.report();
     }
 }
 
+// Takes the lowest set flag out of a block and returns its index
+fn take_lowest_flag(block: &mut FlagBlock) -> usize {
+    let local_bit: usize = block.trailing_zeros() as usize;
+    *block &= !(1 << local_bit);
+    return local_bit
+}
+
+fn take_highest_flag(block: &mut FlagBlock) -> usize {
+    let local_bit: usize = FLAGS_PER_BLOCK - 1 - block.leading_zeros() as usize;
+    *block &= !(1 << local_bit);
+    return local_bit
+}
+
+/*
+    Walks the linked list from the head with next_node and from the tail with
+    prev_node. The two would walk straight past each other, so an end only moves
+    on to another node while there are more items left than the other end is
+    holding. Otherwise it finishes off the other end's block.
+*/
 pub struct Iter<'a, T: Clone> {
-    items: &'a Vec<Option<T>>,
-    flags: &'a Vec<FlagBlock>,
+    items: &'a [MaybeUninit<T>],
+    flags: &'a [FlagBlock],
     next_node: *const Node,
     curr_flags: FlagBlock,
     curr_offset: usize, // self.curr_flags*FLAGS_PER_BLOCK
+    prev_node: *const Node,
+    back_flags: FlagBlock,
+    back_offset: usize,
+    num_items_left: usize,
 }
 
 impl <'a, T: Clone> Iter<'a, T> {
     fn new(
-        items: &'a Vec<Option<T>>, 
-        flags: &'a Vec<FlagBlock>, 
-        head: *const Node
+        items: &'a [MaybeUninit<T>], 
+        flags: &'a [FlagBlock], 
+        head: *const Node,
+        tail: *const Node,
+        num_items: usize,
     ) -> Self {
-        if head == null() {
-            return Self {
-                items,
-                flags,
-                next_node: null_mut(),
-                curr_flags: 0,
-                curr_offset: 0
-            }
-        }
-
-        unsafe {
-            return Self {
-                items,
-                flags,
-                next_node: (*head).next,
-                curr_flags: flags[(*head).block],
-                curr_offset: (*head).block * FLAGS_PER_BLOCK
-            }
+        return Self {
+            items,
+            flags,
+            next_node: head,
+            curr_flags: EMPTY_BLOCK,
+            curr_offset: 0,
+            prev_node: tail,
+            back_flags: EMPTY_BLOCK,
+            back_offset: 0,
+            num_items_left: num_items,
         }
     }
 }
@@ -252,33 +459,272 @@ impl <'a, T: Clone> Iterator for Iter<'a, T> {
     type Item = &'a T;
 
     fn next(&mut self) -> Option<Self::Item> {
-        unsafe {
-            if self.curr_flags == EMPTY_BLOCK {
-                if self.next_node == null() {
-                    return None
-                }
+        if self.num_items_left == 0 {
+            return None
+        }
+
+        let global_offset: usize;
+        if self.curr_flags == EMPTY_BLOCK {
+            let other_nodes_have_items: bool = self.num_items_left > self.back_flags.count_ones() as usize;
+            if !other_nodes_have_items {
+                global_offset = self.back_offset + take_lowest_flag(&mut self.back_flags);
+                self.num_items_left -= 1;
+                return Some(unsafe { self.items[global_offset].assume_init_ref() })
+            }
 
+            unsafe {
+                assert!(self.next_node != null());
                 let curr: *const Node = self.next_node;
                 self.next_node = (*curr).next;
                 self.curr_flags = self.flags[(*curr).block];
                 assert!(self.curr_flags != 0);
                 self.curr_offset = (*curr).block * FLAGS_PER_BLOCK;
             }
+        }
+
+        global_offset = self.curr_offset + take_lowest_flag(&mut self.curr_flags);
+        self.num_items_left -= 1;
+        return Some(unsafe { self.items[global_offset].assume_init_ref() })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        return (self.num_items_left, Some(self.num_items_left))
+    }
+}
+
+impl <'a, T: Clone> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.num_items_left == 0 {
+            return None
+        }
+
+        let global_offset: usize;
+        if self.back_flags == EMPTY_BLOCK {
+            let other_nodes_have_items: bool = self.num_items_left > self.curr_flags.count_ones() as usize;
+            if !other_nodes_have_items {
+                global_offset = self.curr_offset + take_highest_flag(&mut self.curr_flags);
+                self.num_items_left -= 1;
+                return Some(unsafe { self.items[global_offset].assume_init_ref() })
+            }
 
-            let local_offset: FlagBlock = self.curr_flags.trailing_zeros() as FlagBlock;
-            let global_offset: usize = self.curr_offset + local_offset as usize;
-            self.curr_flags &= !(1 << local_offset); // consume the flag
-            let item: &T = self.items[global_offset].as_ref().unwrap();
-            return Some(item)
+            unsafe {
+                assert!(self.prev_node != null());
+                let curr: *const Node = self.prev_node;
+                self.prev_node = (*curr).prev;
+                self.back_flags = self.flags[(*curr).block];
+                assert!(self.back_flags != 0);
+                self.back_offset = (*curr).block * FLAGS_PER_BLOCK;
+            }
         }
+
+        global_offset = self.back_offset + take_highest_flag(&mut self.back_flags);
+        self.num_items_left -= 1;
+        return Some(unsafe { self.items[global_offset].assume_init_ref() })
+    }
+}
+
+impl <'a, T: Clone> ExactSizeIterator for Iter<'a, T> {}
+
+// Walks every block's flags in order instead of the linked list, the same way Stacks' SortedIter does
+pub struct SortedIter<'a, T: Clone> {
+    items: &'a [MaybeUninit<T>],
+    flags: Enumerate<slice::Iter<'a, FlagBlock>>,
+    curr_flags: FlagBlock,
+    curr_offset: usize,
+    back_flags: FlagBlock,
+    back_offset: usize,
+    first_block: usize, // index of the block flags starts at
+    num_items_left: usize,
+}
+
+impl <'a, T: Clone> SortedIter<'a, T> {
+    fn new(items: &'a [MaybeUninit<T>], flags: &'a [FlagBlock], num_items: usize) -> Self {
+        return Self {
+            items,
+            flags: flags.iter().enumerate(),
+            first_block: 0,
+            curr_flags: EMPTY_BLOCK,
+            curr_offset: 0,
+            back_flags: EMPTY_BLOCK,
+            back_offset: 0,
+            num_items_left: num_items,
+        }
+    }
+
+    /*
+        Starts with the blocks at either end of the range already loaded, masked
+        down to the ids in range, and leaves the blocks between them to be walked
+        as usual. Blocks are counted as they're masked, so the iterator still
+        knows exactly how many items it has left.
+    */
+    fn in_range(items: &'a [MaybeUninit<T>], flags: &'a [FlagBlock], range: Range<usize>) -> Self {
+        let end: usize = range.end.min(items.len());
+        let start: usize = range.start.min(end);
+        if start == end {
+            return Self::new(items, &[], 0)
+        }
+
+        let first_block: usize = start / FLAGS_PER_BLOCK;
+        let last_block: usize = (end-1) / FLAGS_PER_BLOCK;
+        let mut curr_flags: FlagBlock = flags[first_block] & (FlagBlock::MAX << (start % FLAGS_PER_BLOCK));
+        let mut back_flags: FlagBlock = flags[last_block] & (FlagBlock::MAX >> (FLAGS_PER_BLOCK - 1 - (end-1) % FLAGS_PER_BLOCK));
+        let middle: &'a [FlagBlock];
+        if first_block == last_block {
+            curr_flags &= back_flags;
+            back_flags = EMPTY_BLOCK;
+            middle = &[];
+        }
+        else {
+            middle = &flags[first_block+1..last_block];
+        }
+
+        let num_items: usize = (curr_flags.count_ones() + back_flags.count_ones()) as usize
+            + middle.iter().map(|flags: &FlagBlock| flags.count_ones() as usize).sum::<usize>();
+        return Self {
+            items,
+            flags: middle.iter().enumerate(),
+            curr_flags,
+            curr_offset: first_block*FLAGS_PER_BLOCK,
+            back_flags,
+            back_offset: last_block*FLAGS_PER_BLOCK,
+            first_block: first_block+1,
+            num_items_left: num_items,
+        }
+    }
+}
+
+impl <'a, T: Clone> Iterator for SortedIter<'a, T> {
+    type Item = (usize, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let id: usize;
+        while self.curr_flags == EMPTY_BLOCK {
+            match self.flags.next() {
+                Some((block, flags)) => {
+                    self.curr_flags = *flags;
+                    self.curr_offset = (self.first_block + block)*FLAGS_PER_BLOCK;
+                },
+
+                None => {
+                    if self.back_flags == EMPTY_BLOCK {
+                        return None
+                    }
+                    id = self.back_offset + take_lowest_flag(&mut self.back_flags);
+                    self.num_items_left -= 1;
+                    return Some((id, unsafe { self.items[id].assume_init_ref() }))
+                },
+            }
+        }
+
+        id = self.curr_offset + take_lowest_flag(&mut self.curr_flags);
+        self.num_items_left -= 1;
+        return Some((id, unsafe { self.items[id].assume_init_ref() }))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        return (self.num_items_left, Some(self.num_items_left))
+    }
+}
+
+impl <'a, T: Clone> DoubleEndedIterator for SortedIter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let id: usize;
+        while self.back_flags == EMPTY_BLOCK {
+            match self.flags.next_back() {
+                Some((block, flags)) => {
+                    self.back_flags = *flags;
+                    self.back_offset = (self.first_block + block)*FLAGS_PER_BLOCK;
+                },
+
+                None => {
+                    if self.curr_flags == EMPTY_BLOCK {
+                        return None
+                    }
+                    id = self.curr_offset + take_highest_flag(&mut self.curr_flags);
+                    self.num_items_left -= 1;
+                    return Some((id, unsafe { self.items[id].assume_init_ref() }))
+                },
+            }
+        }
+
+        id = self.back_offset + take_highest_flag(&mut self.back_flags);
+        self.num_items_left -= 1;
+        return Some((id, unsafe { self.items[id].assume_init_ref() }))
+    }
+}
+
+impl <'a, T: Clone> ExactSizeIterator for SortedIter<'a, T> {}
+
+#[cfg(feature = "rayon")]
+pub struct ParSlots<T> {
+    items: *mut MaybeUninit<T>,
+    flags: *const FlagBlock,
+    num_slots: usize,
+    head: *const Node,
+}
+
+#[cfg(feature = "rayon")]
+impl <T> Clone for ParSlots<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl <T> Copy for ParSlots<T> {}
+
+#[cfg(feature = "rayon")]
+unsafe impl <T> Slots<T> for ParSlots<T> {
+    const SPLIT_ALIGNMENT: usize = FLAGS_PER_BLOCK;
+
+    fn num_slots(&self) -> usize {
+        return self.num_slots
+    }
+
+    fn is_live(&self, id: usize) -> bool {
+        let flags: FlagBlock = unsafe { *self.flags.add(id / FLAGS_PER_BLOCK) };
+        return (flags & (1 << (id % FLAGS_PER_BLOCK))) != 0
+    }
+
+    fn item(&self, id: usize) -> *const T {
+        return unsafe { (*self.items.add(id)).as_ptr() }
+    }
+
+    fn item_mut(&self, id: usize) -> *mut T {
+        return unsafe { (*self.items.add(id)).as_mut_ptr() }
+    }
+
+    // Only looks at the blocks with items in them, by walking the linked list
+    fn split_point(&self, start: usize, end: usize, num_items_wanted: usize) -> (usize, usize) {
+        let head: *const Node = self.head;
+        return split_point_by_blocks(
+            || {
+                let mut node: *const Node = head;
+                return core::iter::from_fn(move || {
+                    if node == null() {
+                        return None
+                    }
+                    let block: usize = unsafe { (*node).block };
+                    node = unsafe { (*node).next };
+                    return Some(block)
+                })
+            },
+            |block: usize| unsafe { *self.flags.add(block) }.count_ones() as usize,
+            FLAGS_PER_BLOCK, start, end, num_items_wanted,
+        )
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::NotSafe;
+    use super::{NotSafe, Node, FLAGS_PER_BLOCK};
     use crate::testing;
+    use crate::InvariantError;
     use crate::testing::Item; 
+    use crate::testing::Growth;
+    use crate::testing::CountingAlloc;
+    use crate::Pool as _;
+    use std::rc::Rc;
 
     type Pool = NotSafe<Item>;
 
@@ -305,12 +751,78 @@ mod tests {
     }
 
     #[test]
+    #[cfg_attr(miri, ignore)] // far too slow to interpret
     fn fuzz_many_pools_few_mutations() {
         testing::fuzz_many_pools_few_mutations::<Pool>();
     }
 
     #[test]
+    #[cfg_attr(miri, ignore)] // far too slow to interpret
     fn fuzz_few_pools_many_mutations() {
         testing::fuzz_few_pools_many_mutations::<Pool>();
     }
+
+    #[test]
+    fn test_sorted_iter() {
+        testing::test_sorted_iter::<Pool>();
+    }
+
+    #[test]
+    fn test_iterators_are_double_ended_and_exact_size() {
+        testing::test_iterators_are_double_ended_and_exact_size::<Pool>();
+    }
+
+    #[test]
+    fn test_iter_range() {
+        testing::test_iter_range::<Pool>();
+    }
+
+    #[test]
+    fn test_stats() {
+        testing::test_stats::<Pool>(Growth::Doubling{ first_capacity: FLAGS_PER_BLOCK }, true);
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn test_par_iter() {
+        testing::test_par_iter::<Pool>();
+    }
+
+    #[test]
+    fn test_all_memory_goes_through_allocator() {
+        testing::test_all_memory_goes_through_allocator::<NotSafe<Item, CountingAlloc>>();
+    }
+
+    #[test]
+    fn test_heap_size_matches_allocator() {
+        testing::test_heap_size_matches_allocator::<NotSafe<Item, CountingAlloc>>();
+    }
+
+    #[test]
+    fn test_items_are_dropped_exactly_once() {
+        testing::test_items_are_dropped_exactly_once::<NotSafe<Rc<()>>>();
+    }
+
+    #[test]
+    fn test_slots_dont_store_a_discriminant() {
+        testing::test_slots_dont_store_a_discriminant::<NotSafe<u8, CountingAlloc>, NotSafe<[u64; 3], CountingAlloc>>();
+    }
+
+    #[test]
+    fn test_check_invariants_finds_broken_linked_list() {
+        let mut pool: Pool = Pool::with_capacity(100);
+        for item in 0..100 {
+            pool.allocate(item);
+        }
+        assert!(pool.check_invariants().is_ok());
+
+        unsafe {
+            let second: *mut Node = (*pool.head).next;
+            let third: *mut Node = (*second).next;
+            (*third).prev = pool.head;
+            assert!(pool.check_invariants() == Err(InvariantError::LinkedListBroken{ block: (*third).block }));
+            (*third).prev = second;
+        }
+        assert!(pool.check_invariants().is_ok());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,169 @@
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use core::ops::Range;
+
+use super::{Pool, OrderedPool, PoolStats, HeapUsage, InvariantError};
+
+/*
+    Hooks for keeping something outside the pool, like a spatial index or a
+    lookup by name, in step with which items are in it. on_allocate() sees the
+    item once it's in the pool, on_deallocate() sees it just before it's
+    dropped, and on_grow() is called whenever an allocation leaves the pool
+    with a bigger capacity than it had before. Reference's capacity is just its
+    length, so for it that's every allocation.
+
+    Every hook does nothing by default, so an observer only has to implement
+    the ones it cares about.
+*/
+pub trait PoolObserver<T> {
+    fn on_allocate(&mut self, _id: usize, _item: &T) {}
+    fn on_deallocate(&mut self, _id: usize, _item: &T) {}
+    fn on_grow(&mut self, _old_capacity: usize, _new_capacity: usize) {}
+}
+
+/*
+    Wraps any pool so that every allocation and deallocation goes past an
+    observer, including the ones made by allocate_many(), retain() and clear().
+    Since the pool can only be changed through the wrapper, the observer can't
+    miss anything.
+*/
+pub struct Observed<T, P: Pool<T>, O: PoolObserver<T>> {
+    pool: P,
+    observer: O,
+    _items: PhantomData<T>,
+}
+
+impl <T, P: Pool<T>, O: PoolObserver<T>> Observed<T, P, O> {
+    pub fn new(observer: O) -> Self {
+        return Self::with_capacity(0, observer)
+    }
+
+    pub fn with_capacity(num_items: usize, observer: O) -> Self {
+        return Self {
+            pool: P::with_capacity(num_items),
+            observer,
+            _items: PhantomData,
+        }
+    }
+
+    pub fn observer(&self) -> &O {
+        return &self.observer
+    }
+
+    pub fn observer_mut(&mut self) -> &mut O {
+        return &mut self.observer
+    }
+
+    pub fn len(&self) -> usize {
+        return self.pool.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        return self.pool.capacity()
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        return self.pool.stats()
+    }
+
+    pub fn heap_size_bytes(&self) -> HeapUsage {
+        return self.pool.heap_size_bytes()
+    }
+
+    pub fn heap_size_bytes_with<F: FnMut(&T) -> usize>(&self, item_heap_size_bytes: F) -> HeapUsage {
+        return self.pool.heap_size_bytes_with(item_heap_size_bytes)
+    }
+
+    pub fn check_invariants(&self) -> Result<(), InvariantError> {
+        return self.pool.check_invariants()
+    }
+
+    #[cfg_attr(feature = "debug-checks", track_caller)]
+    pub fn get(&self, id: usize) -> &T {
+        return self.pool.get(id)
+    }
+
+    #[cfg_attr(feature = "debug-checks", track_caller)]
+    pub fn get_mut(&mut self, id: usize) -> &mut T {
+        return self.pool.get_mut(id)
+    }
+
+    #[cfg_attr(feature = "debug-checks", track_caller)]
+    pub fn allocate(&mut self, item: T) -> usize {
+        let old_capacity: usize = self.pool.capacity();
+        let id: usize = self.pool.allocate(item);
+        let new_capacity: usize = self.pool.capacity();
+        if new_capacity > old_capacity {
+            self.observer.on_grow(old_capacity, new_capacity);
+        }
+        self.observer.on_allocate(id, self.pool.get(id));
+        return id
+    }
+
+    #[cfg_attr(feature = "debug-checks", track_caller)]
+    pub fn deallocate(&mut self, id: usize) {
+        self.observer.on_deallocate(id, self.pool.get(id));
+        self.pool.deallocate(id);
+    }
+
+    // Returns the ids in the same order as the items
+    pub fn allocate_many<I: IntoIterator<Item=T>>(&mut self, items: I) -> Vec<usize> {
+        return items.into_iter().map(|item: T| self.allocate(item)).collect()
+    }
+
+    pub fn retain<F: FnMut(usize, &T) -> bool>(&mut self, mut keep: F) where P: OrderedPool<T> {
+        let ids: Vec<usize> = self.pool.sorted_iter()
+            .filter(|(id, item): &(usize, &T)| !keep(*id, item))
+            .map(|(id, _): (usize, &T)| id)
+            .collect();
+        for id in ids {
+            self.deallocate(id);
+        }
+    }
+
+    pub fn clear(&mut self) where P: OrderedPool<T> {
+        self.retain(|_, _| false);
+    }
+
+    pub fn iter<'a>(&'a self) -> P::Iter<'a> where T: 'a {
+        return self.pool.iter()
+    }
+
+    pub fn sorted_iter<'a>(&'a self) -> P::SortedIter<'a> where P: OrderedPool<T>, T: 'a {
+        return self.pool.sorted_iter()
+    }
+
+    pub fn iter_range<'a>(&'a self, range: Range<usize>) -> P::RangeIter<'a> where P: OrderedPool<T>, T: 'a {
+        return self.pool.iter_range(range)
+    }
+
+    pub fn into_parts(self) -> (P, O) {
+        return (self.pool, self.observer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Observed, PoolObserver};
+    use crate::testing;
+    use crate::testing::Item;
+    use crate::{FreeList};
+
+    testing::test_every_backend!(test_observer_hooks);
+
+    #[test]
+    fn test_hooks_are_optional() {
+        struct CountAllocations(usize);
+        impl PoolObserver<Item> for CountAllocations {
+            fn on_allocate(&mut self, _id: usize, _item: &Item) {
+                self.0 += 1;
+            }
+        }
+
+        let mut pool: Observed<Item, FreeList<Item>, CountAllocations> = Observed::new(CountAllocations(0));
+        pool.allocate_many(0..10);
+        pool.clear();
+        assert!(pool.observer().0 == 10);
+        assert!(pool.len() == 0);
+    }
+}
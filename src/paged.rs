@@ -0,0 +1,662 @@
+use core::any::type_name;
+use core::iter::Enumerate;
+use core::slice;
+use core::ops::Range;
+use core::pin::Pin;
+use core::mem::size_of;
+use allocator_api2::alloc::{Allocator, Global};
+use allocator_api2::boxed::Box;
+use allocator_api2::vec::Vec;
+use super::{Pool, OrderedPool, PreallocatedPool};
+use super::stats::{PoolStats, Watermarks};
+use super::debug_checks::SlotHistories;
+use super::leaks::find_leaks;
+use super::heap_size::{HeapUsage, vec_heap_size_bytes};
+use super::InvariantError;
+use super::invariants::check_free_list;
+#[cfg(feature = "rayon")]
+use super::parallel::{ParallelPool, ParIter, ParIterMut, Slots};
+
+const ITEMS_PER_PAGE: usize = 64;
+
+enum Slot<T> {
+    Item(T),
+    Free{next_free_slot: Option<usize>},
+}
+
+/*
+    Items are stored in fixed-size pages that are boxed once and never reallocated.
+    Growing the pool pushes another page instead of resizing the existing ones, so
+    an item stays at the same address from allocate() until deallocate(), which
+    drops it in place. An id is page*ITEMS_PER_PAGE + slot, so lookups are still
+    O(1), and free slots are chained together like they are in a FreeList.
+
+    Since items never move, they can be pinned. That's only sound if nothing can
+    move them out from under the pin, so get_mut() (and with it, the Pool impl)
+    is only available when T: Unpin. Everything else is available for any T.
+*/
+pub struct Paged<T, A: Allocator = Global> {
+    pages: Vec<Box<[Slot<T>], A>, A>,
+    next_free_slot: Option<usize>,
+    num_items: usize,
+    watermarks: Watermarks, // for stats(), since neither can be worked out afterwards
+    history: SlotHistories<A>, // where each slot was last allocated and freed from, with debug-checks
+}
+
+impl <T, A: Allocator + Clone + Default> Paged<T, A> {
+    pub fn new() -> Self {
+        return Self::new_in(A::default())
+    }
+
+    pub fn with_capacity(num_items: usize) -> Self {
+        return Self::with_capacity_in(num_items, A::default())
+    }
+}
+
+impl <T, A: Allocator + Clone + Default> Default for Paged<T, A> {
+    fn default() -> Self {
+        return Self::new()
+    }
+}
+
+impl <T, A: Allocator + Clone> Paged<T, A> {
+    pub fn new_in(alloc: A) -> Self {
+        return Self {
+            pages: Vec::new_in(alloc.clone()),
+            next_free_slot: None,
+            num_items: 0,
+            watermarks: Watermarks::new(),
+            history: SlotHistories::new_in(alloc),
+        }
+    }
+
+    pub fn with_capacity_in(num_items: usize, alloc: A) -> Self {
+        let mut pool: Self = Self::new_in(alloc);
+        let num_pages: usize =
+            if num_items == 0 {
+                0
+            }
+            else {
+                ((num_items-1)/ITEMS_PER_PAGE)+1
+            };
+        pool.pages.reserve_exact(num_pages);
+        pool.history.grow_to(num_pages*ITEMS_PER_PAGE);
+        for _ in 0..num_pages {
+            pool.add_page();
+        }
+        return pool
+    }
+
+    pub fn len(&self) -> usize {
+        return self.num_items
+    }
+
+    pub fn capacity(&self) -> usize {
+        return self.pages.len() * ITEMS_PER_PAGE
+    }
+
+    pub fn heap_size_bytes(&self) -> HeapUsage {
+        return self.heap_size_bytes_with(|_: &T| 0)
+    }
+
+    pub fn heap_size_bytes_with<F: FnMut(&T) -> usize>(&self, item_heap_size_bytes: F) -> HeapUsage {
+        return HeapUsage::new()
+            .with("page_table", vec_heap_size_bytes(&self.pages))
+            .with("pages", self.pages.len() * ITEMS_PER_PAGE * size_of::<Slot<T>>())
+            .with("history", self.history.heap_size_bytes())
+            .with("item_heap", self.iter().map(item_heap_size_bytes).sum())
+    }
+
+    pub fn check_invariants(&self) -> Result<(), InvariantError> {
+        let num_allocated_slots: usize = self.iter().count();
+        if num_allocated_slots != self.num_items {
+            return Err(InvariantError::NumItemsDoesntMatchSlots{ num_items: self.num_items, num_allocated_slots })
+        }
+
+        return check_free_list(self.next_free_slot, self.capacity(), self.num_items, |slot: usize| {
+            match &self.pages[slot / ITEMS_PER_PAGE][slot % ITEMS_PER_PAGE] {
+                Slot::Item(_) => return None,
+                Slot::Free{ next_free_slot } => return Some(*next_free_slot),
+            }
+        })
+    }
+
+    #[cfg_attr(feature = "debug-checks", track_caller)]
+    pub fn get(&self, id: usize) -> &T {
+        self.history.check_access(id);
+        match &self.pages[id / ITEMS_PER_PAGE][id % ITEMS_PER_PAGE] {
+            Slot::Item(item) => return item,
+            Slot::Free{..} => panic!(),
+        }
+    }
+
+    #[cfg_attr(feature = "debug-checks", track_caller)]
+    pub fn get_pinned(&self, id: usize) -> Pin<&T> {
+        // Safe because items are never moved while they're allocated, and get_mut() only exists for Unpin items
+        return unsafe { Pin::new_unchecked(self.get(id)) }
+    }
+
+    #[cfg_attr(feature = "debug-checks", track_caller)]
+    pub fn get_pinned_mut(&mut self, id: usize) -> Pin<&mut T> {
+        self.history.check_access(id);
+        match &mut self.pages[id / ITEMS_PER_PAGE][id % ITEMS_PER_PAGE] {
+            Slot::Item(item) => return unsafe { Pin::new_unchecked(item) },
+            Slot::Free{..} => panic!(),
+        }
+    }
+
+    #[cfg_attr(feature = "debug-checks", track_caller)]
+    pub fn allocate(&mut self, item: T) -> usize {
+        if self.next_free_slot.is_none() {
+            self.add_page();
+            self.watermarks.grew();
+        }
+
+        let id: usize = self.next_free_slot.unwrap();
+        let slot: &mut Slot<T> = &mut self.pages[id / ITEMS_PER_PAGE][id % ITEMS_PER_PAGE];
+        match slot {
+            Slot::Free{ next_free_slot } => {
+                self.next_free_slot = *next_free_slot;
+            },
+
+            Slot::Item(_) => panic!(),
+        }
+        *slot = Slot::Item(item);
+        self.history.allocated(id);
+        self.num_items += 1;
+        self.watermarks.allocated(self.num_items);
+        return id
+    }
+
+    #[cfg_attr(feature = "debug-checks", track_caller)]
+    pub fn deallocate(&mut self, id: usize) {
+        self.history.check_deallocate(id);
+        let slot: &mut Slot<T> = &mut self.pages[id / ITEMS_PER_PAGE][id % ITEMS_PER_PAGE];
+        if let Slot::Free{..} = slot {
+            panic!();
+        }
+        *slot = Slot::Free{ next_free_slot: self.next_free_slot }; // drops the item in place
+        self.next_free_slot = Some(id);
+        self.history.freed(id);
+        self.num_items -= 1;
+    }
+
+    pub fn iter(&self) -> Iter<'_, T, A> {
+        return Iter::new(SortedIter::new(self.pages.iter(), self.num_items))
+    }
+
+    fn add_page(&mut self) {
+        let first_id: usize = self.pages.len() * ITEMS_PER_PAGE;
+        let next_free_slot_after_page: Option<usize> = self.next_free_slot;
+        let mut page: Vec<Slot<T>, A> = Vec::with_capacity_in(ITEMS_PER_PAGE, self.pages.allocator().clone());
+        page.extend((0..ITEMS_PER_PAGE)
+            .map(|slot: usize| {
+                if slot == ITEMS_PER_PAGE-1 {
+                    return Slot::Free{ next_free_slot: next_free_slot_after_page }
+                }
+                return Slot::Free{ next_free_slot: Some(first_id + slot + 1) }
+            }));
+        self.pages.push(page.into_boxed_slice());
+        self.history.grow_to(self.pages.len()*ITEMS_PER_PAGE);
+        self.next_free_slot = Some(first_id);
+    }
+}
+
+impl <T: Unpin, A: Allocator + Clone + Default> Pool<T> for Paged<T, A> {
+    type Iter<'a> = Iter<'a, T, A> where Self: 'a, T: 'a;
+
+    fn new() -> Self {
+        return Paged::new()
+    }
+
+    fn with_capacity(num_items: usize) -> Self {
+        return Paged::with_capacity(num_items)
+    }
+
+    fn len(&self) -> usize {
+        return Paged::len(self)
+    }
+
+    fn capacity(&self) -> usize {
+        return Paged::capacity(self)
+    }
+
+    fn heap_size_bytes_with<F: FnMut(&T) -> usize>(&self, item_heap_size_bytes: F) -> HeapUsage {
+        return Paged::heap_size_bytes_with(self, item_heap_size_bytes)
+    }
+
+    fn stats(&self) -> PoolStats {
+        let num_partial_pages: usize = self.pages.iter()
+            .map(|page: &Box<[Slot<T>], A>| page.iter().filter(|slot: &&Slot<T>| matches!(slot, Slot::Item(_))).count())
+            .filter(|num_items_in_page: &usize| *num_items_in_page != 0 && *num_items_in_page != ITEMS_PER_PAGE)
+            .count();
+        return PoolStats::new(self.num_items, Paged::capacity(self), self.watermarks, ITEMS_PER_PAGE, num_partial_pages, self.sorted_iter().next_back().map(|(id, _): (usize, &T)| id))
+    }
+
+    fn check_invariants(&self) -> Result<(), InvariantError> {
+        return Paged::check_invariants(self)
+    }
+
+    fn get(&self, id: usize) -> &T {
+        return Paged::get(self, id)
+    }
+
+    fn get_mut(&mut self, id: usize) -> &mut T {
+        return self.get_pinned_mut(id).get_mut()
+    }
+
+    fn allocate(&mut self, item: T) -> usize {
+        return Paged::allocate(self, item)
+    }
+
+    fn deallocate(&mut self, id: usize) {
+        Paged::deallocate(self, id);
+    }
+
+    fn iter<'a>(&'a self) -> Iter<'a, T, A> {
+        return Paged::iter(self)
+    }
+}
+
+impl <T: Unpin, A: Allocator + Clone + Default> PreallocatedPool<T> for Paged<T, A> {
+    type Alloc = A;
+
+    fn allocator(&self) -> &A {
+        return self.pages.allocator()
+    }
+}
+
+impl <T: Unpin, A: Allocator + Clone + Default> OrderedPool<T> for Paged<T, A> {
+    type SortedIter<'a> = SortedIter<'a, T, A> where Self: 'a, T: 'a;
+    type RangeIter<'a> = RangeIter<'a, T, A> where Self: 'a, T: 'a;
+
+    fn sorted_iter<'a>(&'a self) -> SortedIter<'a, T, A> {
+        return SortedIter::new(self.pages.iter(), self.num_items)
+    }
+
+    fn iter_range<'a>(&'a self, range: Range<usize>) -> RangeIter<'a, T, A> {
+        let end_id: usize = range.end.min(self.capacity());
+        return RangeIter {
+            pages: &self.pages,
+            next_id: range.start.min(end_id),
+            end_id,
+        }
+    }
+}
+
+// Items are already stored in id order, so this is just SortedIter without the ids
+#[cfg(feature = "rayon")]
+impl <T: Unpin + Send + Sync, A: Allocator + Clone + Default> ParallelPool<T> for Paged<T, A> {
+    type ParIter<'a> = ParIter<'a, T, ParSlots<T, A>> where Self: 'a, T: 'a;
+    type ParIterMut<'a> = ParIterMut<'a, T, ParSlots<T, A>> where Self: 'a, T: 'a;
+
+    fn par_iter<'a>(&'a self) -> Self::ParIter<'a> {
+        let slots: ParSlots<T, A> = ParSlots{ pages: self.pages.as_ptr() as *mut Box<[Slot<T>], A>, num_slots: self.capacity() };
+        return ParIter::new(slots, self.num_items)
+    }
+
+    fn par_iter_mut<'a>(&'a mut self) -> Self::ParIterMut<'a> {
+        let slots: ParSlots<T, A> = ParSlots{ pages: self.pages.as_mut_ptr(), num_slots: self.capacity() };
+        return ParIterMut::new(slots, self.num_items)
+    }
+}
+
+impl <T, A: Allocator> Drop for Paged<T, A> {
+    fn drop(&mut self) {
+        find_leaks(
+            type_name::<Self>(),
+            self.num_items,
+            self.pages.iter().flat_map(|page: &Box<[Slot<T>], A>| page.iter())
+                .enumerate()
+                .filter(|(_, slot): &(usize, &Slot<T>)| matches!(slot, Slot::Item(_)))
+                .map(|(id, _): (usize, &Slot<T>)| id),
+            |id: usize| self.history.allocated_at(id),
+        ).report();
+    }
+}
+
+pub struct Iter<'a, T, A: Allocator = Global> {
+    inner: SortedIter<'a, T, A>,
+}
+
+impl <'a, T, A: Allocator> Iter<'a, T, A> {
+    fn new(inner: SortedIter<'a, T, A>) -> Self {
+        return Self { inner }
+    }
+}
+
+impl <'a, T, A: Allocator> Iterator for Iter<'a, T, A> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.inner.next() {
+            Some((_, item)) => return Some(item),
+            None => return None,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        return self.inner.size_hint()
+    }
+}
+
+impl <'a, T, A: Allocator> DoubleEndedIterator for Iter<'a, T, A> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        match self.inner.next_back() {
+            Some((_, item)) => return Some(item),
+            None => return None,
+        }
+    }
+}
+
+impl <'a, T, A: Allocator> ExactSizeIterator for Iter<'a, T, A> {}
+
+type PageIter<'a, T> = Enumerate<slice::Iter<'a, Slot<T>>>;
+
+/*
+    Walks pages from both ends. Once the front runs out of untouched pages it
+    carries on through the page the back is partway through, and vice versa.
+*/
+pub struct SortedIter<'a, T, A: Allocator = Global> {
+    pages: Enumerate<slice::Iter<'a, Box<[Slot<T>], A>>>,
+    front: PageIter<'a, T>,
+    front_first_id: usize, // id of the first slot in the front page
+    back: PageIter<'a, T>,
+    back_first_id: usize,
+    num_items_left: usize,
+}
+
+impl <'a, T, A: Allocator> SortedIter<'a, T, A> {
+    fn new(pages: slice::Iter<'a, Box<[Slot<T>], A>>, num_items: usize) -> Self {
+        return Self {
+            pages: pages.enumerate(),
+            front: [].iter().enumerate(),
+            front_first_id: 0,
+            back: [].iter().enumerate(),
+            back_first_id: 0,
+            num_items_left: num_items,
+        }
+    }
+
+    fn next_in_page(page: &mut PageIter<'a, T>, first_id: usize) -> Option<(usize, &'a T)> {
+        for (slot, contents) in page {
+            if let Slot::Item(item) = contents {
+                return Some((first_id + slot, item))
+            }
+        }
+        return None
+    }
+
+    fn next_back_in_page(page: &mut PageIter<'a, T>, first_id: usize) -> Option<(usize, &'a T)> {
+        for (slot, contents) in page.rev() {
+            if let Slot::Item(item) = contents {
+                return Some((first_id + slot, item))
+            }
+        }
+        return None
+    }
+}
+
+impl <'a, T, A: Allocator> Iterator for SortedIter<'a, T, A> {
+    type Item = (usize, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(next) = Self::next_in_page(&mut self.front, self.front_first_id) {
+                self.num_items_left -= 1;
+                return Some(next)
+            }
+
+            match self.pages.next() {
+                Some((page, slots)) => {
+                    self.front = slots.iter().enumerate();
+                    self.front_first_id = page*ITEMS_PER_PAGE;
+                },
+
+                None => {
+                    let next: (usize, &'a T) = Self::next_in_page(&mut self.back, self.back_first_id)?;
+                    self.num_items_left -= 1;
+                    return Some(next)
+                },
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        return (self.num_items_left, Some(self.num_items_left))
+    }
+}
+
+impl <'a, T, A: Allocator> DoubleEndedIterator for SortedIter<'a, T, A> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(prev) = Self::next_back_in_page(&mut self.back, self.back_first_id) {
+                self.num_items_left -= 1;
+                return Some(prev)
+            }
+
+            match self.pages.next_back() {
+                Some((page, slots)) => {
+                    self.back = slots.iter().enumerate();
+                    self.back_first_id = page*ITEMS_PER_PAGE;
+                },
+
+                None => {
+                    let prev: (usize, &'a T) = Self::next_back_in_page(&mut self.front, self.front_first_id)?;
+                    self.num_items_left -= 1;
+                    return Some(prev)
+                },
+            }
+        }
+    }
+}
+
+impl <'a, T, A: Allocator> ExactSizeIterator for SortedIter<'a, T, A> {}
+
+pub struct RangeIter<'a, T, A: Allocator = Global> {
+    pages: &'a [Box<[Slot<T>], A>],
+    next_id: usize,
+    end_id: usize, // one past the last id that hasn't been looked at from the back
+}
+
+impl <'a, T, A: Allocator> RangeIter<'a, T, A> {
+    fn slot(&self, id: usize) -> &'a Slot<T> {
+        return &self.pages[id / ITEMS_PER_PAGE][id % ITEMS_PER_PAGE]
+    }
+}
+
+impl <'a, T, A: Allocator> Iterator for RangeIter<'a, T, A> {
+    type Item = (usize, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.next_id < self.end_id {
+            let id: usize = self.next_id;
+            self.next_id += 1;
+            if let Slot::Item(item) = self.slot(id) {
+                return Some((id, item))
+            }
+        }
+        return None
+    }
+}
+
+impl <'a, T, A: Allocator> DoubleEndedIterator for RangeIter<'a, T, A> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        while self.next_id < self.end_id {
+            self.end_id -= 1;
+            if let Slot::Item(item) = self.slot(self.end_id) {
+                return Some((self.end_id, item))
+            }
+        }
+        return None
+    }
+}
+
+#[cfg(feature = "rayon")]
+pub struct ParSlots<T, A: Allocator> {
+    pages: *mut Box<[Slot<T>], A>,
+    num_slots: usize,
+}
+
+#[cfg(feature = "rayon")]
+impl <T, A: Allocator> Clone for ParSlots<T, A> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl <T, A: Allocator> Copy for ParSlots<T, A> {}
+
+#[cfg(feature = "rayon")]
+unsafe impl <T, A: Allocator> Slots<T> for ParSlots<T, A> {
+    // Each thread gets whole pages, so two threads never borrow the same page's slice
+    const SPLIT_ALIGNMENT: usize = ITEMS_PER_PAGE;
+
+    fn num_slots(&self) -> usize {
+        return self.num_slots
+    }
+
+    fn is_live(&self, id: usize) -> bool {
+        let page: &[Slot<T>] = unsafe { &*self.pages.add(id / ITEMS_PER_PAGE) };
+        return matches!(page[id % ITEMS_PER_PAGE], Slot::Item(_))
+    }
+
+    fn item(&self, id: usize) -> *const T {
+        let page: &[Slot<T>] = unsafe { &*self.pages.add(id / ITEMS_PER_PAGE) };
+        match &page[id % ITEMS_PER_PAGE] {
+            Slot::Item(item) => return item,
+            Slot::Free{..} => panic!(),
+        }
+    }
+
+    fn item_mut(&self, id: usize) -> *mut T {
+        let page: &mut [Slot<T>] = unsafe { &mut *self.pages.add(id / ITEMS_PER_PAGE) };
+        match &mut page[id % ITEMS_PER_PAGE] {
+            Slot::Item(item) => return item,
+            Slot::Free{..} => panic!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Paged, ITEMS_PER_PAGE};
+    use crate::testing;
+    use crate::testing::Item;
+    use crate::testing::Growth;
+    use crate::testing::CountingAlloc;
+    use core::marker::PhantomPinned;
+    use core::ptr;
+    use alloc::vec::Vec;
+
+    type Pool = Paged<Item>;
+
+    #[test]
+    #[should_panic]
+    fn test_invalid_get_to_empty_pool() {
+        testing::test_invalid_get_to_empty_pool::<Pool>();
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_invalid_get_to_nonempty_pool() {
+        testing::test_invalid_get_to_nonempty_pool::<Pool>();
+    }
+
+    #[test]
+    fn test_one_item() {
+        testing::test_one_item::<Pool>();
+    }
+
+    #[test]
+    fn test_many_items() {
+        testing::test_many_items::<Pool>();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // far too slow to interpret
+    fn fuzz_many_pools_few_mutations() {
+        testing::fuzz_many_pools_few_mutations::<Pool>();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // far too slow to interpret
+    fn fuzz_few_pools_many_mutations() {
+        testing::fuzz_few_pools_many_mutations::<Pool>();
+    }
+
+    #[test]
+    fn test_sorted_iter() {
+        testing::test_sorted_iter::<Pool>();
+    }
+
+    #[test]
+    fn test_iterators_are_double_ended_and_exact_size() {
+        testing::test_iterators_are_double_ended_and_exact_size::<Pool>();
+    }
+
+    #[test]
+    fn test_iter_range() {
+        testing::test_iter_range::<Pool>();
+    }
+
+    #[test]
+    fn test_stats() {
+        testing::test_stats::<Pool>(Growth::ByPage{ page_size: ITEMS_PER_PAGE }, true);
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn test_par_iter() {
+        testing::test_par_iter::<Pool>();
+    }
+
+    #[test]
+    fn test_all_memory_goes_through_allocator() {
+        testing::test_all_memory_goes_through_allocator::<Paged<Item, CountingAlloc>>();
+    }
+
+    #[test]
+    fn test_heap_size_matches_allocator() {
+        testing::test_heap_size_matches_allocator::<Paged<Item, CountingAlloc>>();
+    }
+
+    #[test]
+    fn test_items_dont_move_when_pool_grows() {
+        let mut pool: Pool = Paged::new();
+        let mut ids_and_addresses: Vec<(usize, *const Item)> = Vec::new();
+        for i in 0..10_000 {
+            let id: usize = pool.allocate(i);
+            ids_and_addresses.push( (id, pool.get(id) as *const Item) );
+        }
+
+        for (id, address) in ids_and_addresses {
+            assert!(ptr::eq(pool.get(id), address));
+            assert!(ptr::eq(&*pool.get_pinned(id), address));
+        }
+    }
+
+    #[test]
+    fn test_pinned_items_that_arent_unpin() {
+        struct NotUnpin {
+            value: usize,
+            _pinned: PhantomPinned,
+        }
+
+        let mut pool: Paged<NotUnpin> = Paged::new();
+        let id: usize = pool.allocate( NotUnpin{ value: 3, _pinned: PhantomPinned } );
+        let address: *const NotUnpin = &*pool.get_pinned(id);
+        for value in 0..1000 {
+            pool.allocate( NotUnpin{ value, _pinned: PhantomPinned } );
+        }
+
+        unsafe {
+            pool.get_pinned_mut(id).get_unchecked_mut().value = 4;
+        }
+        assert!(ptr::eq(&*pool.get_pinned(id), address));
+        assert!(pool.get(id).value == 4);
+        pool.deallocate(id);
+        assert!(pool.len() == 1000);
+    }
+}
@@ -0,0 +1,247 @@
+use core::marker::PhantomData;
+use rayon::iter::ParallelIterator;
+use rayon::iter::plumbing::{bridge_unindexed, Folder, UnindexedConsumer, UnindexedProducer};
+
+use super::Pool;
+
+pub trait ParallelPool<T: Send + Sync>: Pool<T> {
+    type ParIter<'a>: ParallelIterator<Item=&'a T> where Self: 'a, T: 'a;
+    type ParIterMut<'a>: ParallelIterator<Item=&'a mut T> where Self: 'a, T: 'a;
+
+    fn par_iter<'a>(&'a self) -> Self::ParIter<'a>;
+    fn par_iter_mut<'a>(&'a mut self) -> Self::ParIterMut<'a>;
+}
+
+/*
+    How a backend hands its slots to ParIter and ParIterMut. Both iterators
+    start with every slot and a count of the items in them, and whenever rayon
+    wants to split, they cut their range where half of their items are on each
+    side. So a range with lots of free slots gets split no more than a dense one,
+    and each thread gets a similar number of items rather than of slots.
+
+    Implementations are handed out to several threads at once, each working on
+    its own range of ids, so they're plain pointers rather than references.
+*/
+
+/// # Safety
+/// item() and item_mut() must return a pointer to a live item that no other id
+/// shares, which is what lets ParIterMut hand out &mut T. item_mut() is only
+/// called when the pointers came from a &mut borrow of the pool.
+pub unsafe trait Slots<T>: Copy {
+    const SPLIT_ALIGNMENT: usize = 1; // ranges are only split at multiples of this
+
+    fn num_slots(&self) -> usize;
+    fn is_live(&self, id: usize) -> bool;
+    fn item(&self, id: usize) -> *const T;
+    fn item_mut(&self, id: usize) -> *mut T;
+
+    // Returns the first id after num_items_wanted items in start..end, along with how many items come before it
+    fn split_point(&self, start: usize, end: usize, num_items_wanted: usize) -> (usize, usize) {
+        let mut id: usize = start;
+        let mut num_items: usize = 0;
+        while id < end && (num_items < num_items_wanted || id % Self::SPLIT_ALIGNMENT != 0) {
+            if self.is_live(id) {
+                num_items += 1;
+            }
+            id += 1;
+        }
+        return (id, num_items)
+    }
+}
+
+/*
+    split_point() for backends that keep a list of their non-empty blocks, in
+    no particular order, so that the blocks with no items in them are never
+    looked at. Each pass over the list counts the items falling in each of a
+    fixed number of buckets of blocks, then narrows the range down to the
+    bucket holding the item wanted, until it's down to a single block. Splits
+    are only made at block boundaries.
+*/
+pub(crate) fn split_point_by_blocks<I: Iterator<Item=usize>>(
+    non_empty_blocks: impl Fn() -> I,
+    num_items_in_block: impl Fn(usize) -> usize,
+    block_size: usize,
+    start: usize,
+    end: usize,
+    num_items_wanted: usize,
+)
+-> (usize, usize)
+{
+    const NUM_BUCKETS: usize = 64;
+    let mut first_block: usize = start / block_size;
+    let mut end_block: usize = end.div_ceil(block_size);
+    let mut num_items_before_first_block: usize = 0;
+    while end_block - first_block > 1 {
+        let num_blocks_per_bucket: usize = (end_block - first_block).div_ceil(NUM_BUCKETS);
+        let num_buckets: usize = (end_block - first_block).div_ceil(num_blocks_per_bucket);
+        let mut num_items_in_bucket: [usize; NUM_BUCKETS] = [0; NUM_BUCKETS];
+        for block in non_empty_blocks() {
+            if first_block <= block && block < end_block {
+                num_items_in_bucket[(block - first_block) / num_blocks_per_bucket] += num_items_in_block(block);
+            }
+        }
+
+        let mut bucket: usize = 0;
+        while bucket < num_buckets-1 && num_items_before_first_block + num_items_in_bucket[bucket] < num_items_wanted {
+            num_items_before_first_block += num_items_in_bucket[bucket];
+            bucket += 1;
+        }
+        first_block += bucket * num_blocks_per_bucket;
+        end_block = end_block.min(first_block + num_blocks_per_bucket);
+    }
+
+    let num_items: usize = num_items_before_first_block + num_items_in_block(first_block);
+    return (end.min((first_block+1) * block_size), num_items)
+}
+
+struct Producer<T, S: Slots<T>> {
+    slots: S,
+    start: usize,
+    end: usize,
+    num_items: usize,
+    _items: PhantomData<T>,
+}
+
+impl <T, S: Slots<T>> Producer<T, S> {
+    fn new(slots: S, num_items: usize) -> Self {
+        return Self {
+            slots,
+            start: 0,
+            end: slots.num_slots(),
+            num_items,
+            _items: PhantomData,
+        }
+    }
+
+    fn split(self) -> (Self, Option<Self>) {
+        if self.num_items < 2 {
+            return (self, None)
+        }
+
+        let (mid, num_items_before_mid): (usize, usize) = self.slots.split_point(self.start, self.end, self.num_items/2);
+        if num_items_before_mid == 0 || num_items_before_mid == self.num_items {
+            return (self, None)
+        }
+
+        let right: Self = Self {
+            slots: self.slots,
+            start: mid,
+            end: self.end,
+            num_items: self.num_items - num_items_before_mid,
+            _items: PhantomData,
+        };
+        let left: Self = Self {
+            end: mid,
+            num_items: num_items_before_mid,
+            ..self
+        };
+        return (left, Some(right))
+    }
+
+    fn ids(&self) -> impl Iterator<Item=usize> {
+        let slots: S = self.slots;
+        return (self.start..self.end).filter(move |id: &usize| slots.is_live(*id))
+    }
+}
+
+pub struct ParIter<'a, T, S: Slots<T>> {
+    producer: Producer<T, S>,
+    _items: PhantomData<&'a T>,
+}
+
+impl <'a, T, S: Slots<T>> ParIter<'a, T, S> {
+    // The slots must only be read through for as long as 'a
+    pub(crate) fn new(slots: S, num_items: usize) -> Self {
+        return Self {
+            producer: Producer::new(slots, num_items),
+            _items: PhantomData,
+        }
+    }
+}
+
+// Same as a &'a Pool would be
+unsafe impl <'a, T: Sync, S: Slots<T>> Send for ParIter<'a, T, S> {}
+
+impl <'a, T: 'a + Sync, S: Slots<T>> ParallelIterator for ParIter<'a, T, S> {
+    type Item = &'a T;
+
+    fn drive_unindexed<C: UnindexedConsumer<Self::Item>>(self, consumer: C) -> C::Result {
+        return bridge_unindexed(SharedProducer::<'a, T, S>{ inner: self.producer, _items: PhantomData }, consumer)
+    }
+}
+
+pub struct ParIterMut<'a, T, S: Slots<T>> {
+    producer: Producer<T, S>,
+    _items: PhantomData<&'a mut T>,
+}
+
+impl <'a, T, S: Slots<T>> ParIterMut<'a, T, S> {
+    // The slots must come from a &'a mut borrow of the pool
+    pub(crate) fn new(slots: S, num_items: usize) -> Self {
+        return Self {
+            producer: Producer::new(slots, num_items),
+            _items: PhantomData,
+        }
+    }
+}
+
+// Same as a &'a mut Pool would be
+unsafe impl <'a, T: Send, S: Slots<T>> Send for ParIterMut<'a, T, S> {}
+
+impl <'a, T: 'a + Send, S: Slots<T>> ParallelIterator for ParIterMut<'a, T, S> {
+    type Item = &'a mut T;
+
+    fn drive_unindexed<C: UnindexedConsumer<Self::Item>>(self, consumer: C) -> C::Result {
+        return bridge_unindexed(MutProducer::<'a, T, S>{ inner: self.producer, _items: PhantomData }, consumer)
+    }
+}
+
+struct SharedProducer<'a, T, S: Slots<T>> {
+    inner: Producer<T, S>,
+    _items: PhantomData<&'a T>,
+}
+
+// Only ever sends reads of items to other threads, which is fine as long as they're Sync
+unsafe impl <'a, T: Sync, S: Slots<T>> Send for SharedProducer<'a, T, S> {}
+
+impl <'a, T: 'a + Sync, S: Slots<T>> UnindexedProducer for SharedProducer<'a, T, S> {
+    type Item = &'a T;
+
+    fn split(self) -> (Self, Option<Self>) {
+        let (left, right): (Producer<T, S>, Option<Producer<T, S>>) = self.inner.split();
+        return (
+            Self { inner: left, _items: PhantomData },
+            right.map(|right: Producer<T, S>| Self { inner: right, _items: PhantomData }),
+        )
+    }
+
+    fn fold_with<F: Folder<Self::Item>>(self, folder: F) -> F {
+        let slots: S = self.inner.slots;
+        return folder.consume_iter(self.inner.ids().map(|id: usize| unsafe { &*slots.item(id) }))
+    }
+}
+
+struct MutProducer<'a, T, S: Slots<T>> {
+    inner: Producer<T, S>,
+    _items: PhantomData<&'a mut T>,
+}
+
+// Each producer has its own range of ids, so the items it hands out are never shared with another thread
+unsafe impl <'a, T: Send, S: Slots<T>> Send for MutProducer<'a, T, S> {}
+
+impl <'a, T: 'a + Send, S: Slots<T>> UnindexedProducer for MutProducer<'a, T, S> {
+    type Item = &'a mut T;
+
+    fn split(self) -> (Self, Option<Self>) {
+        let (left, right): (Producer<T, S>, Option<Producer<T, S>>) = self.inner.split();
+        return (
+            Self { inner: left, _items: PhantomData },
+            right.map(|right: Producer<T, S>| Self { inner: right, _items: PhantomData }),
+        )
+    }
+
+    fn fold_with<F: Folder<Self::Item>>(self, folder: F) -> F {
+        let slots: S = self.inner.slots;
+        return folder.consume_iter(self.inner.ids().map(|id: usize| unsafe { &mut *slots.item_mut(id) }))
+    }
+}
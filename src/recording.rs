@@ -0,0 +1,358 @@
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::marker::PhantomData;
+use core::mem::{size_of, align_of};
+
+use super::Pool;
+
+/*
+    A trace is the sequence of calls made to a pool, in a form that can be
+    replayed against any other backend to compare them on a real workload.
+
+    Ids mean different things to different backends, so a trace never stores
+    them. Every item is instead known by its handle, which is the number of
+    allocations made before it: the first item allocated is handle 0, the next
+    is handle 1, and so on. Replaying only needs a table from handles to the
+    ids the pool being replayed against handed out.
+
+    Items aren't stored either, only their size and alignment, so a replay can
+    stand in items laid out the same way.
+
+    On disk it's the magic bytes, a version byte, and the item size and
+    alignment as varints, followed by one byte per call and, for the calls
+    that take an id, the item's handle as a varint. Varints are LEB128, 7 bits
+    to a byte with the high bit set on every byte but the last.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceOp {
+    Allocate,
+    Deallocate(usize), // the handle of the item
+    Get(usize),
+    GetMut(usize),
+    Iter,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    UnknownOp(u8),
+    Truncated,
+    UnknownHandle(usize), // a handle that isn't live at that point in the trace
+}
+
+const MAGIC: &[u8; 4] = b"PPTR";
+const VERSION: u8 = 1;
+
+const ALLOCATE: u8 = 0;
+const DEALLOCATE: u8 = 1;
+const GET: u8 = 2;
+const GET_MUT: u8 = 3;
+const ITER: u8 = 4;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Trace {
+    item_size: usize,
+    item_align: usize,
+    num_allocations: usize,
+    ops: Vec<u8>, // encoded the same way as on disk, minus the header
+}
+
+impl Trace {
+    fn new(item_size: usize, item_align: usize) -> Self {
+        return Self {
+            item_size,
+            item_align,
+            num_allocations: 0,
+            ops: Vec::new(),
+        }
+    }
+
+    // The size of the items in the pool that was recorded
+    pub fn item_size(&self) -> usize {
+        return self.item_size
+    }
+
+    pub fn item_align(&self) -> usize {
+        return self.item_align
+    }
+
+    // Also how many handles the trace uses, for sizing the table a replay keeps
+    pub fn num_allocations(&self) -> usize {
+        return self.num_allocations
+    }
+
+    pub fn ops(&self) -> TraceOps<'_> {
+        return TraceOps { bytes: &self.ops }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::with_capacity(MAGIC.len() + 1 + 2*10 + self.ops.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        push_varint(&mut bytes, self.item_size);
+        push_varint(&mut bytes, self.item_align);
+        bytes.extend_from_slice(&self.ops);
+        return bytes
+    }
+
+    // Checks the whole trace up front, so that ops() never has to fail
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, TraceError> {
+        if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(TraceError::BadMagic)
+        }
+        let mut bytes: &[u8] = &bytes[MAGIC.len()..];
+        let version: u8 = *bytes.first().ok_or(TraceError::Truncated)?;
+        if version != VERSION {
+            return Err(TraceError::UnsupportedVersion(version))
+        }
+        bytes = &bytes[1..];
+        let item_size: usize = read_varint(&mut bytes)?;
+        let item_align: usize = read_varint(&mut bytes)?;
+
+        let ops: &[u8] = bytes;
+        let mut live: BTreeSet<usize> = BTreeSet::new();
+        let mut num_allocations: usize = 0;
+        while !bytes.is_empty() {
+            match read_op(&mut bytes)? {
+                TraceOp::Allocate => {
+                    live.insert(num_allocations);
+                    num_allocations += 1;
+                }
+                TraceOp::Deallocate(handle) => {
+                    if !live.remove(&handle) {
+                        return Err(TraceError::UnknownHandle(handle))
+                    }
+                }
+                TraceOp::Get(handle) | TraceOp::GetMut(handle) => {
+                    if !live.contains(&handle) {
+                        return Err(TraceError::UnknownHandle(handle))
+                    }
+                }
+                TraceOp::Iter => {}
+            }
+        }
+
+        return Ok(Self {
+            item_size,
+            item_align,
+            num_allocations,
+            ops: ops.to_vec(),
+        })
+    }
+
+    fn push(&mut self, op: TraceOp) {
+        match op {
+            TraceOp::Allocate => {
+                self.ops.push(ALLOCATE);
+                self.num_allocations += 1;
+            }
+            TraceOp::Deallocate(handle) => {
+                self.ops.push(DEALLOCATE);
+                push_varint(&mut self.ops, handle);
+            }
+            TraceOp::Get(handle) => {
+                self.ops.push(GET);
+                push_varint(&mut self.ops, handle);
+            }
+            TraceOp::GetMut(handle) => {
+                self.ops.push(GET_MUT);
+                push_varint(&mut self.ops, handle);
+            }
+            TraceOp::Iter => self.ops.push(ITER),
+        }
+    }
+}
+
+pub struct TraceOps<'a> {
+    bytes: &'a [u8],
+}
+
+impl <'a> Iterator for TraceOps<'a> {
+    type Item = TraceOp;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.bytes.is_empty() {
+            return None
+        }
+        return Some(read_op(&mut self.bytes).unwrap()) // already checked by from_bytes(), or written by a Recording
+    }
+}
+
+fn push_varint(bytes: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        bytes.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn read_varint(bytes: &mut &[u8]) -> Result<usize, TraceError> {
+    let mut value: usize = 0;
+    let mut shift: u32 = 0;
+    loop {
+        let byte: u8 = *bytes.first().ok_or(TraceError::Truncated)?;
+        *bytes = &bytes[1..];
+        if shift >= usize::BITS {
+            return Err(TraceError::Truncated) // more bytes than a usize can hold, so it can't have been written by push_varint()
+        }
+        value |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value)
+        }
+        shift += 7;
+    }
+}
+
+fn read_op(bytes: &mut &[u8]) -> Result<TraceOp, TraceError> {
+    let op: u8 = *bytes.first().ok_or(TraceError::Truncated)?;
+    *bytes = &bytes[1..];
+    match op {
+        ALLOCATE   => return Ok(TraceOp::Allocate),
+        DEALLOCATE => return Ok(TraceOp::Deallocate(read_varint(bytes)?)),
+        GET        => return Ok(TraceOp::Get(read_varint(bytes)?)),
+        GET_MUT    => return Ok(TraceOp::GetMut(read_varint(bytes)?)),
+        ITER       => return Ok(TraceOp::Iter),
+        _          => return Err(TraceError::UnknownOp(op)),
+    }
+}
+
+/*
+    Wraps any pool and records every call made through it into a Trace.
+
+    get() and iter() only borrow the pool, so the trace sits in a RefCell. The
+    handles of the live items are kept in a map from their ids, which is what
+    lets ids be reused by the pool without confusing the trace.
+*/
+pub struct Recording<T, P: Pool<T>> {
+    pool: P,
+    trace: RefCell<Trace>,
+    handles: BTreeMap<usize, usize>, // from the id of every live item to its handle
+    _items: PhantomData<T>,
+}
+
+impl <T, P: Pool<T>> Recording<T, P> {
+    pub fn new() -> Self {
+        return Self::with_capacity(0)
+    }
+
+    pub fn with_capacity(num_items: usize) -> Self {
+        return Self {
+            pool: P::with_capacity(num_items),
+            trace: RefCell::new(Trace::new(size_of::<T>(), align_of::<T>())),
+            handles: BTreeMap::new(),
+            _items: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        return self.pool.len()
+    }
+
+    #[cfg_attr(feature = "debug-checks", track_caller)]
+    pub fn get(&self, id: usize) -> &T {
+        let item: &T = self.pool.get(id);
+        self.trace.borrow_mut().push(TraceOp::Get(self.handles[&id]));
+        return item
+    }
+
+    #[cfg_attr(feature = "debug-checks", track_caller)]
+    pub fn get_mut(&mut self, id: usize) -> &mut T {
+        let item: &mut T = self.pool.get_mut(id);
+        self.trace.get_mut().push(TraceOp::GetMut(self.handles[&id]));
+        return item
+    }
+
+    #[cfg_attr(feature = "debug-checks", track_caller)]
+    pub fn allocate(&mut self, item: T) -> usize {
+        let id: usize = self.pool.allocate(item);
+        let trace: &mut Trace = self.trace.get_mut();
+        self.handles.insert(id, trace.num_allocations());
+        trace.push(TraceOp::Allocate);
+        return id
+    }
+
+    #[cfg_attr(feature = "debug-checks", track_caller)]
+    pub fn deallocate(&mut self, id: usize) {
+        self.pool.deallocate(id);
+        let handle: usize = self.handles.remove(&id).unwrap();
+        self.trace.get_mut().push(TraceOp::Deallocate(handle));
+    }
+
+    pub fn iter<'a>(&'a self) -> P::Iter<'a> where T: 'a {
+        self.trace.borrow_mut().push(TraceOp::Iter);
+        return self.pool.iter()
+    }
+
+    pub fn trace(&self) -> Trace {
+        return self.trace.borrow().clone()
+    }
+
+    pub fn into_parts(self) -> (P, Trace) {
+        return (self.pool, self.trace.into_inner())
+    }
+}
+
+impl <T, P: Pool<T>> Default for Recording<T, P> {
+    fn default() -> Self {
+        return Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Trace, TraceOp, TraceError, Recording};
+    use crate::testing;
+    use crate::testing::Item;
+    use crate::{Simple, FreeList};
+
+    testing::test_every_backend!(test_recording_replays);
+
+    #[test]
+    fn test_handles_survive_reused_ids() {
+        let mut pool: Recording<Item, FreeList<Item>> = Recording::new();
+        let first: usize = pool.allocate(1);
+        pool.deallocate(first);
+        let reused: usize = pool.allocate(2);
+        assert!(reused == first);
+        *pool.get_mut(reused) += 1;
+        pool.iter().count();
+        assert!(pool.trace().ops().eq([TraceOp::Allocate, TraceOp::Deallocate(0), TraceOp::Allocate, TraceOp::GetMut(1), TraceOp::Iter]));
+    }
+
+    #[test]
+    fn test_item_layout_is_recorded() {
+        #[derive(Clone)]
+        #[repr(align(32))]
+        struct Aligned([u8; 40]);
+        let mut pool: Recording<Aligned, Simple<Aligned>> = Recording::new();
+        let id: usize = pool.allocate(Aligned([7; 40]));
+        assert!(pool.get(id).0 == [7; 40]);
+        let trace: Trace = Trace::from_bytes(&pool.trace().to_bytes()).unwrap();
+        assert!(trace.item_size() == 64 && trace.item_align() == 32);
+    }
+
+    #[test]
+    fn test_malformed_traces_are_rejected() {
+        let mut pool: Recording<Item, Simple<Item>> = Recording::new();
+        let id: usize = pool.allocate(1);
+        pool.deallocate(id);
+        let bytes: Vec<u8> = pool.trace().to_bytes();
+
+        assert!(Trace::from_bytes(&bytes[1..]) == Err(TraceError::BadMagic));
+        assert!(Trace::from_bytes(&bytes[..bytes.len()-1]) == Err(TraceError::Truncated));
+
+        let mut bad_version: Vec<u8> = bytes.clone();
+        bad_version[4] = 0xff;
+        assert!(Trace::from_bytes(&bad_version) == Err(TraceError::UnsupportedVersion(0xff)));
+
+        let mut bad_op: Vec<u8> = bytes.clone();
+        bad_op.push(0xff);
+        assert!(Trace::from_bytes(&bad_op) == Err(TraceError::UnknownOp(0xff)));
+
+        let mut double_free: Vec<u8> = bytes.clone();
+        double_free.extend_from_slice(&bytes[bytes.len()-2..]);
+        assert!(Trace::from_bytes(&double_free) == Err(TraceError::UnknownHandle(0)));
+    }
+}
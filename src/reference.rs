@@ -1,50 +1,106 @@
-use crate::Pool;
-use std::collections::HashMap;
+use core::any::type_name;
+use crate::{Pool, OrderedPool};
+use crate::stats::{PoolStats, Watermarks};
+use crate::debug_checks::AddressHistories;
+use crate::leaks::find_leaks;
+use crate::{HeapUsage, InvariantError};
+use core::mem::size_of;
+#[cfg(feature = "std")]
+use core::mem::align_of;
+#[cfg(not(feature = "std"))]
+use core::mem::MaybeUninit;
+use core::ops::Range;
+use alloc::vec;
+use alloc::vec::Vec;
+use allocator_api2::alloc::{Allocator, Global};
+use allocator_api2::boxed::Box;
+#[cfg(feature = "rayon")]
+use crate::ParallelPool;
+
+// Without std there's no HashMap, so fall back on a BTreeMap, which has the same interface for what's used here
+#[cfg(feature = "std")]
+use std::collections::HashMap as Map;
+#[cfg(feature = "std")]
 use std::collections::hash_map::Values;
 
-pub struct Reference<T> {
-    map: HashMap<usize, Box<T>>,
+#[cfg(not(feature = "std"))]
+use alloc::collections::BTreeMap as Map;
+#[cfg(not(feature = "std"))]
+use alloc::collections::btree_map::Values;
+
+pub struct Reference<T, A: Allocator = Global> {
+    map: Map<usize, Box<T, A>>, // only the boxes go through A, the map itself uses the global allocator
+    alloc: A,
+    watermarks: Watermarks,
+    history: AddressHistories, // where each slot was last allocated and freed from, with debug-checks
 }
 
-impl <T> Pool<T> for Reference<T> {
-    type Iter<'a> = Iter<'a, T> where Self: 'a, T: 'a;
+impl <T, A: Allocator + Clone + Default> Pool<T> for Reference<T, A> {
+    type Iter<'a> = Iter<'a, T, A> where Self: 'a, T: 'a;
 
     fn new() -> Self {
-        return Self {
-            map: HashMap::default()
-        }
+        return Self::new_in(A::default())
     }
 
     fn with_capacity(num_items: usize) -> Self {
-        return Self {
-            map: HashMap::with_capacity(num_items)
-        }
+        return Self::with_capacity_in(num_items, A::default())
     }
 
     fn len(&self) -> usize {
         return self.map.len()
     }
 
+    fn capacity(&self) -> usize {
+        return self.map.len() // every item gets its own box, so there's never room for more than what's already here
+    }
+
+    fn stats(&self) -> PoolStats {
+        return PoolStats::new(self.map.len(), self.map.len(), self.watermarks, 1, 0, None) // ids are addresses, so there's no fragmentation to speak of
+    }
+
+    fn heap_size_bytes_with<F: FnMut(&T) -> usize>(&self, item_heap_size_bytes: F) -> HeapUsage {
+        return HeapUsage::new()
+            .with("map", map_heap_size_bytes(&self.map))
+            .with("boxes", self.map.len() * size_of::<T>())
+            .with("item_heap", self.iter().map(item_heap_size_bytes).sum())
+    }
+
+    fn check_invariants(&self) -> Result<(), InvariantError> {
+        for (id, item) in self.map.iter() {
+            if (item.as_ref() as *const T) as usize != *id {
+                return Err(InvariantError::IdIsntItemAddress{ id: *id })
+            }
+        }
+        return Ok(())
+    }
+
     fn get(&self, id: usize) -> &T {
-        let item: &Box<T> = self.map.get(&id).unwrap();
+        self.history.check_access(id);
+        let item: &Box<T, A> = self.map.get(&id).unwrap();
         return item.as_ref()
     }
 
     fn get_mut(&mut self, id: usize) -> &mut T {
-        let item: &mut Box<T> = self.map.get_mut(&id).unwrap();
+        self.history.check_access(id);
+        let item: &mut Box<T, A> = self.map.get_mut(&id).unwrap();
         return item.as_mut()
     }
 
     fn allocate(&mut self, item: T) -> usize {
-        let item: Box<T> = Box::new(item);
+        let item: Box<T, A> = Box::new_in(item, self.alloc.clone());
         let address: usize = (&(*item) as *const T) as usize;
         self.map.insert(address, item);
+        self.history.allocated(address);
+        self.watermarks.grew(); // capacity() is len(), so every allocation grows it
+        self.watermarks.allocated(self.map.len());
         return address
     }
 
     fn deallocate(&mut self, id: usize) {
+        self.history.check_deallocate(id);
         assert!(self.map.get(&id).is_some());
         self.map.remove(&id);
+        self.history.freed(id);
     }
 
     fn iter<'a>(&'a self) -> Self::Iter<'a> {
@@ -52,23 +108,264 @@ impl <T> Pool<T> for Reference<T> {
     }
 }
 
-pub struct Iter<'a, T> {
-    inner: Values<'a, usize, Box<T>>
+// The map doesn't keep its keys in order (with std anyway), so these sort a copy of its entries
+impl <T, A: Allocator + Clone + Default> OrderedPool<T> for Reference<T, A> {
+    type SortedIter<'a> = vec::IntoIter<(usize, &'a T)> where Self: 'a, T: 'a;
+    type RangeIter<'a> = vec::IntoIter<(usize, &'a T)> where Self: 'a, T: 'a;
+
+    fn sorted_iter<'a>(&'a self) -> Self::SortedIter<'a> {
+        return self.sorted_entries(0..usize::MAX)
+    }
+
+    fn iter_range<'a>(&'a self, range: Range<usize>) -> Self::RangeIter<'a> {
+        return self.sorted_entries(range)
+    }
+}
+
+/*
+    The map keeps its storage to itself, so this works it out from how the
+    standard library lays it out. A HashMap is a single allocation of buckets
+    followed by a control byte per bucket and a group's worth more, and
+    capacity() gives away how many buckets there are: all but one of them when
+    there are fewer than 8, and 7 in every 8 after that.
+*/
+#[cfg(feature = "std")]
+fn map_heap_size_bytes<V>(map: &Map<usize, V>) -> usize {
+    const GROUP_WIDTH: usize = if cfg!(all(any(target_arch = "x86", target_arch = "x86_64"), target_feature = "sse2")) { 16 } else { size_of::<usize>() };
+    let capacity: usize = map.capacity();
+    if capacity == 0 {
+        return 0
+    }
+    let num_buckets: usize = if capacity < 8 { capacity + 1 } else { capacity / 7 * 8 };
+    let ctrl_align: usize = align_of::<(usize, V)>().max(GROUP_WIDTH);
+    let ctrl_offset: usize = (num_buckets * size_of::<(usize, V)>()).next_multiple_of(ctrl_align);
+    return ctrl_offset + num_buckets + GROUP_WIDTH
+}
+
+/*
+    A BTreeMap doesn't say how its entries are spread over its nodes, so this
+    counts them as if every leaf were full, with the internal nodes above them
+    just as full. Nodes are at least half full, so it's off by at most a factor
+    of two, and always on the low side.
+*/
+#[cfg(not(feature = "std"))]
+fn map_heap_size_bytes<V>(map: &Map<usize, V>) -> usize {
+    const NODE_CAPACITY: usize = 11; // entries per node
+    #[allow(dead_code)]
+    struct LeafNode<V> {
+        parent: *const (),
+        parent_idx: u16,
+        len: u16,
+        keys: [MaybeUninit<usize>; NODE_CAPACITY],
+        vals: [MaybeUninit<V>; NODE_CAPACITY],
+    }
+    #[allow(dead_code)]
+    struct InternalNode<V> {
+        data: LeafNode<V>,
+        edges: [*const (); NODE_CAPACITY + 1],
+    }
+
+    let mut num_nodes: usize = map.len().div_ceil(NODE_CAPACITY);
+    let mut num_bytes: usize = num_nodes * size_of::<LeafNode<V>>();
+    while num_nodes > 1 {
+        num_nodes = num_nodes.div_ceil(NODE_CAPACITY + 1);
+        num_bytes += num_nodes * size_of::<InternalNode<V>>();
+    }
+    return num_bytes
+}
+
+#[cfg(feature = "rayon")]
+impl <T: Send + Sync, A: Allocator + Clone + Default> ParallelPool<T> for Reference<T, A> {
+    type ParIter<'a> = rayon::vec::IntoIter<&'a T> where Self: 'a, T: 'a;
+    type ParIterMut<'a> = rayon::vec::IntoIter<&'a mut T> where Self: 'a, T: 'a;
+
+    // The map can't be split up between threads, so this collects references to the items first
+    fn par_iter<'a>(&'a self) -> Self::ParIter<'a> {
+        let items: Vec<&'a T> = self.map.values().map(|item: &'a Box<T, A>| item.as_ref()).collect();
+        return rayon::iter::IntoParallelIterator::into_par_iter(items)
+    }
+
+    fn par_iter_mut<'a>(&'a mut self) -> Self::ParIterMut<'a> {
+        let items: Vec<&'a mut T> = self.map.values_mut().map(|item: &'a mut Box<T, A>| item.as_mut()).collect();
+        return rayon::iter::IntoParallelIterator::into_par_iter(items)
+    }
+}
+
+impl <T, A: Allocator> Reference<T, A> {
+    pub fn new_in(alloc: A) -> Self {
+        return Self {
+            map: Map::default(),
+            alloc,
+            watermarks: Watermarks::new(),
+            history: AddressHistories::new(),
+        }
+    }
+
+    pub fn with_capacity_in(num_items: usize, alloc: A) -> Self {
+        return Self {
+            map: Self::map_with_capacity(num_items),
+            alloc,
+            watermarks: Watermarks::new(),
+            history: AddressHistories::new(),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn map_with_capacity(num_items: usize) -> Map<usize, Box<T, A>> {
+        return Map::with_capacity(num_items)
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn map_with_capacity(_num_items: usize) -> Map<usize, Box<T, A>> {
+        return Map::new() // BTreeMaps don't preallocate
+    }
+
+    fn sorted_entries<'a>(&'a self, range: Range<usize>) -> vec::IntoIter<(usize, &'a T)> {
+        let mut entries: Vec<(usize, &'a T)> = self.map.iter()
+            .filter(|(id, _): &(&usize, &'a Box<T, A>)| range.contains(*id))
+            .map(|(id, item): (&usize, &'a Box<T, A>)| (*id, item.as_ref()))
+            .collect();
+        entries.sort_unstable_by_key(|(id, _): &(usize, &T)| *id);
+        return entries.into_iter()
+    }
+}
+
+impl <T, A: Allocator> Drop for Reference<T, A> {
+    fn drop(&mut self) {
+        find_leaks(type_name::<Self>(), self.map.len(), self.map.keys().copied(), |id: usize| self.history.allocated_at(id)).report();
+    }
+}
+
+/*
+    A HashMap can only be walked forwards, so the first call to next_back()
+    collects whatever the front hasn't reached yet, and both ends take from
+    that from then on.
+*/
+pub struct Iter<'a, T, A: Allocator = Global> {
+    inner: Values<'a, usize, Box<T, A>>,
+    rest: Option<vec::IntoIter<&'a T>>,
 }
 
-impl <'a, T> Iter<'a, T> {
-    fn new(inner: Values<'a, usize, Box<T>>) -> Self {
-        return Self { inner }
+impl <'a, T, A: Allocator> Iter<'a, T, A> {
+    fn new(inner: Values<'a, usize, Box<T, A>>) -> Self {
+        return Self { inner, rest: None }
     }
 }
 
-impl <'a, T> Iterator for Iter<'a, T> {
+impl <'a, T, A: Allocator> Iterator for Iter<'a, T, A> {
     type Item = &'a T;
     
     fn next(&mut self) -> Option<Self::Item> {
+        if let Some(rest) = &mut self.rest {
+            return rest.next()
+        }
+
         match self.inner.next() {
             Some(next) => return Some(next.as_ref()),
             None       => return None,
         }
     }
-}
\ No newline at end of file
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match &self.rest {
+            Some(rest) => return rest.size_hint(),
+            None       => return self.inner.size_hint(),
+        }
+    }
+}
+
+impl <'a, T, A: Allocator> DoubleEndedIterator for Iter<'a, T, A> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let inner: &mut Values<'a, usize, Box<T, A>> = &mut self.inner;
+        let rest: &mut vec::IntoIter<&'a T> = self.rest.get_or_insert_with(|| {
+            return inner.map(|item: &'a Box<T, A>| item.as_ref()).collect::<Vec<&'a T>>().into_iter()
+        });
+        return rest.next_back()
+    }
+}
+
+impl <'a, T, A: Allocator> ExactSizeIterator for Iter<'a, T, A> {}
+
+#[cfg(test)]
+mod tests {
+    use super::{Reference, Map, map_heap_size_bytes};
+    use core::mem::size_of;
+    use crate::Pool;
+    use crate::testing;
+    use crate::testing::{Item, CountingAlloc, Growth};
+
+    #[test]
+    fn test_boxes_go_through_allocator() {
+        testing::reset_counting_alloc();
+        let mut pool: Reference<Item, CountingAlloc> = Pool::new();
+        let ids: Vec<usize> = (0..100).map(|item: Item| pool.allocate(item)).collect();
+        assert!(testing::num_counting_alloc_allocations() == 100);
+        pool.deallocate(ids[0]);
+        drop(pool);
+        assert!(testing::num_counting_alloc_bytes_in_use() == 0);
+    }
+
+    // Only the boxes go through the pool's allocator, so they're all that can be checked exactly
+    #[test]
+    fn test_heap_size_of_boxes() {
+        testing::reset_counting_alloc();
+        let mut pool: Reference<Item, CountingAlloc> = Pool::new();
+        let ids: Vec<usize> = (0..100).map(|item: Item| pool.allocate(item)).collect();
+        for id in ids.iter().step_by(3) {
+            pool.deallocate(*id);
+        }
+        assert!(pool.heap_size_bytes().bytes_of("boxes") == Some(testing::num_counting_alloc_bytes_in_use()));
+        assert!(pool.heap_size_bytes().bytes_of("map") == Some(map_heap_size_bytes(&pool.map)));
+    }
+
+    // The map goes through the global allocator, so its size is checked on a map of its own, away from anything else allocating
+    #[test]
+    fn test_heap_size_of_map() {
+        let mut map: Map<usize, Box<Item>> = Map::new();
+        let mut num_bytes: isize = 0;
+        for round in 0..10 {
+            num_bytes += testing::count_bytes_allocated(|| {
+                for id in 0..round*100 + 3 {
+                    map.insert(round*10_000 + id, Box::new(id as Item));
+                }
+            });
+            assert!(map_heap_size_bytes(&map) as isize == num_bytes - (map.len() * size_of::<Item>()) as isize);
+        }
+
+        num_bytes += testing::count_bytes_allocated(|| {
+            map.retain(|id: &usize, _: &mut Box<Item>| id % 2 == 0);
+            map.shrink_to_fit();
+        });
+        assert!(map_heap_size_bytes(&map) as isize == num_bytes - (map.len() * size_of::<Item>()) as isize);
+
+        let mut map: Option<Map<usize, Box<Item>>> = None;
+        let num_bytes: isize = testing::count_bytes_allocated(|| map = Some(Map::with_capacity(1000)));
+        assert!(map_heap_size_bytes(map.as_ref().unwrap()) as isize == num_bytes);
+    }
+
+    #[test]
+    fn test_sorted_iter() {
+        testing::test_sorted_iter::<Reference<Item>>();
+    }
+
+    #[test]
+    fn test_iterators_are_double_ended_and_exact_size() {
+        testing::test_iterators_are_double_ended_and_exact_size::<Reference<Item>>();
+    }
+
+    #[test]
+    fn test_iter_range() {
+        testing::test_iter_range::<Reference<Item>>();
+    }
+
+    #[test]
+    fn test_stats() {
+        testing::test_stats::<Reference<Item>>(Growth::EveryAllocation, false);
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn test_par_iter() {
+        testing::test_par_iter::<Reference<Item>>();
+    }
+}
@@ -0,0 +1,503 @@
+use alloc::rc;
+use alloc::rc::Rc;
+use core::cell::{Ref, RefCell, RefMut, UnsafeCell};
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+use allocator_api2::alloc::{Allocator, Global};
+use allocator_api2::vec::Vec;
+use super::FreeList;
+
+#[derive(Clone, Copy)]
+struct Counts {
+    strong: usize,   // number of PoolRcs pointing at the slot, 0 when it's free
+    generation: u64, // bumped every time the slot is freed, so PoolWeaks to an old item can tell it's gone
+    borrows: isize,  // like a RefCell's flag: how many PoolRefs to the item are alive, or -1 while a PoolRefMut is
+}
+
+impl Counts {
+    const FREE: Self = Self{ strong: 0, generation: 0, borrows: 0 };
+}
+
+/*
+    An item that can be written through a shared borrow of the FreeList, once
+    its slot's borrow flag says nothing else is looking at it.
+
+    FreeList needs its items to be Clone, but only ever clones the free slots
+    it grows into, so this never reads an item that's borrowed.
+*/
+struct ItemCell<T>(UnsafeCell<ManuallyDrop<T>>);
+
+impl <T: Clone> Clone for ItemCell<T> {
+    fn clone(&self) -> Self {
+        return Self(UnsafeCell::new(unsafe { (*self.0.get()).clone() }))
+    }
+}
+
+// The counts are borrowed separately from the items, so handles can be cloned, dropped and upgraded while an item is borrowed
+struct Shared<T: Clone, A: Allocator + Clone> {
+    items: RefCell<FreeList<ItemCell<T>, A>>, // dropped by hand after the RefCell is released, see PoolRc::drop()
+    counts: RefCell<Vec<Counts, A>>,
+    pending_frees: RefCell<Vec<usize, A>>, // slots whose items are gone, but couldn't be deallocated while other items were borrowed
+}
+
+impl <T: Clone, A: Allocator + Clone> Shared<T, A> {
+    fn deallocate_pending(items: &mut FreeList<ItemCell<T>, A>, pending_frees: &mut Vec<usize, A>) {
+        for id in pending_frees.drain(..) {
+            items.deallocate(id); // the slot only holds a ManuallyDrop, so this doesn't drop the item again
+        }
+    }
+}
+
+impl <T: Clone, A: Allocator + Clone> Drop for Shared<T, A> {
+    fn drop(&mut self) {
+        Self::deallocate_pending(self.items.get_mut(), self.pending_frees.get_mut());
+    }
+}
+
+/*
+    A FreeList whose items are owned by Rc-like handles. allocate_shared() hands
+    back a PoolRc, cloning it bumps a count stored in a column alongside the
+    slots, and dropping the last one deallocates the slot.
+
+    A PoolWeak remembers the slot's generation along with its id, so it only
+    upgrades while the item it was made from is still alive, even if the slot has
+    since been reused for another item.
+
+    The handles need to reach the pool to deallocate from it, so the pool lives in
+    an Rc, with the items and the counts each in their own RefCell. Each item is
+    borrowed on its own, through a borrow flag in its counts, so any number of
+    different items can be borrowed at once, mutably or not. Every borrow also
+    holds the items' RefCell shared, which keeps the storage from moving under
+    it, so allocating while any item is borrowed panics the way a RefCell
+    would.
+
+    Dropping the last handle to an item drops the item right away. Its slot is
+    deallocated then too, unless other items are borrowed, in which case it's
+    put aside and deallocated the next time the pool is borrowed mutably.
+    Items are dropped after every borrow taken to free them is released, so an
+    item that owns handles into its own pool can be dropped without a panic.
+*/
+pub struct SharedPool<T: Clone, A: Allocator + Clone = Global> {
+    shared: Rc<Shared<T, A>>,
+}
+
+impl <T: Clone, A: Allocator + Clone + Default> SharedPool<T, A> {
+    pub fn new() -> Self {
+        return Self::new_in(A::default())
+    }
+
+    pub fn with_capacity(num_items: usize) -> Self {
+        return Self::with_capacity_in(num_items, A::default())
+    }
+}
+
+impl <T: Clone, A: Allocator + Clone + Default> Default for SharedPool<T, A> {
+    fn default() -> Self {
+        return Self::new()
+    }
+}
+
+impl <T: Clone, A: Allocator + Clone> SharedPool<T, A> {
+    pub fn new_in(alloc: A) -> Self {
+        return Self::with_capacity_in(0, alloc)
+    }
+
+    pub fn with_capacity_in(num_items: usize, alloc: A) -> Self {
+        let mut counts: Vec<Counts, A> = Vec::with_capacity_in(num_items, alloc.clone());
+        counts.resize(num_items, Counts::FREE);
+        return Self {
+            shared: Rc::new(Shared {
+                items: RefCell::new(FreeList::with_capacity_in(num_items, alloc.clone())),
+                counts: RefCell::new(counts),
+                pending_frees: RefCell::new(Vec::new_in(alloc)),
+            }),
+        }
+    }
+
+    #[cfg_attr(feature = "debug-checks", track_caller)]
+    pub fn allocate_shared(&self, item: T) -> PoolRc<T, A> {
+        let mut items: RefMut<FreeList<ItemCell<T>, A>> = self.shared.items.borrow_mut();
+        Shared::deallocate_pending(&mut items, &mut self.shared.pending_frees.borrow_mut());
+        let id: usize = items.allocate(ItemCell(UnsafeCell::new(ManuallyDrop::new(item))));
+        drop(items);
+
+        let mut counts: RefMut<Vec<Counts, A>> = self.shared.counts.borrow_mut();
+        if id >= counts.len() {
+            counts.resize(id+1, Counts::FREE);
+        }
+        assert!(counts[id].strong == 0 && counts[id].borrows == 0);
+        counts[id].strong = 1;
+
+        return PoolRc {
+            shared: self.shared.clone(),
+            id,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        return self.shared.items.borrow().len() - self.shared.pending_frees.borrow().len()
+    }
+}
+
+impl <T: Clone, A: Allocator + Clone> Clone for SharedPool<T, A> {
+    fn clone(&self) -> Self {
+        return Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+pub struct PoolRc<T: Clone, A: Allocator + Clone = Global> {
+    shared: Rc<Shared<T, A>>,
+    id: usize,
+}
+
+impl <T: Clone, A: Allocator + Clone> PoolRc<T, A> {
+    pub fn id(&self) -> usize {
+        return self.id
+    }
+
+    // Panics if the item is already borrowed mutably, but not when any other item is
+    #[track_caller]
+    pub fn borrow(&self) -> PoolRef<'_, T, A> {
+        let mut counts: RefMut<Vec<Counts, A>> = self.shared.counts.borrow_mut();
+        if counts[self.id].borrows < 0 {
+            panic!("item {} is already mutably borrowed", self.id);
+        }
+        counts[self.id].borrows += 1;
+        drop(counts);
+
+        let items: Ref<FreeList<ItemCell<T>, A>> = self.shared.items.borrow();
+        let item: *const T = items.get(self.id).0.get().cast::<T>(); // ManuallyDrop<T> has the same layout as T
+        return PoolRef { _items: items, counts: &self.shared.counts, id: self.id, item }
+    }
+
+    // Panics if the item is already borrowed, but not when any other item is
+    #[track_caller]
+    pub fn borrow_mut(&self) -> PoolRefMut<'_, T, A> {
+        let mut counts: RefMut<Vec<Counts, A>> = self.shared.counts.borrow_mut();
+        if counts[self.id].borrows != 0 {
+            panic!("item {} is already borrowed", self.id);
+        }
+        counts[self.id].borrows = -1;
+        drop(counts);
+
+        let items: Ref<FreeList<ItemCell<T>, A>> = self.shared.items.borrow();
+        let item: *mut T = items.get(self.id).0.get().cast::<T>();
+        return PoolRefMut { _items: items, counts: &self.shared.counts, id: self.id, item }
+    }
+
+    pub fn strong_count(&self) -> usize {
+        return self.shared.counts.borrow()[self.id].strong
+    }
+
+    pub fn downgrade(&self) -> PoolWeak<T, A> {
+        return PoolWeak {
+            shared: Rc::downgrade(&self.shared),
+            id: self.id,
+            generation: self.shared.counts.borrow()[self.id].generation,
+        }
+    }
+}
+
+impl <T: Clone, A: Allocator + Clone> Clone for PoolRc<T, A> {
+    fn clone(&self) -> Self {
+        self.shared.counts.borrow_mut()[self.id].strong += 1;
+        return Self {
+            shared: self.shared.clone(),
+            id: self.id,
+        }
+    }
+}
+
+impl <T: Clone, A: Allocator + Clone> Drop for PoolRc<T, A> {
+    fn drop(&mut self) {
+        let mut counts: RefMut<Vec<Counts, A>> = self.shared.counts.borrow_mut();
+        counts[self.id].strong -= 1;
+        if counts[self.id].strong > 0 {
+            return
+        }
+        assert!(counts[self.id].borrows == 0); // a borrow holds on to a handle, so the last one can't go while it's alive
+        counts[self.id].generation += 1;
+        drop(counts);
+
+        let item: T = match self.shared.items.try_borrow_mut() {
+            Ok(mut items) => {
+                Shared::deallocate_pending(&mut items, &mut self.shared.pending_frees.borrow_mut());
+                let item: T = unsafe { ManuallyDrop::take(items.get_mut(self.id).0.get_mut()) };
+                items.deallocate(self.id); // the slot only holds a ManuallyDrop, so this doesn't drop the item again
+                item
+            },
+            // Other items are borrowed, and nothing can reach this one anymore, so it's taken out through a shared borrow
+            Err(_) => {
+                let items: Ref<FreeList<ItemCell<T>, A>> = self.shared.items.borrow();
+                let item: T = unsafe { ManuallyDrop::take(&mut *items.get(self.id).0.get()) };
+                self.shared.pending_frees.borrow_mut().push(self.id);
+                item
+            },
+        };
+        drop(item); // may drop handles into this same pool, which is fine now that it isn't borrowed
+    }
+}
+
+// A shared borrow of one item, which keeps only that item from being borrowed mutably
+pub struct PoolRef<'a, T: Clone, A: Allocator + Clone = Global> {
+    _items: Ref<'a, FreeList<ItemCell<T>, A>>, // keeps the storage from moving while the item is borrowed
+    counts: &'a RefCell<Vec<Counts, A>>,
+    id: usize,
+    item: *const T,
+}
+
+impl <'a, T: Clone, A: Allocator + Clone> Deref for PoolRef<'a, T, A> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        return unsafe { &*self.item }
+    }
+}
+
+impl <'a, T: Clone, A: Allocator + Clone> Drop for PoolRef<'a, T, A> {
+    fn drop(&mut self) {
+        self.counts.borrow_mut()[self.id].borrows -= 1;
+    }
+}
+
+// A mutable borrow of one item, which keeps only that item from being borrowed again
+pub struct PoolRefMut<'a, T: Clone, A: Allocator + Clone = Global> {
+    _items: Ref<'a, FreeList<ItemCell<T>, A>>, // shared, since the borrow flag is what makes the item exclusive
+    counts: &'a RefCell<Vec<Counts, A>>,
+    id: usize,
+    item: *mut T,
+}
+
+impl <'a, T: Clone, A: Allocator + Clone> Deref for PoolRefMut<'a, T, A> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        return unsafe { &*self.item }
+    }
+}
+
+impl <'a, T: Clone, A: Allocator + Clone> DerefMut for PoolRefMut<'a, T, A> {
+    fn deref_mut(&mut self) -> &mut T {
+        return unsafe { &mut *self.item }
+    }
+}
+
+impl <'a, T: Clone, A: Allocator + Clone> Drop for PoolRefMut<'a, T, A> {
+    fn drop(&mut self) {
+        self.counts.borrow_mut()[self.id].borrows = 0;
+    }
+}
+
+pub struct PoolWeak<T: Clone, A: Allocator + Clone = Global> {
+    shared: rc::Weak<Shared<T, A>>,
+    id: usize,
+    generation: u64,
+}
+
+impl <T: Clone, A: Allocator + Clone> PoolWeak<T, A> {
+    pub fn upgrade(&self) -> Option<PoolRc<T, A>> {
+        let shared: Rc<Shared<T, A>> = self.shared.upgrade()?;
+        let mut counts: RefMut<Vec<Counts, A>> = shared.counts.borrow_mut();
+        if counts[self.id].strong == 0 || counts[self.id].generation != self.generation {
+            return None
+        }
+
+        counts[self.id].strong += 1;
+        drop(counts);
+        return Some(PoolRc {
+            shared,
+            id: self.id,
+        })
+    }
+}
+
+impl <T: Clone, A: Allocator + Clone> Clone for PoolWeak<T, A> {
+    fn clone(&self) -> Self {
+        return Self {
+            shared: self.shared.clone(),
+            id: self.id,
+            generation: self.generation,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SharedPool, PoolRc, PoolWeak, PoolRef, PoolRefMut};
+    use crate::testing::Item;
+    use crate::testing::CountingAlloc;
+    use crate::testing;
+    use core::cell::RefCell;
+    use std::rc::Rc;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_last_handle_deallocates() {
+        let pool: SharedPool<Item> = SharedPool::new();
+        let first: PoolRc<Item> = pool.allocate_shared(3);
+        let second: PoolRc<Item> = first.clone();
+        assert!(pool.len() == 1);
+        assert!(first.strong_count() == 2);
+
+        *second.borrow_mut() += 1;
+        assert!(*first.borrow() == 4);
+
+        drop(first);
+        assert!(pool.len() == 1);
+        assert!(second.strong_count() == 1);
+        drop(second);
+        assert!(pool.len() == 0);
+    }
+
+    #[test]
+    fn test_weak_handles() {
+        let pool: SharedPool<Item> = SharedPool::new();
+        let item: PoolRc<Item> = pool.allocate_shared(3);
+        let weak: PoolWeak<Item> = item.downgrade();
+        assert!(*weak.upgrade().unwrap().borrow() == 3);
+        assert!(item.strong_count() == 1);
+
+        let id: usize = item.id();
+        drop(item);
+        assert!(weak.upgrade().is_none());
+
+        let reused: PoolRc<Item> = pool.allocate_shared(5);
+        assert!(reused.id() == id);
+        assert!(weak.upgrade().is_none()); // same slot, different generation
+        assert!(reused.downgrade().upgrade().unwrap().id() == id);
+    }
+
+    #[test]
+    fn test_handles_while_an_item_is_borrowed() {
+        let pool: SharedPool<Item> = SharedPool::new();
+        let item: PoolRc<Item> = pool.allocate_shared(3);
+        let other: PoolRc<Item> = pool.allocate_shared(4);
+        let borrowed: PoolRef<Item> = item.borrow();
+
+        let clone: PoolRc<Item> = item.clone();
+        let weak: PoolWeak<Item> = other.downgrade();
+        assert!(clone.strong_count() == 2);
+        assert!(*other.borrow() == 4);
+        assert!(weak.upgrade().unwrap().strong_count() == 2); // the upgraded handle is dropped again right away
+        drop(clone);
+        assert!(item.strong_count() == 1);
+        assert!(*borrowed == 3);
+        drop(borrowed);
+
+        drop(other);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_different_items_borrowed_at_once() {
+        let pool: SharedPool<Item> = SharedPool::new();
+        let first: PoolRc<Item> = pool.allocate_shared(3);
+        let second: PoolRc<Item> = pool.allocate_shared(4);
+
+        *first.borrow_mut() += *second.borrow();
+        assert!(*first.borrow() == 7);
+        let mut first_item: PoolRefMut<Item> = first.borrow_mut();
+        let mut second_item: PoolRefMut<Item> = second.borrow_mut();
+        core::mem::swap(&mut *first_item, &mut *second_item);
+        drop(first_item);
+        drop(second_item);
+        assert!(*first.borrow() == 4 && *second.borrow() == 7);
+    }
+
+    #[test]
+    #[should_panic(expected = "already borrowed")]
+    fn test_same_item_borrowed_mutably_twice() {
+        let pool: SharedPool<Item> = SharedPool::new();
+        let item: PoolRc<Item> = pool.allocate_shared(3);
+        let clone: PoolRc<Item> = item.clone();
+        let _borrowed: PoolRef<Item> = item.borrow();
+        *clone.borrow_mut() += 1;
+    }
+
+    #[test]
+    fn test_dropping_a_handle_while_another_item_is_borrowed() {
+        let counter: Rc<()> = Rc::new(());
+        let pool: SharedPool<Rc<()>> = SharedPool::new();
+        let kept: PoolRc<Rc<()>> = pool.allocate_shared(counter.clone());
+        let dropped: PoolRc<Rc<()>> = pool.allocate_shared(counter.clone());
+        let weak: PoolWeak<Rc<()>> = dropped.downgrade();
+        let id: usize = dropped.id();
+
+        let borrowed: PoolRef<Rc<()>> = kept.borrow();
+        drop(dropped);
+        assert!(Rc::strong_count(&counter) == 2); // the item is dropped right away, only its slot waits
+        assert!(pool.len() == 1);
+        assert!(weak.upgrade().is_none());
+        drop(borrowed);
+
+        let reused: PoolRc<Rc<()>> = pool.allocate_shared(counter.clone());
+        assert!(reused.id() == id);
+        assert!(pool.len() == 2);
+        drop(kept);
+        drop(reused);
+        assert!(pool.len() == 0);
+        assert!(Rc::strong_count(&counter) == 1);
+    }
+
+    #[test]
+    fn test_weak_handles_outlive_the_pool() {
+        let pool: SharedPool<Item> = SharedPool::new();
+        let item: PoolRc<Item> = pool.allocate_shared(3);
+        let weak: PoolWeak<Item> = item.downgrade();
+        drop(pool);
+        assert!(*weak.upgrade().unwrap().borrow() == 3); // the handle keeps the pool alive
+        drop(item);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_items_are_dropped_exactly_once() {
+        let counter: Rc<()> = Rc::new(());
+        let pool: SharedPool<Rc<()>> = SharedPool::new();
+        let mut handles: Vec<PoolRc<Rc<()>>> = (0..100)
+            .map(|_| pool.allocate_shared(counter.clone()))
+            .collect();
+        handles.extend(handles.clone());
+        assert!(Rc::strong_count(&counter) == 101);
+
+        handles = handles.split_off(150); // drops every original and the clones of the first 50 items
+        assert!(Rc::strong_count(&counter) == 51);
+        drop(handles);
+        assert!(Rc::strong_count(&counter) == 1);
+        assert!(pool.len() == 0);
+    }
+
+    #[test]
+    fn test_items_holding_handles_into_their_own_pool() {
+        #[derive(Clone)]
+        struct Node {
+            next: Option<PoolRc<RefCell<Node>>>,
+        }
+
+        let pool: SharedPool<RefCell<Node>> = SharedPool::new();
+        let mut head: PoolRc<RefCell<Node>> = pool.allocate_shared(RefCell::new(Node{ next: None }));
+        for _ in 0..100 {
+            head = pool.allocate_shared(RefCell::new(Node{ next: Some(head) }));
+        }
+        assert!(pool.len() == 101);
+        assert!(head.borrow().borrow().next.is_some());
+        drop(head);
+        assert!(pool.len() == 0);
+    }
+
+    #[test]
+    fn test_all_memory_goes_through_allocator() {
+        testing::reset_counting_alloc();
+        let mut handles: Vec<PoolRc<Item, CountingAlloc>> = Vec::with_capacity(100);
+        let num_global_allocations: usize = testing::count_allocations(|| {
+            let pool: SharedPool<Item, CountingAlloc> = SharedPool::new();
+            handles.extend((0..100).map(|i: Item| pool.allocate_shared(i)));
+            handles.clear();
+        });
+        assert!(num_global_allocations == 1); // the Rc holding the pool itself
+        assert!(testing::num_counting_alloc_allocations() > 0);
+        assert!(testing::num_counting_alloc_bytes_in_use() == 0);
+    }
+}
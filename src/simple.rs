@@ -1,38 +1,171 @@
-use std::slice;
+use core::any::type_name;
+use core::iter::{Enumerate, Zip};
+#[cfg(feature = "debug-checks")]
+use core::mem::MaybeUninit;
+use core::ops::Range;
+use core::slice;
+use allocator_api2::alloc::{Allocator, Global};
+use allocator_api2::vec;
+use allocator_api2::vec::Vec;
 
-use super::Pool;
+use super::{Pool, OrderedPool, PreallocatedPool};
+use super::stats::{PoolStats, Watermarks};
+use super::debug_checks::SlotHistories;
+#[cfg(feature = "debug-checks")]
+use super::debug_checks;
+use super::leaks::find_leaks;
+use super::heap_size::{HeapUsage, vec_heap_size_bytes};
+use super::InvariantError;
+#[cfg(feature = "rayon")]
+use super::parallel::{ParallelPool, ParIter, ParIterMut, Slots};
 
-pub struct Simple<T: Clone> {
-    items: Vec<Option<T>>,
+/*
+    An Option<T>, which is all a slot needs. With debug-checks, whether it
+    holds an item is kept apart from the item's bytes instead, so that a freed
+    slot's bytes can be poisoned like FlagsBasedPool's are, without that
+    turning it into something that can't be read as empty anymore.
+*/
+#[cfg(not(feature = "debug-checks"))]
+#[derive(Clone)]
+struct Slot<T>(Option<T>);
+
+#[cfg(not(feature = "debug-checks"))]
+impl <T> Slot<T> {
+    const EMPTY: Self = Self(None);
+
+    fn new(item: T) -> Self {
+        return Self(Some(item))
+    }
+
+    fn is_some(&self) -> bool {
+        return self.0.is_some()
+    }
+
+    fn as_ref(&self) -> Option<&T> {
+        return self.0.as_ref()
+    }
+
+    fn as_mut(&mut self) -> Option<&mut T> {
+        return self.0.as_mut()
+    }
+
+    fn free(&mut self) {
+        self.0 = None;
+    }
+}
+
+#[cfg(feature = "debug-checks")]
+struct Slot<T> {
+    is_some: bool,
+    item: MaybeUninit<T>, // only initialized while is_some, and poisoned once it's freed
+}
+
+#[cfg(feature = "debug-checks")]
+impl <T> Slot<T> {
+    const EMPTY: Self = Self { is_some: false, item: MaybeUninit::uninit() };
+
+    fn new(item: T) -> Self {
+        return Self { is_some: true, item: MaybeUninit::new(item) }
+    }
+
+    fn is_some(&self) -> bool {
+        return self.is_some
+    }
+
+    fn as_ref(&self) -> Option<&T> {
+        if !self.is_some {
+            return None
+        }
+        return Some(unsafe { self.item.assume_init_ref() })
+    }
+
+    fn as_mut(&mut self) -> Option<&mut T> {
+        if !self.is_some {
+            return None
+        }
+        return Some(unsafe { self.item.assume_init_mut() })
+    }
+
+    fn free(&mut self) {
+        if self.is_some {
+            self.is_some = false; // before dropping, so a panicking drop can't leave it looking live
+            unsafe { self.item.assume_init_drop() };
+            debug_checks::poison(&mut self.item);
+        }
+    }
+}
+
+#[cfg(feature = "debug-checks")]
+impl <T: Clone> Clone for Slot<T> {
+    fn clone(&self) -> Self {
+        match self.as_ref() {
+            Some(item) => return Self::new(item.clone()),
+            None => return Self::EMPTY,
+        }
+    }
+}
+
+#[cfg(feature = "debug-checks")]
+impl <T> Drop for Slot<T> {
+    fn drop(&mut self) {
+        if self.is_some {
+            unsafe { self.item.assume_init_drop() };
+        }
+    }
+}
+
+pub struct Simple<T: Clone, A: Allocator = Global> {
+    items: Vec<Slot<T>, A>,
     num_items: usize,
+    watermarks: Watermarks, // for stats(), since neither can be worked out afterwards
+    history: SlotHistories<A>, // where each slot was last allocated and freed from, with debug-checks
 }
 
-impl <T: Clone> Pool<T> for Simple<T> {
+impl <T: Clone, A: Allocator + Clone + Default> Pool<T> for Simple<T, A> {
     type Iter<'a> = Iter<'a, T> where Self: 'a, T: 'a;
 
     fn new() -> Self {
-        return Self{ 
-            items: Vec::new(),
-            num_items: 0,
-        }
+        return Self::new_in(A::default())
     }
 
     fn with_capacity(num_items: usize) -> Self {
-        return Self { 
-            items: vec![None; num_items], 
-            num_items: 0,
-        }
+        return Self::with_capacity_in(num_items, A::default())
     }
 
     fn len(&self) -> usize {
         return self.num_items
     }
 
+    fn capacity(&self) -> usize {
+        return self.items.len()
+    }
+
+    fn stats(&self) -> PoolStats {
+        return PoolStats::new(self.num_items, self.items.len(), self.watermarks, 1, 0, self.sorted_iter().next_back().map(|(id, _): (usize, &T)| id))
+    }
+
+    fn heap_size_bytes_with<F: FnMut(&T) -> usize>(&self, item_heap_size_bytes: F) -> HeapUsage {
+        return HeapUsage::new()
+            .with("items", vec_heap_size_bytes(&self.items))
+            .with("history", self.history.heap_size_bytes())
+            .with("item_heap", self.iter().map(item_heap_size_bytes).sum())
+    }
+
+    fn check_invariants(&self) -> Result<(), InvariantError> {
+        let num_allocated_slots: usize = self.items.iter().filter(|item: &&Slot<T>| item.is_some()).count();
+        if num_allocated_slots != self.num_items {
+            return Err(InvariantError::NumItemsDoesntMatchSlots{ num_items: self.num_items, num_allocated_slots })
+        }
+        return Ok(())
+    }
+
     fn get(&self, id: usize) -> &T {
+        self.history.check_access(id);
         return self.items[id].as_ref().unwrap()
     }
     
     fn get_mut(&mut self, id: usize) -> &mut T {
+        self.history.check_access(id);
         return self.items[id].as_mut().unwrap()
     }
 
@@ -40,13 +173,17 @@ impl <T: Clone> Pool<T> for Simple<T> {
         const GROWTH_FACTOR: usize = 2;
         if self.num_items == self.items.len() {
             let new_num_items: usize = if self.num_items == 0 { 1 } else { self.num_items*GROWTH_FACTOR };
-            self.items.resize(new_num_items, None);
+            self.items.resize(new_num_items, Slot::EMPTY);
+            self.history.grow_to(new_num_items);
+            self.watermarks.grew();
         }
 
         for id in 0..self.items.len() {
-            if self.items[id].is_none() {
-                self.items[id] = Some(item);
+            if !self.items[id].is_some() {
+                self.items[id] = Slot::new(item);
+                self.history.allocated(id);
                 self.num_items += 1;
+                self.watermarks.allocated(self.num_items);
                 return id
             }
         }
@@ -54,23 +191,97 @@ impl <T: Clone> Pool<T> for Simple<T> {
     }
 
     fn deallocate(&mut self, id: usize) {
+        self.history.check_deallocate(id);
         assert!(self.items[id].is_some());
-        self.items[id] = None;
+        self.items[id].free();
+        self.history.freed(id);
         self.num_items -= 1;
     }
 
     fn iter<'a>(&'a self) -> Self::Iter<'a> {
-        return Iter::new(self.items.iter())
+        return Iter::new(self.items.iter(), self.num_items)
+    }
+}
+
+impl <T: Clone, A: Allocator + Clone + Default> PreallocatedPool<T> for Simple<T, A> {
+    type Alloc = A;
+
+    fn allocator(&self) -> &A {
+        return self.items.allocator()
+    }
+}
+
+impl <T: Clone, A: Allocator + Clone + Default> OrderedPool<T> for Simple<T, A> {
+    type SortedIter<'a> = SortedIter<'a, T> where Self: 'a, T: 'a;
+    type RangeIter<'a> = RangeIter<'a, T> where Self: 'a, T: 'a;
+
+    fn sorted_iter<'a>(&'a self) -> SortedIter<'a, T> {
+        return SortedIter::new(self.items.iter().enumerate(), self.num_items)
+    }
+
+    fn iter_range<'a>(&'a self, range: Range<usize>) -> RangeIter<'a, T> {
+        return RangeIter::new(&self.items, range)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl <T: Clone + Send + Sync, A: Allocator + Clone + Default> ParallelPool<T> for Simple<T, A> {
+    type ParIter<'a> = ParIter<'a, T, ParSlots<T>> where Self: 'a, T: 'a;
+    type ParIterMut<'a> = ParIterMut<'a, T, ParSlots<T>> where Self: 'a, T: 'a;
+
+    fn par_iter<'a>(&'a self) -> Self::ParIter<'a> {
+        let slots: ParSlots<T> = ParSlots{ items: self.items.as_ptr() as *mut Slot<T>, num_slots: self.items.len() };
+        return ParIter::new(slots, self.num_items)
+    }
+
+    fn par_iter_mut<'a>(&'a mut self) -> Self::ParIterMut<'a> {
+        let slots: ParSlots<T> = ParSlots{ items: self.items.as_mut_ptr(), num_slots: self.items.len() };
+        return ParIterMut::new(slots, self.num_items)
+    }
+}
+
+impl <T: Clone, A: Allocator + Clone> Simple<T, A> {
+    pub fn new_in(alloc: A) -> Self {
+        return Self{ 
+            items: Vec::new_in(alloc.clone()),
+            num_items: 0,
+            watermarks: Watermarks::new(),
+            history: SlotHistories::new_in(alloc),
+        }
+    }
+
+    pub fn with_capacity_in(num_items: usize, alloc: A) -> Self {
+        return Self { 
+            items: vec![in alloc.clone(); Slot::EMPTY; num_items], 
+            num_items: 0,
+            watermarks: Watermarks::new(),
+            history: SlotHistories::with_capacity_in(num_items, alloc),
+        }
+    }
+}
+
+impl <T: Clone, A: Allocator> Drop for Simple<T, A> {
+    fn drop(&mut self) {
+        find_leaks(
+            type_name::<Self>(),
+            self.num_items,
+            self.items.iter()
+                .enumerate()
+                .filter(|(_, item): &(usize, &Slot<T>)| item.is_some())
+                .map(|(id, _): (usize, &Slot<T>)| id),
+            |id: usize| self.history.allocated_at(id),
+        ).report();
     }
 }
 
 pub struct Iter<'a, T> {
-    inner: slice::Iter<'a, Option<T>>
+    inner: slice::Iter<'a, Slot<T>>,
+    num_items_left: usize,
 }
 
 impl <'a, T> Iter<'a, T> {
-    fn new(inner: slice::Iter<'a, Option<T>>) -> Self {
-        return Self { inner }
+    fn new(inner: slice::Iter<'a, Slot<T>>, num_items: usize) -> Self {
+        return Self { inner, num_items_left: num_items }
     }
 }
 
@@ -81,7 +292,29 @@ impl <'a, T> Iterator for Iter<'a, T> {
         loop {
             match self.inner.next() {
                 Some(item) => {
-                    if let Some(item) = item {
+                    if let Some(item) = item.as_ref() {
+                        self.num_items_left -= 1;
+                        return Some(item)
+                    }
+                },
+
+                None => return None,
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        return (self.num_items_left, Some(self.num_items_left))
+    }
+}
+
+impl <'a, T> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.inner.next_back() {
+                Some(item) => {
+                    if let Some(item) = item.as_ref() {
+                        self.num_items_left -= 1;
                         return Some(item)
                     }
                 },
@@ -92,11 +325,145 @@ impl <'a, T> Iterator for Iter<'a, T> {
     }
 }
 
+impl <'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+pub struct SortedIter<'a, T> {
+    inner: Enumerate<slice::Iter<'a, Slot<T>>>,
+    num_items_left: usize,
+}
+
+impl <'a, T> SortedIter<'a, T> {
+    fn new(inner: Enumerate<slice::Iter<'a, Slot<T>>>, num_items: usize) -> Self {
+        return Self { inner, num_items_left: num_items }
+    }
+}
+
+impl <'a, T> Iterator for SortedIter<'a, T> {
+    type Item = (usize, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.inner.next() {
+                Some((id, slot)) => {
+                    if let Some(item) = slot.as_ref() {
+                        self.num_items_left -= 1;
+                        return Some((id, item))
+                    }
+                },
+                None => return None,
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        return (self.num_items_left, Some(self.num_items_left))
+    }
+}
+
+impl <'a, T> DoubleEndedIterator for SortedIter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.inner.next_back() {
+                Some((id, slot)) => {
+                    if let Some(item) = slot.as_ref() {
+                        self.num_items_left -= 1;
+                        return Some((id, item))
+                    }
+                },
+                None => return None,
+            }
+        }
+    }
+}
+
+impl <'a, T> ExactSizeIterator for SortedIter<'a, T> {}
+
+pub struct RangeIter<'a, T> {
+    inner: Zip<Range<usize>, slice::Iter<'a, Slot<T>>>,
+}
+
+impl <'a, T> RangeIter<'a, T> {
+    fn new(slots: &'a [Slot<T>], range: Range<usize>) -> Self {
+        let end: usize = range.end.min(slots.len());
+        let start: usize = range.start.min(end);
+        return Self { inner: (start..end).zip(slots[start..end].iter()) }
+    }
+}
+
+impl <'a, T> Iterator for RangeIter<'a, T> {
+    type Item = (usize, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.inner.next() {
+                Some((id, slot)) => {
+                    if let Some(item) = slot.as_ref() {
+                        return Some((id, item))
+                    }
+                },
+                None => return None,
+            }
+        }
+    }
+}
+
+impl <'a, T> DoubleEndedIterator for RangeIter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.inner.next_back() {
+                Some((id, slot)) => {
+                    if let Some(item) = slot.as_ref() {
+                        return Some((id, item))
+                    }
+                },
+                None => return None,
+            }
+        }
+    }
+}
+
+#[cfg(feature = "rayon")]
+pub struct ParSlots<T> {
+    items: *mut Slot<T>,
+    num_slots: usize,
+}
+
+#[cfg(feature = "rayon")]
+impl <T> Clone for ParSlots<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl <T> Copy for ParSlots<T> {}
+
+#[cfg(feature = "rayon")]
+unsafe impl <T> Slots<T> for ParSlots<T> {
+    fn num_slots(&self) -> usize {
+        return self.num_slots
+    }
+
+    fn is_live(&self, id: usize) -> bool {
+        return unsafe { (*self.items.add(id)).is_some() }
+    }
+
+    fn item(&self, id: usize) -> *const T {
+        return unsafe { (*self.items.add(id)).as_ref().unwrap() }
+    }
+
+    fn item_mut(&self, id: usize) -> *mut T {
+        return unsafe { (*self.items.add(id)).as_mut().unwrap() }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::Simple;
     use crate::testing;
     use crate::testing::Item; 
+    use crate::testing::Growth;
+    use crate::testing::CountingAlloc;
 
     type Pool = Simple<Item>;
 
@@ -123,12 +490,67 @@ mod tests {
     }
 
     #[test]
+    #[cfg_attr(miri, ignore)] // far too slow to interpret
     fn fuzz_many_pools_few_mutations() {
         testing::fuzz_many_pools_few_mutations::<Pool>();
     }
 
     #[test]
+    #[cfg_attr(miri, ignore)] // far too slow to interpret
     fn fuzz_few_pools_many_mutations() {
         testing::fuzz_few_pools_many_mutations::<Pool>();
     }
+
+    #[test]
+    fn test_sorted_iter() {
+        testing::test_sorted_iter::<Pool>();
+    }
+
+    #[test]
+    fn test_iterators_are_double_ended_and_exact_size() {
+        testing::test_iterators_are_double_ended_and_exact_size::<Pool>();
+    }
+
+    #[test]
+    fn test_iter_range() {
+        testing::test_iter_range::<Pool>();
+    }
+
+    #[test]
+    fn test_stats() {
+        testing::test_stats::<Pool>(Growth::Doubling{ first_capacity: 1 }, true);
+    }
+
+    #[test]
+    fn test_provided_methods() {
+        testing::test_provided_methods::<Pool>();
+    }
+
+    #[cfg(feature = "debug-checks")]
+    #[test]
+    fn test_freed_slots_are_poisoned() {
+        use crate::Pool as _;
+        let mut pool: Pool = Pool::new();
+        let id: usize = pool.allocate(-1);
+        pool.allocate(2);
+        pool.deallocate(id);
+        let stale_item: Item = unsafe { pool.items[id].item.assume_init() }; // every byte was written by the poisoning
+        assert!(stale_item == Item::from_ne_bytes([0xdd; size_of::<Item>()]));
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn test_par_iter() {
+        testing::test_par_iter::<Pool>();
+    }
+
+    #[test]
+    fn test_all_memory_goes_through_allocator() {
+        testing::test_all_memory_goes_through_allocator::<Simple<Item, CountingAlloc>>();
+    }
+
+    #[test]
+    fn test_heap_size_matches_allocator() {
+        testing::test_heap_size_matches_allocator::<Simple<Item, CountingAlloc>>();
+    }
 }
\ No newline at end of file
@@ -0,0 +1,494 @@
+use core::any::type_name;
+use core::iter::{Enumerate, Zip};
+use core::ops::Range;
+use core::slice;
+use allocator_api2::alloc::{Allocator, Global};
+use allocator_api2::vec::Vec;
+use super::{Pool, OrderedPool, PreallocatedPool};
+use super::stats::{PoolStats, Watermarks};
+use super::debug_checks::SlotHistories;
+use super::leaks::find_leaks;
+use super::heap_size::{HeapUsage, vec_heap_size_bytes};
+use super::InvariantError;
+use super::invariants::check_free_list;
+#[cfg(feature = "rayon")]
+use super::parallel::ParallelPool;
+
+#[derive(Clone, Copy)]
+enum Entry {
+    Dense(usize), // where the id's item is in items
+    Free{next_free_id: Option<usize>},
+}
+
+/*
+    Items are kept packed together at the front of a Vec, with no holes
+    between them, so iterating over them is iterating over a slice.
+    deallocate() moves the last item into the hole it leaves, which means
+    items don't stay where they were allocated, and iter() doesn't give them
+    in any particular order.
+
+    Ids stay put regardless. Each id has an entry saying where its item is in
+    items, and ids[i] says which id items[i] belongs to, so the entry of the
+    item that gets moved can be updated. Free entries are chained together
+    like the slots in a FreeList, so ids are reused the same way.
+*/
+pub struct SparseSet<T, A: Allocator = Global> {
+    items: Vec<T, A>,
+    ids: Vec<usize, A>, // the id of each item in items
+    entries: Vec<Entry, A>, // one per id
+    next_free_id: Option<usize>,
+    watermarks: Watermarks, // for stats(), since neither can be worked out afterwards
+    history: SlotHistories<A>, // where each slot was last allocated and freed from, with debug-checks
+}
+
+impl <T, A: Allocator + Clone + Default> Pool<T> for SparseSet<T, A> {
+    type Iter<'a> = slice::Iter<'a, T> where Self: 'a, T: 'a;
+
+    fn new() -> Self {
+        return Self::new_in(A::default())
+    }
+
+    fn with_capacity(num_items: usize) -> Self {
+        return Self::with_capacity_in(num_items, A::default())
+    }
+
+    fn len(&self) -> usize {
+        return self.items.len()
+    }
+
+    fn capacity(&self) -> usize {
+        return self.entries.len()
+    }
+
+    fn stats(&self) -> PoolStats {
+        return PoolStats::new(self.items.len(), self.entries.len(), self.watermarks, 1, 0, self.sorted_iter().next_back().map(|(id, _): (usize, &T)| id))
+    }
+
+    // The free list is threaded through the free entries, so it doesn't take up anything of its own
+    fn heap_size_bytes_with<F: FnMut(&T) -> usize>(&self, item_heap_size_bytes: F) -> HeapUsage {
+        return HeapUsage::new()
+            .with("items", vec_heap_size_bytes(&self.items))
+            .with("ids", vec_heap_size_bytes(&self.ids))
+            .with("entries", vec_heap_size_bytes(&self.entries))
+            .with("history", self.history.heap_size_bytes())
+            .with("item_heap", self.iter().map(item_heap_size_bytes).sum())
+    }
+
+    fn check_invariants(&self) -> Result<(), InvariantError> {
+        if self.items.len() != self.ids.len() {
+            return Err(InvariantError::DenseArraysDisagree{ num_items: self.items.len(), num_ids: self.ids.len() })
+        }
+
+        let num_allocated_slots: usize = self.entries.iter().filter(|entry: &&Entry| matches!(entry, Entry::Dense(_))).count();
+        if num_allocated_slots != self.items.len() {
+            return Err(InvariantError::NumItemsDoesntMatchSlots{ num_items: self.items.len(), num_allocated_slots })
+        }
+
+        /*
+            With as many dense entries as items, each pointing at an item that
+            points back, no two ids can share an item and none are left over
+        */
+        for (id, entry) in self.entries.iter().enumerate() {
+            if let Entry::Dense(dense_index) = entry {
+                if *dense_index >= self.ids.len() {
+                    return Err(InvariantError::DenseIndexOutOfBounds{ id })
+                }
+                if self.ids[*dense_index] != id {
+                    return Err(InvariantError::DenseIndexPointsToAnotherId{ id })
+                }
+            }
+        }
+
+        return check_free_list(self.next_free_id, self.entries.len(), self.items.len(), |id: usize| {
+            match self.entries[id] {
+                Entry::Dense(_) => return None,
+                Entry::Free{ next_free_id } => return Some(next_free_id),
+            }
+        })
+    }
+
+    fn get(&self, id: usize) -> &T {
+        self.history.check_access(id);
+        match self.entries[id] {
+            Entry::Dense(dense_index) => return &self.items[dense_index],
+            Entry::Free{..} => panic!(),
+        }
+    }
+
+    fn get_mut(&mut self, id: usize) -> &mut T {
+        self.history.check_access(id);
+        match self.entries[id] {
+            Entry::Dense(dense_index) => return &mut self.items[dense_index],
+            Entry::Free{..} => panic!(),
+        }
+    }
+
+    fn allocate(&mut self, item: T) -> usize {
+        self.expand_if_needed();
+        let id: usize = self.next_free_id.unwrap();
+        match self.entries[id] {
+            Entry::Free{ next_free_id } => {
+                self.next_free_id = next_free_id;
+            },
+
+            Entry::Dense(_) => panic!(),
+        }
+        self.entries[id] = Entry::Dense(self.items.len());
+        self.items.push(item);
+        self.ids.push(id);
+        self.history.allocated(id);
+        self.watermarks.allocated(self.items.len());
+        return id
+    }
+
+    fn deallocate(&mut self, id: usize) {
+        self.history.check_deallocate(id);
+        let dense_index: usize = match self.entries[id] {
+            Entry::Dense(dense_index) => dense_index,
+            Entry::Free{..} => panic!(),
+        };
+        self.items.swap_remove(dense_index); // drops the item, and moves the last one into its place
+        self.ids.swap_remove(dense_index);
+        if dense_index < self.ids.len() {
+            let moved_id: usize = self.ids[dense_index];
+            self.entries[moved_id] = Entry::Dense(dense_index);
+        }
+        self.entries[id] = Entry::Free{ next_free_id: self.next_free_id };
+        self.next_free_id = Some(id);
+        self.history.freed(id);
+    }
+
+    fn iter<'a>(&'a self) -> slice::Iter<'a, T> {
+        return self.items.iter()
+    }
+}
+
+impl <T, A: Allocator + Clone + Default> PreallocatedPool<T> for SparseSet<T, A> {
+    type Alloc = A;
+
+    fn allocator(&self) -> &A {
+        return self.items.allocator()
+    }
+}
+
+impl <T, A: Allocator + Clone + Default> OrderedPool<T> for SparseSet<T, A> {
+    type SortedIter<'a> = SortedIter<'a, T> where Self: 'a, T: 'a;
+    type RangeIter<'a> = RangeIter<'a, T> where Self: 'a, T: 'a;
+
+    fn sorted_iter<'a>(&'a self) -> SortedIter<'a, T> {
+        return SortedIter::new(self.entries.iter().enumerate(), &self.items)
+    }
+
+    fn iter_range<'a>(&'a self, range: Range<usize>) -> RangeIter<'a, T> {
+        return RangeIter::new(&self.entries, &self.items, range)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl <T: Send + Sync, A: Allocator + Clone + Default> ParallelPool<T> for SparseSet<T, A> {
+    type ParIter<'a> = rayon::slice::Iter<'a, T> where Self: 'a, T: 'a;
+    type ParIterMut<'a> = rayon::slice::IterMut<'a, T> where Self: 'a, T: 'a;
+
+    // The items are already a slice, so rayon can split it evenly without the help of parallel::Slots
+    fn par_iter<'a>(&'a self) -> Self::ParIter<'a> {
+        return rayon::iter::IntoParallelRefIterator::par_iter(self.items.as_slice())
+    }
+
+    fn par_iter_mut<'a>(&'a mut self) -> Self::ParIterMut<'a> {
+        return rayon::iter::IntoParallelRefMutIterator::par_iter_mut(self.items.as_mut_slice())
+    }
+}
+
+impl <T, A: Allocator + Clone> SparseSet<T, A> {
+    pub fn new_in(alloc: A) -> Self {
+        return Self {
+            items: Vec::new_in(alloc.clone()),
+            ids: Vec::new_in(alloc.clone()),
+            entries: Vec::new_in(alloc.clone()),
+            next_free_id: None,
+            watermarks: Watermarks::new(),
+            history: SlotHistories::new_in(alloc),
+        }
+    }
+
+    pub fn with_capacity_in(num_items: usize, alloc: A) -> Self {
+        let mut pool: Self = Self {
+            items: Vec::with_capacity_in(num_items, alloc.clone()),
+            ids: Vec::with_capacity_in(num_items, alloc.clone()),
+            entries: Vec::with_capacity_in(num_items, alloc.clone()),
+            next_free_id: None,
+            watermarks: Watermarks::new(),
+            history: SlotHistories::with_capacity_in(num_items, alloc),
+        };
+        pool.add_free_entries(num_items);
+        return pool
+    }
+
+    // The items in the order iter() gives them, which is also where they are in memory
+    pub fn as_slice(&self) -> &[T] {
+        return &self.items
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        return &mut self.items
+    }
+
+    // The id of each item in as_slice()
+    pub fn ids(&self) -> &[usize] {
+        return &self.ids
+    }
+
+    fn expand_if_needed(&mut self) {
+        if self.next_free_id.is_some() {
+            return
+        }
+
+        self.watermarks.grew();
+        const GROWTH_FACTOR: usize = 2;
+        let new_num_ids: usize =
+            if self.entries.len() == 0 {
+                1
+            }
+            else {
+                self.entries.len()*GROWTH_FACTOR
+            };
+        // Grown alongside the entries, so that allocate() never has to grow the dense arrays on its own
+        self.items.reserve_exact(new_num_ids - self.items.len());
+        self.ids.reserve_exact(new_num_ids - self.ids.len());
+        self.add_free_entries(new_num_ids - self.entries.len());
+        self.history.grow_to(new_num_ids);
+    }
+
+    // Only called when every existing id is taken
+    fn add_free_entries(&mut self, num_entries: usize) {
+        if num_entries == 0 {
+            return
+        }
+
+        let old_num_ids: usize = self.entries.len();
+        let new_num_ids: usize = old_num_ids + num_entries;
+        self.entries.reserve_exact(num_entries);
+        for i in old_num_ids..(new_num_ids-1) {
+            self.entries.push(Entry::Free{ next_free_id: Some(i+1) });
+        }
+        self.entries.push(Entry::Free{ next_free_id: None });
+        self.next_free_id = Some(old_num_ids);
+    }
+}
+
+impl <T, A: Allocator> Drop for SparseSet<T, A> {
+    fn drop(&mut self) {
+        find_leaks(type_name::<Self>(), self.items.len(), self.ids.iter().copied(), |id: usize| self.history.allocated_at(id)).report();
+    }
+}
+
+pub struct SortedIter<'a, T> {
+    entries: Enumerate<slice::Iter<'a, Entry>>,
+    items: &'a [T],
+    num_items_left: usize,
+}
+
+impl <'a, T> SortedIter<'a, T> {
+    fn new(entries: Enumerate<slice::Iter<'a, Entry>>, items: &'a [T]) -> Self {
+        return Self { entries, items, num_items_left: items.len() }
+    }
+}
+
+impl <'a, T> Iterator for SortedIter<'a, T> {
+    type Item = (usize, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.entries.next() {
+                Some((id, Entry::Dense(dense_index))) => {
+                    self.num_items_left -= 1;
+                    return Some((id, &self.items[*dense_index]))
+                },
+                Some((_, Entry::Free{..})) => continue,
+                None => return None,
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        return (self.num_items_left, Some(self.num_items_left))
+    }
+}
+
+impl <'a, T> DoubleEndedIterator for SortedIter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.entries.next_back() {
+                Some((id, Entry::Dense(dense_index))) => {
+                    self.num_items_left -= 1;
+                    return Some((id, &self.items[*dense_index]))
+                },
+                Some((_, Entry::Free{..})) => continue,
+                None => return None,
+            }
+        }
+    }
+}
+
+impl <'a, T> ExactSizeIterator for SortedIter<'a, T> {}
+
+pub struct RangeIter<'a, T> {
+    entries: Zip<Range<usize>, slice::Iter<'a, Entry>>,
+    items: &'a [T],
+}
+
+impl <'a, T> RangeIter<'a, T> {
+    fn new(entries: &'a [Entry], items: &'a [T], range: Range<usize>) -> Self {
+        let end: usize = range.end.min(entries.len());
+        let start: usize = range.start.min(end);
+        return Self { entries: (start..end).zip(entries[start..end].iter()), items }
+    }
+}
+
+impl <'a, T> Iterator for RangeIter<'a, T> {
+    type Item = (usize, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.entries.next() {
+                Some((id, Entry::Dense(dense_index))) => return Some((id, &self.items[*dense_index])),
+                Some((_, Entry::Free{..})) => continue,
+                None => return None,
+            }
+        }
+    }
+}
+
+impl <'a, T> DoubleEndedIterator for RangeIter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.entries.next_back() {
+                Some((id, Entry::Dense(dense_index))) => return Some((id, &self.items[*dense_index])),
+                Some((_, Entry::Free{..})) => continue,
+                None => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use super::{SparseSet, Entry};
+    use crate::testing;
+    use crate::{InvariantError, Pool as _};
+    use crate::testing::Item;
+    use crate::testing::Growth;
+    use crate::testing::CountingAlloc;
+
+    type Pool = SparseSet<Item>;
+
+    #[test]
+    #[should_panic]
+    fn test_invalid_get_to_empty_pool() {
+        testing::test_invalid_get_to_empty_pool::<Pool>();
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_invalid_get_to_nonempty_pool() {
+        testing::test_invalid_get_to_nonempty_pool::<Pool>();
+    }
+
+    #[test]
+    fn test_one_item() {
+        testing::test_one_item::<Pool>();
+    }
+
+    #[test]
+    fn test_many_items() {
+        testing::test_many_items::<Pool>();
+    }
+
+    #[test]
+    fn test_items_are_dropped_exactly_once() {
+        testing::test_items_are_dropped_exactly_once::<SparseSet<Rc<()>>>();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // far too slow to interpret
+    fn fuzz_many_pools_few_mutations() {
+        testing::fuzz_many_pools_few_mutations::<Pool>();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // far too slow to interpret
+    fn fuzz_few_pools_many_mutations() {
+        testing::fuzz_few_pools_many_mutations::<Pool>();
+    }
+
+    #[test]
+    fn test_sorted_iter() {
+        testing::test_sorted_iter::<Pool>();
+    }
+
+    #[test]
+    fn test_iterators_are_double_ended_and_exact_size() {
+        testing::test_iterators_are_double_ended_and_exact_size::<Pool>();
+    }
+
+    #[test]
+    fn test_iter_range() {
+        testing::test_iter_range::<Pool>();
+    }
+
+    #[test]
+    fn test_stats() {
+        testing::test_stats::<Pool>(Growth::Doubling{ first_capacity: 1 }, true);
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn test_par_iter() {
+        testing::test_par_iter::<Pool>();
+    }
+
+    #[test]
+    fn test_all_memory_goes_through_allocator() {
+        testing::test_all_memory_goes_through_allocator::<SparseSet<Item, CountingAlloc>>();
+    }
+
+    #[test]
+    fn test_heap_size_matches_allocator() {
+        testing::test_heap_size_matches_allocator::<SparseSet<Item, CountingAlloc>>();
+    }
+
+    #[test]
+    fn test_deallocate_keeps_items_dense() {
+        let mut pool: Pool = Pool::new();
+        let ids: [usize; 4] = [pool.allocate(10), pool.allocate(11), pool.allocate(12), pool.allocate(13)];
+        pool.deallocate(ids[1]);
+        assert!(pool.as_slice() == [10, 13, 12]);
+        assert!(pool.ids() == [ids[0], ids[3], ids[2]]);
+        assert!(*pool.get(ids[3]) == 13);
+        pool.deallocate(ids[2]);
+        assert!(pool.as_slice() == [10, 13]);
+        assert!(pool.check_invariants().is_ok());
+    }
+
+    #[test]
+    fn test_check_invariants_finds_broken_entries() {
+        let mut pool: Pool = Pool::with_capacity(4);
+        let first: usize = pool.allocate(1);
+        let second: usize = pool.allocate(2);
+        assert!(pool.check_invariants().is_ok());
+
+        pool.entries[second] = Entry::Dense(0);
+        assert!(pool.check_invariants() == Err(InvariantError::DenseIndexPointsToAnotherId{ id: second }));
+        pool.entries[second] = Entry::Dense(2);
+        assert!(pool.check_invariants() == Err(InvariantError::DenseIndexOutOfBounds{ id: second }));
+        pool.entries[second] = Entry::Dense(1);
+        pool.ids.pop();
+        assert!(pool.check_invariants() == Err(InvariantError::DenseArraysDisagree{ num_items: 2, num_ids: 1 }));
+        pool.ids.push(second);
+
+        let next_free_id: usize = pool.next_free_id.unwrap();
+        pool.entries[next_free_id] = Entry::Free{ next_free_id: Some(first) };
+        assert!(pool.check_invariants() == Err(InvariantError::FreeListLeadsToItem{ slot: first }));
+    }
+}
\ No newline at end of file
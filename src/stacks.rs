@@ -1,6 +1,21 @@
-use std::slice;
-use std::mem::size_of;
-use super::Pool;
+use core::any::type_name;
+use core::iter::Enumerate;
+use core::slice;
+use core::ops::Range;
+use core::mem::size_of;
+use core::mem::MaybeUninit;
+use allocator_api2::alloc::{Allocator, Global};
+use allocator_api2::vec;
+use allocator_api2::vec::Vec;
+use super::{Pool, OrderedPool, PreallocatedPool};
+use super::stats::{PoolStats, Watermarks};
+use super::debug_checks::SlotHistories;
+use super::leaks::{find_leaks, PendingLeakReport};
+use super::heap_size::{HeapUsage, vec_heap_size_bytes};
+use super::InvariantError;
+use super::invariants::find_block_listed_wrongly;
+#[cfg(feature = "rayon")]
+use super::parallel::{ParallelPool, ParIter, ParIterMut, Slots, split_point_by_blocks};
 
 type Block = u8;
 const BITS_PER_BYTE: usize = 8;
@@ -8,65 +23,82 @@ const FLAGS_PER_BLOCK: usize = size_of::<Block>() * BITS_PER_BYTE;
 const EMPTY_BLOCK: Block = 0;
 const FULL_BLOCK: Block = Block::MAX;
 
-pub struct Stacks<T: Clone> {
-    items: Vec<Option<T>>,
+pub struct Stacks<T: Clone, A: Allocator = Global> {
+    items: Vec<MaybeUninit<T>, A>, // only initialized where the item's flag is set
     num_items: usize,
+    watermarks: Watermarks, // for stats(), since neither can be worked out afterwards
+    history: SlotHistories<A>, // where each slot was last allocated and freed from, with debug-checks
 
-    flags: Vec<Block>, // flags for each item (0 for unallocated, 1 for allocated)
-    open_blocks: Vec<usize>,  // indices of blocks that have at least one item unallocated
-    alloc_blocks: Vec<usize>, // indices of blocks that have one or more items allocated
+    flags: Vec<Block, A>, // flags for each item (0 for unallocated, 1 for allocated)
+    open_blocks: Vec<usize, A>,  // indices of blocks that have at least one item unallocated
+    alloc_blocks: Vec<usize, A>, // indices of blocks that have one or more items allocated
 }
 
-impl <T: Clone> Pool<T> for Stacks<T> {
+impl <T: Clone, A: Allocator + Clone + Default> Pool<T> for Stacks<T, A> {
     type Iter<'a> = Iter<'a, T> where Self: 'a, T: 'a;
     
     fn new() -> Self {
-        return Self {
-            items: Vec::new(),
-            num_items: 0,
-            
-            flags: Vec::new(),
-            open_blocks: Vec::new(),
-            alloc_blocks: Vec::new(),
-        }        
+        return Self::new_in(A::default())
     }
 
     fn with_capacity(num_items: usize) -> Self {
-        let num_blocks: usize;
-        if num_items == 0 {
-            num_blocks = 0;
-        }
-        else {
-            num_blocks = ((num_items-1)/FLAGS_PER_BLOCK)+1;
-        };
-        
-        let items: Vec<Option<T>> = vec![None; num_blocks*FLAGS_PER_BLOCK];
-        let num_items: usize = 0;
+        return Self::with_capacity_in(num_items, A::default())
+    }
 
-        let flags: Vec<Block> = vec![0; num_blocks];
-        let open_blocks: Vec<usize> = (0..num_blocks).rev().collect();
-        let alloc_blocks: Vec<usize> = Vec::new();
+    fn len(&self) -> usize {
+        return self.num_items
+    }
 
-        return Self {
-            items,
-            num_items,
+    fn capacity(&self) -> usize {
+        return self.items.len()
+    }
 
-            flags,
-            open_blocks,
-            alloc_blocks,
-        }
+    fn stats(&self) -> PoolStats {
+        let num_partial_blocks: usize = self.flags.iter()
+            .filter(|flags: &&Block| **flags != EMPTY_BLOCK && **flags != FULL_BLOCK)
+            .count();
+        return PoolStats::new(self.num_items, self.items.len(), self.watermarks, FLAGS_PER_BLOCK, num_partial_blocks, self.sorted_iter().next_back().map(|(id, _): (usize, &T)| id))
     }
 
-    fn len(&self) -> usize {
-        return self.num_items
+    fn heap_size_bytes_with<F: FnMut(&T) -> usize>(&self, item_heap_size_bytes: F) -> HeapUsage {
+        return HeapUsage::new()
+            .with("items", vec_heap_size_bytes(&self.items))
+            .with("flags", vec_heap_size_bytes(&self.flags))
+            .with("open_blocks", vec_heap_size_bytes(&self.open_blocks))
+            .with("alloc_blocks", vec_heap_size_bytes(&self.alloc_blocks))
+            .with("history", self.history.heap_size_bytes())
+            .with("item_heap", self.iter().map(item_heap_size_bytes).sum())
+    }
+
+    fn check_invariants(&self) -> Result<(), InvariantError> {
+        if self.items.len() != self.flags.len()*FLAGS_PER_BLOCK {
+            return Err(InvariantError::StorageLengthsDisagree{ num_slots: self.items.len(), num_flags: self.flags.len()*FLAGS_PER_BLOCK })
+        }
+
+        let num_allocated_slots: usize = self.flags.iter().map(|flags: &Block| flags.count_ones() as usize).sum();
+        if num_allocated_slots != self.num_items {
+            return Err(InvariantError::NumItemsDoesntMatchSlots{ num_items: self.num_items, num_allocated_slots })
+        }
+
+        if let Some(block) = find_block_listed_wrongly(&self.open_blocks, self.flags.len(), |block: usize| self.flags[block] != FULL_BLOCK) {
+            return Err(InvariantError::OpenBlocksDisagreeWithFlags{ block })
+        }
+        if let Some(block) = find_block_listed_wrongly(&self.alloc_blocks, self.flags.len(), |block: usize| self.flags[block] != EMPTY_BLOCK) {
+            return Err(InvariantError::AllocBlocksDisagreeWithFlags{ block })
+        }
+        return Ok(())
     }
 
     fn get(&self, id: usize) -> &T {
-        return self.items[id].as_ref().unwrap()
+        self.history.check_access(id);
+        assert!(self.is_allocated(id));
+        return unsafe { self.items[id].assume_init_ref() }
     }
 
     fn get_mut(&mut self, id: usize) -> &mut T {
-        return self.items[id].as_mut().unwrap()
+        self.history.check_access(id);
+        assert!(self.is_allocated(id));
+        return unsafe { self.items[id].assume_init_mut() }
     }
 
     fn allocate(&mut self, item: T) -> usize {
@@ -88,15 +120,17 @@ impl <T: Clone> Pool<T> for Stacks<T> {
         }
 
         let global_bit: usize = open_block*FLAGS_PER_BLOCK + local_bit;
-        assert!(self.items[global_bit].is_none());
-        self.items[global_bit] = Some(item);
+        self.items[global_bit].write(item);
+        self.history.allocated(global_bit);
         self.num_items += 1;
+        self.watermarks.allocated(self.num_items);
 
         return global_bit
     }
 
     fn deallocate(&mut self, id: usize) {
-        assert!(self.items[id].is_some());
+        self.history.check_deallocate(id);
+        assert!(self.is_allocated(id));
         let block: usize = id / FLAGS_PER_BLOCK;
         let local_bit: usize = id % FLAGS_PER_BLOCK;
         self.flags[block] &= !(1 << local_bit);
@@ -115,10 +149,9 @@ impl <T: Clone> Pool<T> for Stacks<T> {
         }
 
         let global_bit: usize = block*FLAGS_PER_BLOCK + local_bit;
-        assert!(self.items[global_bit].is_some());
-        self.items[global_bit] = None;
-
+        self.history.freed(global_bit);
         self.num_items -= 1;
+        unsafe { self.items[global_bit].assume_init_drop() };
     }
 
     fn iter<'a>(&'a self) -> Iter<'a, T> {
@@ -126,11 +159,99 @@ impl <T: Clone> Pool<T> for Stacks<T> {
             &self.items,
             &self.flags,
             &self.alloc_blocks,
+            self.num_items,
         )
     }
 }
 
-impl <T: Clone> Stacks<T> {
+impl <T: Clone, A: Allocator + Clone + Default> PreallocatedPool<T> for Stacks<T, A> {
+    type Alloc = A;
+
+    fn allocator(&self) -> &A {
+        return self.items.allocator()
+    }
+}
+
+impl <T: Clone, A: Allocator + Clone + Default> OrderedPool<T> for Stacks<T, A> {
+    type SortedIter<'a> = SortedIter<'a, T> where Self: 'a, T: 'a;
+    type RangeIter<'a> = SortedIter<'a, T> where Self: 'a, T: 'a; // can count the items in range a block at a time, so this stays exact-size
+
+    fn sorted_iter<'a>(&'a self) -> SortedIter<'a, T> {
+        return SortedIter::new(&self.items, &self.flags, self.num_items)
+    }
+
+    fn iter_range<'a>(&'a self, range: Range<usize>) -> SortedIter<'a, T> {
+        return SortedIter::in_range(&self.items, &self.flags, range)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl <T: Clone + Send + Sync, A: Allocator + Clone + Default> ParallelPool<T> for Stacks<T, A> {
+    type ParIter<'a> = ParIter<'a, T, ParSlots<T>> where Self: 'a, T: 'a;
+    type ParIterMut<'a> = ParIterMut<'a, T, ParSlots<T>> where Self: 'a, T: 'a;
+
+    // Splits on block boundaries, so each thread only ever looks at whole blocks of flags
+    fn par_iter<'a>(&'a self) -> Self::ParIter<'a> {
+        let slots: ParSlots<T> = ParSlots{ items: self.items.as_ptr() as *mut MaybeUninit<T>, flags: self.flags.as_ptr(), num_slots: self.items.len(), alloc_blocks: self.alloc_blocks.as_ptr(), num_alloc_blocks: self.alloc_blocks.len() };
+        return ParIter::new(slots, self.num_items)
+    }
+
+    fn par_iter_mut<'a>(&'a mut self) -> Self::ParIterMut<'a> {
+        let slots: ParSlots<T> = ParSlots{ items: self.items.as_mut_ptr(), flags: self.flags.as_ptr(), num_slots: self.items.len(), alloc_blocks: self.alloc_blocks.as_ptr(), num_alloc_blocks: self.alloc_blocks.len() };
+        return ParIterMut::new(slots, self.num_items)
+    }
+}
+
+impl <T: Clone, A: Allocator + Clone> Stacks<T, A> {
+    pub fn new_in(alloc: A) -> Self {
+        return Self {
+            items: Vec::new_in(alloc.clone()),
+            num_items: 0,
+            watermarks: Watermarks::new(),
+            history: SlotHistories::new_in(alloc.clone()),
+            
+            flags: Vec::new_in(alloc.clone()),
+            open_blocks: Vec::new_in(alloc.clone()),
+            alloc_blocks: Vec::new_in(alloc),
+        }        
+    }
+
+    pub fn with_capacity_in(num_items: usize, alloc: A) -> Self {
+        let num_blocks: usize;
+        if num_items == 0 {
+            num_blocks = 0;
+        }
+        else {
+            num_blocks = ((num_items-1)/FLAGS_PER_BLOCK)+1;
+        };
+        
+        let mut items: Vec<MaybeUninit<T>, A> = Vec::with_capacity_in(num_blocks*FLAGS_PER_BLOCK, alloc.clone());
+        items.resize_with(num_blocks*FLAGS_PER_BLOCK, MaybeUninit::uninit);
+        let num_items: usize = 0;
+
+        let flags: Vec<Block, A> = vec![in alloc.clone(); 0; num_blocks];
+        let mut open_blocks: Vec<usize, A> = Vec::with_capacity_in(num_blocks, alloc.clone());
+        open_blocks.extend((0..num_blocks).rev());
+        let alloc_blocks: Vec<usize, A> = Vec::with_capacity_in(num_blocks, alloc.clone()); // reserved up front so allocate() doesn't have to
+
+        return Self {
+            items,
+            num_items,
+            watermarks: Watermarks::new(),
+            history: SlotHistories::with_capacity_in(num_blocks*FLAGS_PER_BLOCK, alloc),
+
+            flags,
+            open_blocks,
+            alloc_blocks,
+        }
+    }
+
+    fn is_allocated(&self, id: usize) -> bool {
+        let block: usize = id / FLAGS_PER_BLOCK;
+        let local_bit: usize = id % FLAGS_PER_BLOCK;
+        return (self.flags[block] & (1 << local_bit)) != 0
+    }
+
     fn expand_if_needed(&mut self) {
         if !self.open_blocks.is_empty() {
             return
@@ -139,6 +260,7 @@ impl <T: Clone> Stacks<T> {
         assert!(self.alloc_blocks.len() == self.flags.len());
         assert!(self.flags.iter().all(|block: &Block| *block == FULL_BLOCK));
 
+        self.watermarks.grew();
         const GROWTH_FACTOR: usize = 2;
         let old_num_blocks: usize = self.flags.len();
         let new_num_blocks: usize;
@@ -151,29 +273,76 @@ impl <T: Clone> Stacks<T> {
         };
         let new_num_items: usize = new_num_blocks * FLAGS_PER_BLOCK;
         
-        self.items.resize(new_num_items, None);
+        self.items.resize_with(new_num_items, MaybeUninit::uninit);
+        self.history.grow_to(new_num_items);
         self.flags.resize(new_num_blocks, 0);
-        self.open_blocks.append( &mut (old_num_blocks..new_num_blocks).rev().collect() ); 
+        self.open_blocks.extend( (old_num_blocks..new_num_blocks).rev() ); 
         assert!(self.items.len() == self.flags.len()*FLAGS_PER_BLOCK);
     }
 }
 
+impl <T: Clone, A: Allocator> Drop for Stacks<T, A> {
+    fn drop(&mut self) {
+        let leaks: PendingLeakReport = find_leaks(
+            type_name::<Self>(),
+            self.num_items,
+            (0..self.items.len()).filter(|id: &usize| self.flags[id / FLAGS_PER_BLOCK] & (1 << (id % FLAGS_PER_BLOCK)) != 0),
+            |id: usize| self.history.allocated_at(id),
+        );
+
+        for block in self.alloc_blocks.iter() {
+            let mut flags: Block = self.flags[*block];
+            while flags != EMPTY_BLOCK {
+                let local_bit: usize = flags.trailing_zeros() as usize;
+                flags &= !(1 << local_bit);
+                unsafe { self.items[block*FLAGS_PER_BLOCK + local_bit].assume_init_drop() };
+            }
+        }
+
+        leaks.report();
+    }
+}
+
+// Takes the lowest set flag out of a block and returns its index
+fn take_lowest_flag(block: &mut Block) -> usize {
+    let local_bit: usize = block.trailing_zeros() as usize;
+    *block &= !(1 << local_bit);
+    return local_bit
+}
+
+fn take_highest_flag(block: &mut Block) -> usize {
+    let local_bit: usize = FLAGS_PER_BLOCK - 1 - block.leading_zeros() as usize;
+    *block &= !(1 << local_bit);
+    return local_bit
+}
+
+/*
+    The front and the back each hold the flags of the block they're partway
+    through. When there are no more blocks to hand out, whichever end runs dry
+    takes what's left of the other end's block.
+*/
 pub struct Iter<'a, T: Clone> {
-    items: &'a Vec<Option<T>>,
-    flags: &'a Vec<Block>,
+    items: &'a [MaybeUninit<T>],
+    flags: &'a [Block],
     block: Block,
     offset: usize,
+    back_block: Block,
+    back_offset: usize,
     alloc_blocks: slice::Iter<'a, usize>,
+    num_items_left: usize,
 }
 
 impl <'a, T: Clone> Iter<'a, T> {
-    fn new(items: &'a Vec<Option<T>>, flags: &'a Vec<Block>, alloc_blocks: &'a Vec<usize>) -> Self {
+    fn new(items: &'a [MaybeUninit<T>], flags: &'a [Block], alloc_blocks: &'a [usize], num_items: usize) -> Self {
         return Self { 
             items,
             flags,
             block: 0,
             offset: 0,
-            alloc_blocks: alloc_blocks.iter()
+            back_block: 0,
+            back_offset: 0,
+            alloc_blocks: alloc_blocks.iter(),
+            num_items_left: num_items,
         }
     }
 }
@@ -182,6 +351,7 @@ impl <'a, T: Clone> Iterator for Iter<'a, T> {
     type Item = &'a T;
 
     fn next(&mut self) -> Option<Self::Item> {
+        let global_bit: usize;
         if self.block == EMPTY_BLOCK {
             match self.alloc_blocks.next() {
                 Some(block) => {
@@ -189,23 +359,255 @@ impl <'a, T: Clone> Iterator for Iter<'a, T> {
                     self.offset = (*block)*FLAGS_PER_BLOCK;
                 },
 
-                None => return None,
+                None => {
+                    if self.back_block == EMPTY_BLOCK {
+                        return None
+                    }
+                    global_bit = self.back_offset + take_lowest_flag(&mut self.back_block);
+                    self.num_items_left -= 1;
+                    return Some(unsafe { self.items[global_bit].assume_init_ref() })
+                },
             }
         }
 
         assert!(self.block != 0);
-        let local_bit: usize = self.block.trailing_zeros() as usize;
-        self.block &= !(1 << local_bit);
-        let global_bit: usize = self.offset + local_bit;
-        return Some(self.items[global_bit].as_ref().unwrap())
+        global_bit = self.offset + take_lowest_flag(&mut self.block);
+        self.num_items_left -= 1;
+        return Some(unsafe { self.items[global_bit].assume_init_ref() })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        return (self.num_items_left, Some(self.num_items_left))
+    }
+}
+
+impl <'a, T: Clone> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let global_bit: usize;
+        if self.back_block == EMPTY_BLOCK {
+            match self.alloc_blocks.next_back() {
+                Some(block) => {
+                    self.back_block = self.flags[*block];
+                    self.back_offset = (*block)*FLAGS_PER_BLOCK;
+                },
+
+                None => {
+                    if self.block == EMPTY_BLOCK {
+                        return None
+                    }
+                    global_bit = self.offset + take_highest_flag(&mut self.block);
+                    self.num_items_left -= 1;
+                    return Some(unsafe { self.items[global_bit].assume_init_ref() })
+                },
+            }
+        }
+
+        assert!(self.back_block != 0);
+        global_bit = self.back_offset + take_highest_flag(&mut self.back_block);
+        self.num_items_left -= 1;
+        return Some(unsafe { self.items[global_bit].assume_init_ref() })
+    }
+}
+
+impl <'a, T: Clone> ExactSizeIterator for Iter<'a, T> {}
+
+/*
+    Rather than sorting the block indices that iter() follows, this walks every
+    block's flags in order and skips the empty ones. That's a byte per block of
+    capacity, which is about as cheap as reading the allocated blocks' flags
+    anyway, and it doesn't need any scratch space. Both ends work the same way
+    they do in Iter.
+*/
+pub struct SortedIter<'a, T: Clone> {
+    items: &'a [MaybeUninit<T>],
+    flags: Enumerate<slice::Iter<'a, Block>>,
+    curr_flags: Block,
+    curr_offset: usize,
+    back_flags: Block,
+    back_offset: usize,
+    first_block: usize, // index of the block flags starts at
+    num_items_left: usize,
+}
+
+impl <'a, T: Clone> SortedIter<'a, T> {
+    fn new(items: &'a [MaybeUninit<T>], flags: &'a [Block], num_items: usize) -> Self {
+        return Self {
+            items,
+            flags: flags.iter().enumerate(),
+            first_block: 0,
+            curr_flags: EMPTY_BLOCK,
+            curr_offset: 0,
+            back_flags: EMPTY_BLOCK,
+            back_offset: 0,
+            num_items_left: num_items,
+        }
+    }
+
+    /*
+        Starts with the blocks at either end of the range already loaded, masked
+        down to the ids in range, and leaves the blocks between them to be walked
+        as usual. Blocks are counted as they're masked, so the iterator still
+        knows exactly how many items it has left.
+    */
+    fn in_range(items: &'a [MaybeUninit<T>], flags: &'a [Block], range: Range<usize>) -> Self {
+        let end: usize = range.end.min(items.len());
+        let start: usize = range.start.min(end);
+        if start == end {
+            return Self::new(items, &[], 0)
+        }
+
+        let first_block: usize = start / FLAGS_PER_BLOCK;
+        let last_block: usize = (end-1) / FLAGS_PER_BLOCK;
+        let mut curr_flags: Block = flags[first_block] & (Block::MAX << (start % FLAGS_PER_BLOCK));
+        let mut back_flags: Block = flags[last_block] & (Block::MAX >> (FLAGS_PER_BLOCK - 1 - (end-1) % FLAGS_PER_BLOCK));
+        let middle: &'a [Block];
+        if first_block == last_block {
+            curr_flags &= back_flags;
+            back_flags = EMPTY_BLOCK;
+            middle = &[];
+        }
+        else {
+            middle = &flags[first_block+1..last_block];
+        }
+
+        let num_items: usize = (curr_flags.count_ones() + back_flags.count_ones()) as usize
+            + middle.iter().map(|flags: &Block| flags.count_ones() as usize).sum::<usize>();
+        return Self {
+            items,
+            flags: middle.iter().enumerate(),
+            curr_flags,
+            curr_offset: first_block*FLAGS_PER_BLOCK,
+            back_flags,
+            back_offset: last_block*FLAGS_PER_BLOCK,
+            first_block: first_block+1,
+            num_items_left: num_items,
+        }
+    }
+}
+
+impl <'a, T: Clone> Iterator for SortedIter<'a, T> {
+    type Item = (usize, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let id: usize;
+        while self.curr_flags == EMPTY_BLOCK {
+            match self.flags.next() {
+                Some((block, flags)) => {
+                    self.curr_flags = *flags;
+                    self.curr_offset = (self.first_block + block)*FLAGS_PER_BLOCK;
+                },
+
+                None => {
+                    if self.back_flags == EMPTY_BLOCK {
+                        return None
+                    }
+                    id = self.back_offset + take_lowest_flag(&mut self.back_flags);
+                    self.num_items_left -= 1;
+                    return Some((id, unsafe { self.items[id].assume_init_ref() }))
+                },
+            }
+        }
+
+        id = self.curr_offset + take_lowest_flag(&mut self.curr_flags);
+        self.num_items_left -= 1;
+        return Some((id, unsafe { self.items[id].assume_init_ref() }))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        return (self.num_items_left, Some(self.num_items_left))
+    }
+}
+
+impl <'a, T: Clone> DoubleEndedIterator for SortedIter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let id: usize;
+        while self.back_flags == EMPTY_BLOCK {
+            match self.flags.next_back() {
+                Some((block, flags)) => {
+                    self.back_flags = *flags;
+                    self.back_offset = (self.first_block + block)*FLAGS_PER_BLOCK;
+                },
+
+                None => {
+                    if self.curr_flags == EMPTY_BLOCK {
+                        return None
+                    }
+                    id = self.curr_offset + take_highest_flag(&mut self.curr_flags);
+                    self.num_items_left -= 1;
+                    return Some((id, unsafe { self.items[id].assume_init_ref() }))
+                },
+            }
+        }
+
+        id = self.back_offset + take_highest_flag(&mut self.back_flags);
+        self.num_items_left -= 1;
+        return Some((id, unsafe { self.items[id].assume_init_ref() }))
+    }
+}
+
+impl <'a, T: Clone> ExactSizeIterator for SortedIter<'a, T> {}
+
+#[cfg(feature = "rayon")]
+pub struct ParSlots<T> {
+    items: *mut MaybeUninit<T>,
+    flags: *const Block,
+    num_slots: usize,
+    alloc_blocks: *const usize,
+    num_alloc_blocks: usize,
+}
+
+#[cfg(feature = "rayon")]
+impl <T> Clone for ParSlots<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl <T> Copy for ParSlots<T> {}
+
+#[cfg(feature = "rayon")]
+unsafe impl <T> Slots<T> for ParSlots<T> {
+    const SPLIT_ALIGNMENT: usize = FLAGS_PER_BLOCK;
+
+    fn num_slots(&self) -> usize {
+        return self.num_slots
+    }
+
+    fn is_live(&self, id: usize) -> bool {
+        let flags: Block = unsafe { *self.flags.add(id / FLAGS_PER_BLOCK) };
+        return (flags & (1 << (id % FLAGS_PER_BLOCK))) != 0
+    }
+
+    fn item(&self, id: usize) -> *const T {
+        return unsafe { (*self.items.add(id)).as_ptr() }
+    }
+
+    fn item_mut(&self, id: usize) -> *mut T {
+        return unsafe { (*self.items.add(id)).as_mut_ptr() }
+    }
+
+    // Only looks at the blocks with items in them
+    fn split_point(&self, start: usize, end: usize, num_items_wanted: usize) -> (usize, usize) {
+        let alloc_blocks: &[usize] = unsafe { slice::from_raw_parts(self.alloc_blocks, self.num_alloc_blocks) };
+        return split_point_by_blocks(
+            || alloc_blocks.iter().copied(),
+            |block: usize| unsafe { *self.flags.add(block) }.count_ones() as usize,
+            FLAGS_PER_BLOCK, start, end, num_items_wanted,
+        )
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Stacks;
+    use super::{Stacks, FLAGS_PER_BLOCK};
     use crate::testing;
+    use crate::InvariantError;
     use crate::testing::Item; 
+    use crate::testing::Growth;
+    use crate::testing::CountingAlloc;
+    use crate::Pool as _;
+    use std::rc::Rc;
 
     type Pool = Stacks<Item>;
 
@@ -232,12 +634,76 @@ mod tests {
     }
 
     #[test]
+    #[cfg_attr(miri, ignore)] // far too slow to interpret
     fn fuzz_many_pools_few_mutations() {
         testing::fuzz_many_pools_few_mutations::<Pool>();
     }
 
     #[test]
+    #[cfg_attr(miri, ignore)] // far too slow to interpret
     fn fuzz_few_pools_many_mutations() {
         testing::fuzz_few_pools_many_mutations::<Pool>();
     }
+
+    #[test]
+    fn test_sorted_iter() {
+        testing::test_sorted_iter::<Pool>();
+    }
+
+    #[test]
+    fn test_iterators_are_double_ended_and_exact_size() {
+        testing::test_iterators_are_double_ended_and_exact_size::<Pool>();
+    }
+
+    #[test]
+    fn test_iter_range() {
+        testing::test_iter_range::<Pool>();
+    }
+
+    #[test]
+    fn test_stats() {
+        testing::test_stats::<Pool>(Growth::Doubling{ first_capacity: FLAGS_PER_BLOCK }, true);
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn test_par_iter() {
+        testing::test_par_iter::<Pool>();
+    }
+
+    #[test]
+    fn test_all_memory_goes_through_allocator() {
+        testing::test_all_memory_goes_through_allocator::<Stacks<Item, CountingAlloc>>();
+    }
+
+    #[test]
+    fn test_heap_size_matches_allocator() {
+        testing::test_heap_size_matches_allocator::<Stacks<Item, CountingAlloc>>();
+    }
+
+    #[test]
+    fn test_items_are_dropped_exactly_once() {
+        testing::test_items_are_dropped_exactly_once::<Stacks<Rc<()>>>();
+    }
+
+    #[test]
+    fn test_slots_dont_store_a_discriminant() {
+        testing::test_slots_dont_store_a_discriminant::<Stacks<u8, CountingAlloc>, Stacks<[u64; 3], CountingAlloc>>();
+    }
+
+    #[test]
+    fn test_check_invariants_finds_misplaced_blocks() {
+        let mut pool: Pool = Pool::with_capacity(100);
+        pool.allocate(1);
+        assert!(pool.check_invariants().is_ok());
+
+        let open_block: usize = *pool.open_blocks.last().unwrap();
+        pool.open_blocks.push(open_block);
+        assert!(pool.check_invariants() == Err(InvariantError::OpenBlocksDisagreeWithFlags{ block: open_block }));
+        pool.open_blocks.pop();
+
+        let alloc_block: usize = pool.alloc_blocks.pop().unwrap();
+        assert!(pool.check_invariants() == Err(InvariantError::AllocBlocksDisagreeWithFlags{ block: alloc_block }));
+        pool.alloc_blocks.push(alloc_block);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,65 @@
+/*
+    A snapshot of how a pool is using its storage, for comparing backends and
+    keeping an eye on pools in production.
+
+    Blocks are whatever groups of slots a backend keeps track of together: the
+    bytes of flags in Stacks and NotSafe, the words of a BitVec or the bottom
+    level of a HierarchicalBitVec, and the pages in Paged. Backends that track
+    every slot on its own have a block_size of 1, so none of their blocks are
+    ever partially full.
+
+    fragmentation is the fraction of the slots up to and including the highest
+    live id that are free, so 0.0 means the items are packed at the start of the
+    pool and values near 1.0 mean a few items are keeping a mostly empty range
+    of ids alive. Reference's ids are addresses, so it always reports 0.0.
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PoolStats {
+    pub num_items: usize,
+    pub capacity: usize,
+    pub peak_num_items: usize, // the most items the pool has held at once
+    pub num_grows: usize,      // how many times allocate() had to grow the pool's storage
+    pub block_size: usize,
+    pub num_partial_blocks: usize, // blocks with both items and free slots in them
+    pub fragmentation: f64,
+}
+
+impl PoolStats {
+    pub(crate) fn new(num_items: usize, capacity: usize, watermarks: Watermarks, block_size: usize, num_partial_blocks: usize, highest_id: Option<usize>) -> Self {
+        let fragmentation: f64 = match highest_id {
+            Some(highest_id) => ((highest_id + 1 - num_items) as f64) / ((highest_id + 1) as f64),
+            None => 0.0,
+        };
+
+        return Self {
+            num_items,
+            capacity,
+            peak_num_items: watermarks.peak_num_items,
+            num_grows: watermarks.num_grows,
+            block_size,
+            num_partial_blocks,
+            fragmentation,
+        }
+    }
+}
+
+// What every backend keeps track of as it goes, since it can't be worked out from the pool afterwards
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Watermarks {
+    peak_num_items: usize,
+    num_grows: usize,
+}
+
+impl Watermarks {
+    pub(crate) fn new() -> Self {
+        return Self::default()
+    }
+
+    pub(crate) fn allocated(&mut self, num_items: usize) {
+        self.peak_num_items = self.peak_num_items.max(num_items);
+    }
+
+    pub(crate) fn grew(&mut self) {
+        self.num_grows += 1;
+    }
+}
@@ -1,411 +1,980 @@
-use std::hash::{Hash, Hasher};
 use std::collections::HashMap;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::{Cell, RefCell};
+use std::ptr::NonNull;
+use std::rc::Rc;
+use allocator_api2::alloc::{Allocator, AllocError};
 use rand::Rng;
 use rand::SeedableRng;
 use rand_xoshiro::Xoshiro256StarStar;
-use super::Pool;
-use super::reference::Reference;
+use super::{Pool, OrderedPool, PreallocatedPool, PoolStats, HeapUsage, Leak, LeakReport, LeakHandler, set_leak_handler};
+use super::conformance;
+use super::bounded::Bounded;
+use super::guard::{allocate_scoped, SlotGuard};
+use super::tracked::{Tracked, Change};
+use super::observed::{Observed, PoolObserver};
+use super::recording::{Recording, Trace, TraceOp};
+use super::simple::Simple;
+#[cfg(feature = "rayon")]
+use super::ParallelPool;
+#[cfg(feature = "rayon")]
+use rayon::iter::ParallelIterator;
 
 pub type Item = i32;
 
-// Should panic
+/*
+    The standard suite lives in conformance, so that it can be run against
+    pools outside this crate too. These run it with the items and seed the
+    backends here have always been tested with.
+*/
 pub fn test_invalid_get_to_empty_pool<T: Pool<Item>>() {
-    let pool: T = Pool::new();
-    pool.get(3);
+    conformance::test_invalid_get_to_empty_pool::<Item, T>();
 }
 
-// Should panic
 pub fn test_invalid_get_to_nonempty_pool<T: Pool<Item>>() {
-    let mut pool: T = Pool::new();
+    conformance::test_invalid_get_to_nonempty_pool::<Item, T>();
+}
+
+pub fn test_one_item<T: Pool<Item>>() {
+    conformance::test_one_item::<Item, T>();
+}
+
+pub fn test_many_items<T: Pool<Item>>() {
+    conformance::test_many_items::<Item, T>();
+}
+
+pub fn test_items_are_dropped_exactly_once<T: Pool<Rc<()>>>() {
+    conformance::test_items_are_dropped_exactly_once::<T>();
+}
+
+pub fn fuzz_many_pools_few_mutations<T: Pool<Item>>() {
+    conformance::fuzz_many_pools_few_mutations::<Item, T>(conformance::DEFAULT_RNG_SEED);
+}
+
+pub fn fuzz_few_pools_many_mutations<T: Pool<Item>>() {
+    conformance::fuzz_few_pools_many_mutations::<Item, T>(conformance::DEFAULT_RNG_SEED);
+}
+
+/*
+    For the wrappers, which are checked against every backend the same way.
+    test_every_backend!(test_slot_guard) writes a #[test] per backend, named
+    after it, that calls testing::test_slot_guard::<Backend<Item>>(). Several
+    harness functions can be given at once, and ending the list with
+    `except Reference` leaves out the backend the wrapper can't be used with.
+    Ending it with `for $type` instead makes pools of that type of item.
+*/
+macro_rules! test_every_backend {
+    ($($test:ident),+) => {
+        $crate::testing::test_every_backend!($($test),+ ; for $crate::testing::Item);
+    };
+
+    ($($test:ident),+ ; except Reference) => {
+        $crate::testing::test_every_backend!(@except_reference $crate::testing::Item; $($test),+);
+    };
+
+    ($($test:ident),+ ; for $item:ty) => {
+        $crate::testing::test_every_backend!(@except_reference $item; $($test),+);
+        $crate::testing::test_every_backend!(@backend test_reference, $crate::Reference<$item>; $($test),+);
+    };
+
+    (@except_reference $item:ty; $($test:ident),+) => {
+        $crate::testing::test_every_backend!(@backend test_simple, $crate::Simple<$item>; $($test),+);
+        $crate::testing::test_every_backend!(@backend test_freelist, $crate::FreeList<$item>; $($test),+);
+        $crate::testing::test_every_backend!(@backend test_stacks, $crate::Stacks<$item>; $($test),+);
+        $crate::testing::test_every_backend!(@backend test_notsafe, $crate::NotSafe<$item>; $($test),+);
+        $crate::testing::test_every_backend!(@backend test_bit_flags, $crate::BitFlags<$item>; $($test),+);
+        $crate::testing::test_every_backend!(@backend test_bool_flags, $crate::BoolFlags<$item>; $($test),+);
+        $crate::testing::test_every_backend!(@backend test_hierarchical_flags, $crate::HierarchicalFlags<$item>; $($test),+);
+        $crate::testing::test_every_backend!(@backend test_paged, $crate::Paged<$item>; $($test),+);
+        $crate::testing::test_every_backend!(@backend test_sparse_set, $crate::SparseSet<$item>; $($test),+);
+    };
+
+    (@backend $name:ident, $backend:ty; $($test:ident),+) => {
+        #[test]
+        fn $name() {
+            $( $crate::testing::$test::<$backend>(); )+
+        }
+    };
+}
+pub(crate) use test_every_backend;
+
+fn generate_random_item<T: Rng>(rng: &mut T) -> Item {
+    return rng.gen_range(Item::MIN..=Item::MAX)
+}
+
+pub fn test_bounded_refuses_to_grow<T: PreallocatedPool<Item>>() {
+    const CAPACITY: usize = 100;
+    let mut pool: Bounded<Item, T> = Bounded::with_capacity(CAPACITY);
+    assert!(pool.capacity() == CAPACITY);
+    assert!(pool.remaining() == CAPACITY);
+
     let mut ids: Vec<usize> = Vec::new();
-    for _ in 0..128 {
-        let id: usize = pool.allocate(0);
-        ids.push(id);
+    for i in 0..CAPACITY {
+        assert!(!pool.is_full());
+        ids.push( pool.try_allocate(i as Item).unwrap() );
+        assert!(pool.remaining() == CAPACITY-(i+1));
     }
-
-    pool.deallocate(ids[0]);
-    pool.get(ids[0]);
+    assert!(pool.is_full());
+    assert!(pool.try_allocate(-1) == Err(-1));
+    assert!(pool.len() == CAPACITY);
+
+    pool.deallocate(ids[7]);
+    assert!(!pool.is_full());
+    assert!(pool.remaining() == 1);
+    let id: usize = pool.try_allocate(-7).unwrap();
+    assert!(*pool.get(id) == -7);
+    assert!(pool.try_allocate(-2) == Err(-2));
 }
 
-pub fn test_one_item<T: Pool<Item>>() {
-    let mut pool: T = Pool::new();
+pub fn test_bounded_never_allocates<T: PreallocatedPool<Item> + OrderedPool<Item>>() {
+    const CAPACITY: usize = 1000;
+    let mut pool: Bounded<Item, T> = Bounded::with_capacity(CAPACITY);
+    let mut ids: Vec<usize> = Vec::with_capacity(CAPACITY);
 
-    let id: usize = pool.allocate(72);
-    assert!(pool.len() == 1);
-    assert!(pool.iter().cloned().collect::<Vec<Item>>() == vec![72]);
+    let num_allocations: usize = count_allocations(|| {
+        for _ in 0..3 {
+            for i in 0..CAPACITY {
+                ids.push( pool.try_allocate(i as Item).unwrap() );
+            }
+            assert!(pool.try_allocate(0).is_err());
+            assert!(pool.iter().count() == CAPACITY);
 
-    pool.deallocate(id);
-    assert!(pool.len() == 0);
-    assert!(pool.iter().next().is_none());
+            // Free every other item, then the rest, so blocks get reopened and emptied in both orders
+            for i in (0..CAPACITY).step_by(2) {
+                *pool.get_mut(ids[i]) += 1;
+                pool.deallocate(ids[i]);
+            }
+            assert!(pool.iter().rev().count() == CAPACITY/2);
+            assert!(pool.sorted_iter().count() == CAPACITY/2);
+            assert!(pool.iter_range(0..CAPACITY/2).rev().count() <= CAPACITY/2);
+            for i in (1..CAPACITY).step_by(2) {
+                pool.deallocate(ids[i]);
+            }
+            ids.clear();
+        }
+    });
+    assert!(num_allocations == 0, "{} heap allocations after construction", num_allocations);
 }
 
-pub fn test_many_items<T: Pool<Item>>() {
-    let mut pool: T = Pool::new();
-    let mut map: HashMap<usize, Item> = HashMap::new();
-    for i in 0..3827 {
-        let item: Item = i;
-        let id: usize = pool.allocate(item);
-        map.insert(id, item);
+pub fn test_sorted_iter<T: OrderedPool<Item>>() {
+    const RNG_SEED: u64 = 34;
+    let mut rng: Xoshiro256StarStar = Xoshiro256StarStar::seed_from_u64(RNG_SEED);
+    let mut pool: T = T::new();
+    assert!(pool.sorted_iter().next().is_none());
+
+    let mut ids: Vec<usize> = Vec::new();
+    for _ in 0..10 {
+        for _ in 0..rng.gen_range(0..200) {
+            ids.push( pool.allocate(generate_random_item(&mut rng)) );
+        }
+        for _ in 0..rng.gen_range(0..=ids.len()) {
+            let id: usize = ids.swap_remove(rng.gen_range(0..ids.len()));
+            pool.deallocate(id);
+        }
+
+        let sorted: Vec<(usize, &Item)> = pool.sorted_iter().collect();
+        ids.sort();
+        assert!(sorted.len() == pool.len());
+        assert!(sorted.iter().map(|(id, _): &(usize, &Item)| *id).eq(ids.iter().copied()));
+        for (id, item) in sorted {
+            assert!(std::ptr::eq(item, pool.get(id)));
+        }
     }
+}
 
-    for id in map.clone().keys() {
-        if id/2 % 3 == 0 {
-            pool.deallocate(*id);
-            map.remove(id);
+pub fn test_iterators_are_double_ended_and_exact_size<T: OrderedPool<Item>>() {
+    const RNG_SEED: u64 = 35;
+    let mut rng: Xoshiro256StarStar = Xoshiro256StarStar::seed_from_u64(RNG_SEED);
+    let mut pool: T = T::new();
+    let mut ids: Vec<usize> = Vec::new();
+    for round in 0..40 {
+        // Small pools first, so that both ends often meet inside a single block or page
+        let max_num_allocations: usize = if round < 20 { 10 } else { 300 };
+        for _ in 0..rng.gen_range(0..max_num_allocations) {
+            ids.push( pool.allocate(generate_random_item(&mut rng)) );
+        }
+        for _ in 0..rng.gen_range(0..=ids.len()) {
+            let id: usize = ids.swap_remove(rng.gen_range(0..ids.len()));
+            pool.deallocate(id);
         }
+
+        let forwards: Vec<*const Item> = pool.iter().map(|item: &Item| item as *const Item).collect();
+        let mut backwards: Vec<*const Item> = pool.iter().rev().map(|item: &Item| item as *const Item).collect();
+        backwards.reverse();
+        assert!(forwards.len() == pool.len());
+        assert!(forwards == backwards);
+        let sorted_forwards: Vec<usize> = pool.sorted_iter().map(|(id, _): (usize, &Item)| id).collect();
+        let mut sorted_backwards: Vec<usize> = pool.sorted_iter().rev().map(|(id, _): (usize, &Item)| id).collect();
+        sorted_backwards.reverse();
+        assert!(sorted_forwards == sorted_backwards);
+
+        // Take from both ends at random until they meet
+        let mut iter: T::Iter<'_> = pool.iter();
+        let mut sorted_iter: T::SortedIter<'_> = pool.sorted_iter();
+        let mut front: Vec<*const Item> = Vec::new();
+        let mut back: Vec<*const Item> = Vec::new();
+        let mut sorted_front: Vec<usize> = Vec::new();
+        let mut sorted_back: Vec<usize> = Vec::new();
+        for num_taken in 0..pool.len() {
+            assert!(iter.len() == pool.len() - num_taken);
+            assert!(iter.size_hint() == (iter.len(), Some(iter.len())));
+            assert!(sorted_iter.len() == pool.len() - num_taken);
+            if rng.gen_bool(0.5) {
+                front.push(iter.next().unwrap());
+                sorted_front.push(sorted_iter.next().unwrap().0);
+            }
+            else {
+                back.push(iter.next_back().unwrap());
+                sorted_back.push(sorted_iter.next_back().unwrap().0);
+            }
+        }
+        assert!(iter.len() == 0);
+        assert!(iter.next().is_none() && iter.next_back().is_none());
+        assert!(sorted_iter.next().is_none() && sorted_iter.next_back().is_none());
+        front.extend(back.iter().rev());
+        sorted_front.extend(sorted_back.iter().rev());
+        assert!(front == forwards);
+        assert!(sorted_front == sorted_forwards);
     }
+}
+
+pub fn test_iter_range<T: OrderedPool<Item>>() {
+    const RNG_SEED: u64 = 37;
+    let mut rng: Xoshiro256StarStar = Xoshiro256StarStar::seed_from_u64(RNG_SEED);
+    let mut pool: T = T::new();
+    let mut ids: Vec<usize> = Vec::new();
+    for round in 0..40 {
+        let max_num_allocations: usize = if round < 20 { 10 } else { 3000 };
+        for _ in 0..rng.gen_range(0..max_num_allocations) {
+            ids.push( pool.allocate(generate_random_item(&mut rng)) );
+        }
+        for _ in 0..rng.gen_range(0..=ids.len()) {
+            let id: usize = ids.swap_remove(rng.gen_range(0..ids.len()));
+            pool.deallocate(id);
+        }
 
-    assert!(pool.len() == map.len());
-    
-    for id in map.keys() {
-        assert!(pool.get(*id) == map.get(id).unwrap());
+        let sorted: Vec<(usize, *const Item)> = pool.sorted_iter().map(|(id, item): (usize, &Item)| (id, item as *const Item)).collect();
+        let max_id: usize = sorted.last().map(|(id, _): &(usize, *const Item)| *id + 1).unwrap_or(0);
+        for _ in 0..20 {
+            // Ranges that go past the last id, and backwards ones, shouldn't panic
+            let start: usize = rng.gen_range(0..=max_id+100);
+            let end: usize = rng.gen_range(0..=max_id+100);
+            let expected: Vec<(usize, *const Item)> = sorted.iter()
+                .filter(|(id, _): &&(usize, *const Item)| start <= *id && *id < end)
+                .copied()
+                .collect();
+
+            let forwards: Vec<(usize, *const Item)> = pool.iter_range(start..end).map(|(id, item): (usize, &Item)| (id, item as *const Item)).collect();
+            let mut backwards: Vec<(usize, *const Item)> = pool.iter_range(start..end).rev().map(|(id, item): (usize, &Item)| (id, item as *const Item)).collect();
+            backwards.reverse();
+            assert!(forwards == expected);
+            assert!(backwards == expected);
+
+            // Take from both ends at random until they meet
+            let mut iter: T::RangeIter<'_> = pool.iter_range(start..end);
+            let mut front: Vec<usize> = Vec::new();
+            let mut back: Vec<usize> = Vec::new();
+            for _ in 0..expected.len() {
+                if rng.gen_bool(0.5) {
+                    front.push(iter.next().unwrap().0);
+                }
+                else {
+                    back.push(iter.next_back().unwrap().0);
+                }
+            }
+            assert!(iter.next().is_none());
+            assert!(iter.next_back().is_none());
+            back.reverse();
+            front.extend(back);
+            assert!(front.into_iter().eq(expected.iter().map(|(id, _): &(usize, *const Item)| *id)));
+        }
     }
+}
+
+#[cfg(feature = "rayon")]
+pub fn test_par_iter<T: ParallelPool<Item>>() {
+    const RNG_SEED: u64 = 36;
+    let mut rng: Xoshiro256StarStar = Xoshiro256StarStar::seed_from_u64(RNG_SEED);
+    let threads: rayon::ThreadPool = rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+    let mut pool: T = T::new();
+    let mut ids: Vec<usize> = Vec::new();
+    for round in 0..20 {
+        // Big enough for rayon to actually split, with stretches of free slots for it to split around
+        let max_num_allocations: usize = if round < 10 { 10 } else { 5000 };
+        for _ in 0..rng.gen_range(0..max_num_allocations) {
+            ids.push( pool.allocate(generate_random_item(&mut rng)) );
+        }
+        for _ in 0..rng.gen_range(0..=ids.len()) {
+            let id: usize = ids.swap_remove(rng.gen_range(0..ids.len()));
+            pool.deallocate(id);
+        }
+
+        let mut expected: Vec<usize> = pool.iter().map(|item: &Item| item as *const Item as usize).collect();
+        let mut actual: Vec<usize> = pool.par_iter().map(|item: &Item| item as *const Item as usize).collect();
+        expected.sort();
+        actual.sort();
+        assert!(actual == expected);
+        assert!(pool.par_iter().count() == pool.len());
+
+        // Each piece rayon splits the pool into folds to one count, and the first splits should halve the items
+        let par_iter: T::ParIter<'_> = pool.par_iter();
+        let piece_lens: Vec<usize> = threads.install(move || par_iter.fold(|| 0, |n: usize, _: &Item| n+1).collect());
+        assert!(piece_lens.iter().sum::<usize>() == pool.len());
+        if pool.len() >= 1000 {
+            assert!(piece_lens.len() >= 2);
+            assert!(*piece_lens.iter().max().unwrap() <= pool.len() * 3/4);
+        }
 
-    let mut pool_items: Vec<Item> = pool.iter().cloned().collect();
-    let mut map_items: Vec<Item> = map.values().cloned().collect();
-    pool_items.sort();
-    map_items.sort();
-    assert!(pool_items == map_items);
+        let before: Vec<Item> = pool.iter().copied().collect();
+        pool.par_iter_mut().for_each(|item: &mut Item| *item = item.wrapping_add(1));
+        let after: Vec<Item> = pool.iter().copied().collect();
+        assert!(after.len() == before.len());
+        assert!(before.iter().zip(after.iter()).all(|(before, after): (&Item, &Item)| before.wrapping_add(1) == *after));
+    }
 }
 
-pub fn fuzz_many_pools_few_mutations<T: Pool<Item>>() {
-    const NUM_POOLS_TO_FUZZ: usize = 10_000;
-    const MAX_NUM_MUTATIONS: usize = 10;
-    fuzz_many_item_pools::<T>(NUM_POOLS_TO_FUZZ, MAX_NUM_MUTATIONS);
+pub fn test_tracked_changes<T: PreallocatedPool<Item>>() {
+    const RNG_SEED: u64 = 38;
+    let mut rng: Xoshiro256StarStar = Xoshiro256StarStar::seed_from_u64(RNG_SEED);
+    let mut pool: Tracked<Item, T> = Tracked::new();
+    let mut replica: HashMap<usize, Item> = HashMap::new(); // what the other side saw at the last drain
+    let mut ids: Vec<usize> = Vec::new();
+    for _ in 0..100 {
+        let mut mutated: Vec<usize> = Vec::new();
+        for _ in 0..rng.gen_range(0..300) {
+            match rng.gen_range(0..3) {
+                0 => ids.push( pool.allocate(generate_random_item(&mut rng)) ),
+                1 if !ids.is_empty() => pool.deallocate( ids.swap_remove(rng.gen_range(0..ids.len())) ),
+                2 if !ids.is_empty() => {
+                    let id: usize = ids[rng.gen_range(0..ids.len())];
+                    *pool.get_mut(id) = generate_random_item(&mut rng);
+                    mutated.push(id);
+                },
+                _ => {},
+            }
+        }
+
+        let changes: Vec<Change> = pool.drain_changed().collect();
+        let mut last_id: Option<usize> = None;
+        for change in changes.iter() {
+            let id: usize = match *change {
+                Change::Added(id) => {
+                    assert!(replica.insert(id, *pool.get(id)).is_none());
+                    id
+                },
+                Change::Changed(id) => {
+                    assert!(mutated.contains(&id));
+                    assert!(replica.insert(id, *pool.get(id)).is_some());
+                    id
+                },
+                Change::Removed(id) => {
+                    assert!(replica.remove(&id).is_some());
+                    id
+                },
+            };
+
+            // Ascending, except for a reused id's Added following its Removed
+            if let Some(last_id) = last_id {
+                assert!(last_id < id || (last_id == id && matches!(change, Change::Added(_))));
+            }
+            last_id = Some(id);
+        }
+
+        assert!(replica.len() == pool.len());
+        for id in ids.iter() {
+            assert!(replica[id] == *pool.get(*id));
+        }
+        assert!(pool.drain_changed().next().is_none());
+    }
 }
 
-pub fn fuzz_few_pools_many_mutations<T: Pool<Item>>() {
-    const NUM_POOLS_TO_FUZZ: usize = 100;
-    const MAX_NUM_MUTATIONS: usize = 1000;
-    fuzz_many_item_pools::<T>(NUM_POOLS_TO_FUZZ, MAX_NUM_MUTATIONS);
+struct Mirror {
+    items: HashMap<usize, Item>,
+    capacity: usize,
 }
 
-// Hey, it's like that Leetcode problem
-// https://leetcode.com/problems/insert-delete-getrandom-o1/
-#[derive(Clone, Copy, PartialEq, Eq)]
-struct PairOfIds {
-    id_in_test: usize,
-    id_in_reference: usize,
+impl PoolObserver<Item> for Mirror {
+    fn on_allocate(&mut self, id: usize, item: &Item) {
+        assert!(self.items.insert(id, *item).is_none());
+    }
+
+    fn on_deallocate(&mut self, id: usize, item: &Item) {
+        assert!(self.items.remove(&id) == Some(*item));
+    }
+
+    fn on_grow(&mut self, old_capacity: usize, new_capacity: usize) {
+        assert!(old_capacity <= self.capacity); // Reference's capacity shrinks along with it, the rest never shrink
+        assert!(new_capacity > old_capacity);
+        self.capacity = new_capacity;
+    }
 }
 
-impl Hash for PairOfIds {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        state.write_usize(self.id_in_test);
-        state.write_usize(self.id_in_reference);
+pub fn test_observer_hooks<T: OrderedPool<Item>>() {
+    const RNG_SEED: u64 = 39;
+    let mut rng: Xoshiro256StarStar = Xoshiro256StarStar::seed_from_u64(RNG_SEED);
+    let mut pool: Observed<Item, T, Mirror> = Observed::new(Mirror{ items: HashMap::new(), capacity: 0 });
+    pool.observer_mut().capacity = pool.capacity();
+    let mut ids: Vec<usize> = Vec::new();
+    for round in 0..100 {
+        for _ in 0..rng.gen_range(0..100) {
+            match rng.gen_range(0..4) {
+                0 => ids.push( pool.allocate(generate_random_item(&mut rng)) ),
+                1 if !ids.is_empty() => pool.deallocate( ids.swap_remove(rng.gen_range(0..ids.len())) ),
+                2 => {
+                    let items: Vec<Item> = (0..rng.gen_range(0..20)).map(|_| generate_random_item(&mut rng)).collect();
+                    let new_ids: Vec<usize> = pool.allocate_many(items.iter().copied());
+                    assert!(new_ids.iter().map(|id: &usize| *pool.get(*id)).eq(items.iter().copied()));
+                    ids.extend(new_ids);
+                },
+                3 => {
+                    let divisor: Item = rng.gen_range(2..5);
+                    pool.retain(|_, item: &Item| item % divisor != 0);
+                    ids.retain(|id: &usize| pool.observer().items.contains_key(id));
+                },
+                _ => {},
+            }
+        }
+        if round % 10 == 9 {
+            pool.clear();
+            ids.clear();
+        }
+
+        let mirror: &Mirror = pool.observer();
+        assert!(mirror.items.len() == pool.len());
+        assert!(pool.capacity() <= mirror.capacity); // never grew without telling the observer
+        assert!(pool.capacity() >= pool.len());
+        for (id, item) in pool.sorted_iter() {
+            assert!(mirror.items[&id] == *item);
+        }
     }
 }
 
-struct Allocations {
-    map: HashMap<PairOfIds, usize>, // ids -> index in vec that contains ids
-    vec: Vec<PairOfIds>,
+// How a backend's capacity grows when it runs out of slots, which is what test_stats() expects num_grows to count
+pub enum Growth {
+    Doubling{ first_capacity: usize },
+    ByPage{ page_size: usize },
+    EveryAllocation,
 }
 
-impl Allocations {
-    pub fn new() -> Self {
-        return Self {
-            map: HashMap::default(),
-            vec: Vec::new(),
+impl Growth {
+    fn num_grows_to_fit(&self, num_items: usize) -> usize {
+        match self {
+            Growth::Doubling{ first_capacity } => {
+                let mut capacity: usize = 0;
+                let mut num_grows: usize = 0;
+                while capacity < num_items {
+                    capacity = if capacity == 0 { *first_capacity } else { capacity*2 };
+                    num_grows += 1;
+                }
+                return num_grows
+            },
+            Growth::ByPage{ page_size } => return num_items.div_ceil(*page_size),
+            Growth::EveryAllocation => return num_items,
         }
     }
+}
 
-    pub fn add(&mut self, ids: PairOfIds) {
-        self.map.insert(ids, self.vec.len());
-        self.vec.push(ids);
+// Reference's ids are addresses rather than slots, so it's the one backend that can't report fragmentation
+pub fn test_stats<T: OrderedPool<Item>>(growth: Growth, reports_fragmentation: bool) {
+    const RNG_SEED: u64 = 40;
+    const NUM_ITEMS: usize = 500;
+    let mut rng: Xoshiro256StarStar = Xoshiro256StarStar::seed_from_u64(RNG_SEED);
+    let mut pool: T = T::new();
+    let stats: PoolStats = pool.stats();
+    assert!(stats.num_items == 0 && stats.peak_num_items == 0 && stats.num_grows == 0);
+    assert!(stats.fragmentation == 0.0);
+
+    let mut ids: Vec<usize> = (0..NUM_ITEMS).map(|_| pool.allocate(generate_random_item(&mut rng))).collect();
+    let stats: PoolStats = pool.stats();
+    assert!(stats.num_items == NUM_ITEMS);
+    assert!(stats.peak_num_items == NUM_ITEMS);
+    assert!(stats.capacity == pool.capacity());
+    assert!(stats.num_grows == growth.num_grows_to_fit(NUM_ITEMS));
+
+    // Leave every other item in id order, so any backend that groups slots into blocks ends up with partial ones
+    ids.sort();
+    for id in ids.iter().step_by(2) {
+        pool.deallocate(*id);
     }
+    let stats: PoolStats = pool.stats();
+    assert!(stats.num_items == NUM_ITEMS/2);
+    assert!(stats.peak_num_items == NUM_ITEMS);
+    assert!(stats.num_partial_blocks <= stats.capacity.div_ceil(stats.block_size));
+    if stats.block_size > 1 {
+        assert!(stats.num_partial_blocks > 0);
+    }
+    assert!(stats.fragmentation >= 0.0 && stats.fragmentation < 1.0);
 
-    // This function right here is the reason for this data structure to exist- it
-    // removes ids in O(1) time. It's a lot faster to run tests with this vs something 
-    // like a Vec with O(n) removal 
-    pub fn remove(&mut self, removed_ids: PairOfIds) {        
-        let index_of_removed_ids: usize = *self.map.get(&removed_ids).unwrap();
-        let index_of_last_ids: usize = self.vec.len()-1;
-        if index_of_removed_ids != index_of_last_ids {
-            let last_ids: PairOfIds = self.vec[ index_of_last_ids ];
-            self.vec[ index_of_removed_ids ] = last_ids;
-            self.map.insert(last_ids, index_of_removed_ids);
+    // With only the highest id left, everything below it is a hole
+    let highest_id: usize = pool.sorted_iter().next_back().unwrap().0;
+    for id in ids.iter().skip(1).step_by(2) {
+        if *id != highest_id {
+            pool.deallocate(*id);
         }
-        self.vec.pop().unwrap();
-        self.map.remove(&removed_ids);
+    }
+    let stats: PoolStats = pool.stats();
+    assert!(stats.num_items == 1);
+    if reports_fragmentation {
+        assert!(stats.fragmentation == (highest_id as f64) / ((highest_id + 1) as f64));
+    }
+    else {
+        assert!(stats.fragmentation == 0.0);
     }
 
-    pub fn get_random_pair_of_ids<T: Rng>(&self, rng: &mut T) -> Option<PairOfIds> {
-        if self.vec.is_empty() {
-            return None
+    // Pools that size their storage up front shouldn't have to grow into it
+    let mut pool: T = T::with_capacity(NUM_ITEMS);
+    if pool.capacity() >= NUM_ITEMS {
+        for _ in 0..NUM_ITEMS {
+            pool.allocate(generate_random_item(&mut rng));
         }
+        assert!(pool.stats().num_grows == 0);
+    }
+}
+
+// Implements only the methods Pool requires, by forwarding them, so that the provided ones are what gets tested
+pub struct OnlyRequiredMethods<P>(P);
+
+impl <P: Pool<Item>> Pool<Item> for OnlyRequiredMethods<P> {
+    type Iter<'a> = P::Iter<'a> where Self: 'a;
 
-        let index: usize = rng.gen_range(0..self.len());
-        return Some( self.vec[index] )
+    fn new() -> Self {
+        return Self(P::new())
     }
 
-    pub fn len(&self) -> usize {
-        return self.map.len()
+    fn with_capacity(num_items: usize) -> Self {
+        return Self(P::with_capacity(num_items))
     }
 
-    pub fn pairs_of_ids(&self) -> impl Iterator<Item=&PairOfIds> {
-        return self.vec.iter()
+    fn len(&self) -> usize {
+        return self.0.len()
     }
-}
 
-// Constructions
-const NEW: usize = 0;
-const WITH_CAPACITY: usize = 1;
+    fn get(&self, id: usize) -> &Item {
+        return self.0.get(id)
+    }
 
-#[derive(Clone, Copy, Debug)]
-enum Construction {
-    New,
-    WithCapacity{capacity: usize},
+    fn get_mut(&mut self, id: usize) -> &mut Item {
+        return self.0.get_mut(id)
+    }
+
+    fn allocate(&mut self, item: Item) -> usize {
+        return self.0.allocate(item)
+    }
+
+    fn deallocate(&mut self, id: usize) {
+        self.0.deallocate(id);
+    }
+
+    fn iter<'a>(&'a self) -> Self::Iter<'a> {
+        return self.0.iter()
+    }
 }
 
-fn generate_random_construction<T: Rng>(rng: &mut T) -> Construction {
-    const MAX_CAPACITY: usize = 10_000;
-    let construction: usize = rng.gen_range(NEW..=WITH_CAPACITY);
-    match construction {
-        NEW => {
-            return Construction::New
-        },
+pub fn test_provided_methods<T: Pool<Item>>() {
+    const RNG_SEED: u64 = 39;
+    let mut rng: Xoshiro256StarStar = Xoshiro256StarStar::seed_from_u64(RNG_SEED);
+    let mut pool: OnlyRequiredMethods<T> = OnlyRequiredMethods::new();
+    assert!(pool.capacity() == 0);
 
-        WITH_CAPACITY => {
-            let capacity: usize = rng.gen_range(0..MAX_CAPACITY);
-            return Construction::WithCapacity{ capacity }
-        },
+    let mut ids: Vec<usize> = (0..100).map(|_| pool.allocate(generate_random_item(&mut rng))).collect();
+    for id in ids.drain(..50) {
+        pool.deallocate(id);
+    }
+    assert!(pool.capacity() == pool.len());
+    let stats: PoolStats = pool.stats();
+    assert!(stats.num_items == 50 && stats.capacity == 50 && stats.peak_num_items == 50);
+    assert!(stats.num_grows == 0 && stats.block_size == 1 && stats.num_partial_blocks == 0);
+    assert!(stats.fragmentation == 0.0);
+    assert!(pool.heap_size_bytes().components() == [("item_heap", 0)]);
+    assert!(pool.heap_size_bytes_with(|_: &Item| 3).total_bytes() == 150);
+    assert!(pool.check_invariants() == Ok(()));
+}
 
-        _ => unreachable!(),
+// Records a random workload, then replays its trace against Simple and checks both pools end up holding the same items
+pub fn test_recording_replays<T: Pool<Item>>() {
+    const RNG_SEED: u64 = 42;
+    const NUM_OPS: usize = 5000;
+    let mut rng: Xoshiro256StarStar = Xoshiro256StarStar::seed_from_u64(RNG_SEED);
+    let mut pool: Recording<Item, T> = Recording::new();
+    let mut ids: Vec<usize> = Vec::new();
+    let mut num_allocations: usize = 0;
+    for _ in 0..NUM_OPS {
+        match rng.gen_range(0..10) {
+            0..=3 => {
+                ids.push( pool.allocate(num_allocations as Item) ); // every item is its handle, so the replay can tell them apart
+                num_allocations += 1;
+            }
+            4..=5 if !ids.is_empty() => {
+                let id: usize = ids.swap_remove(rng.gen_range(0..ids.len()));
+                pool.deallocate(id);
+            }
+            6..=7 if !ids.is_empty() => {
+                pool.get(ids[rng.gen_range(0..ids.len())]);
+            }
+            8 if !ids.is_empty() => {
+                pool.get_mut(ids[rng.gen_range(0..ids.len())]);
+            }
+            _ => {
+                pool.iter().count();
+            }
+        }
+    }
+
+    let (pool, trace): (T, Trace) = pool.into_parts();
+    let trace: Trace = Trace::from_bytes(&trace.to_bytes()).unwrap();
+    assert!(trace.item_size() == core::mem::size_of::<Item>());
+    assert!(trace.num_allocations() == num_allocations);
+
+    let mut replayed: Simple<Item> = Pool::new();
+    let mut handles: Vec<Option<usize>> = vec![None; trace.num_allocations()];
+    let mut next_handle: usize = 0;
+    for op in trace.ops() {
+        match op {
+            TraceOp::Allocate => {
+                handles[next_handle] = Some( replayed.allocate(next_handle as Item) );
+                next_handle += 1;
+            }
+            TraceOp::Deallocate(handle) => replayed.deallocate(handles[handle].take().unwrap()),
+            TraceOp::Get(handle) => assert!(*replayed.get(handles[handle].unwrap()) == handle as Item),
+            TraceOp::GetMut(handle) => assert!(*replayed.get_mut(handles[handle].unwrap()) == handle as Item),
+            TraceOp::Iter => assert!(replayed.iter().count() == replayed.len()),
+        }
     }
+
+    let mut items: Vec<Item> = pool.iter().copied().collect();
+    let mut replayed_items: Vec<Item> = replayed.iter().copied().collect();
+    items.sort();
+    replayed_items.sort();
+    assert!(items == replayed_items);
 }
 
-// Mutations
-const SET: usize = 0;
-const ALLOCATE: usize = 1;
-const DEALLOCATE: usize = 2;
-const NUM_MUTATIONS: usize = 3;
+// The message a function panics with, which has to be a formatted one
+#[cfg(feature = "debug-checks")]
+fn panic_message<F: FnOnce()>(f: F) -> String {
+    let payload: Box<dyn std::any::Any + Send> = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).unwrap_err();
+    return *payload.downcast::<String>().unwrap()
+}
 
-#[allow(dead_code)]
-#[derive(Clone, Copy, Debug)]
-enum Mutation {
-    Set{id_in_test: usize, id_in_reference: usize, value: Item},
-    Allocate{item: Item},
-    Deallocate{id_in_test: usize, id_in_reference: usize},
+#[cfg(feature = "debug-checks")]
+pub fn test_use_after_free_is_reported<T: Pool<Item>>() {
+    let mut pool: T = Pool::new();
+    let id: usize = pool.allocate(1);
+    pool.allocate(2);
+    let freed_at: u32 = line!() + 1;
+    pool.deallocate(id);
+    let freed_at: String = format!("id {} was freed at {}:{}:", id, file!(), freed_at);
+
+    let accessed_at: u32 = line!() + 1;
+    let message: String = panic_message(|| { pool.get(id); });
+    assert!(message.starts_with(&freed_at));
+    assert!(message.contains(&format!(", accessed at {}:{}:", file!(), accessed_at)));
+    assert!(message.ends_with("(generation 1)"));
+
+    let accessed_at: u32 = line!() + 1;
+    let message: String = panic_message(|| { pool.get_mut(id); });
+    assert!(message.starts_with(&freed_at));
+    assert!(message.contains(&format!(", accessed at {}:{}:", file!(), accessed_at)));
+
+    let freed_again_at: u32 = line!() + 1;
+    let message: String = panic_message(|| pool.deallocate(id));
+    assert!(message.starts_with(&freed_at));
+    assert!(message.contains(&format!(", freed again at {}:{}:", file!(), freed_again_at)));
 }
 
-struct MutationGenerator {
-    tokens: [usize; NUM_MUTATIONS],
-    total_num_tokens: usize,
+// With room for a single item, the slot that was freed is the only one to hand out again, and it's fair game once it has been
+#[cfg(feature = "debug-checks")]
+pub fn test_reused_ids_start_a_new_generation<T: PreallocatedPool<Item>>() {
+    let mut pool: T = Pool::with_capacity(1);
+    let id: usize = pool.allocate(1);
+    pool.deallocate(id);
+    assert!(panic_message(|| { pool.get(id); }).ends_with("(generation 1)"));
+
+    assert!(pool.allocate(2) == id);
+    assert!(*pool.get(id) == 2);
+    pool.deallocate(id);
+    assert!(panic_message(|| { pool.get(id); }).ends_with("(generation 2)"));
 }
 
-impl MutationGenerator {    
-    const MAX_NUM_TOKENS: usize = 10;
-    
-    pub fn new<T: Rng>(rng: &mut T) -> Self {
-        let mut tokens: [usize; NUM_MUTATIONS] = [0; NUM_MUTATIONS];
-        let mut total_num_tokens: usize = 0;
-        for mutation in 0..NUM_MUTATIONS {
-            let num_tokens: usize = rng.gen_range(1..=Self::MAX_NUM_TOKENS);
-            tokens[mutation] = num_tokens;
-            total_num_tokens += num_tokens;
-        }
+pub fn test_leaks_are_reported<T: Pool<Item>>() {
+    let reports: Rc<RefCell<Vec<LeakReport>>> = Rc::new(RefCell::new(Vec::new()));
+    let reports_in_handler: Rc<RefCell<Vec<LeakReport>>> = reports.clone();
+    set_leak_handler(Some(LeakHandler::Callback(Rc::new(move |report: &LeakReport| {
+        reports_in_handler.borrow_mut().push(report.clone());
+    }))));
 
-        assert!(tokens.iter().cloned().all(|num_tokens| num_tokens > 0));
+    let mut pool: T = Pool::new();
+    let id: usize = pool.allocate(1);
+    pool.deallocate(id);
+    drop(pool);
+    assert!(reports.borrow().is_empty()); // nothing to report
 
-        return Self { 
-            tokens,
-            total_num_tokens 
+    let mut pool: T = Pool::new();
+    let mut ids: Vec<usize> = Vec::new();
+    let allocated_at: u32 = line!() + 2;
+    for i in 0..100 {
+        ids.push( pool.allocate(i) );
+    }
+    for id in ids.iter().step_by(3) {
+        pool.deallocate(*id);
+    }
+    let mut leaked_ids: Vec<usize> = pool.iter().map(|item: &Item| ids[*item as usize]).collect();
+    leaked_ids.sort();
+    drop(pool);
+    set_leak_handler(None);
+
+    let reports: Vec<LeakReport> = reports.take();
+    assert!(reports.len() == 1);
+    assert!(reports[0].pool_type() == core::any::type_name::<T>());
+    assert!(reports[0].leaks().iter().map(|leak: &Leak| leak.id).eq(leaked_ids.iter().copied()));
+    for leak in reports[0].leaks() {
+        match leak.allocated_at {
+            Some(location) => assert!(cfg!(feature = "debug-checks") && location.file() == file!() && location.line() == allocated_at),
+            None => assert!(!cfg!(feature = "debug-checks")),
         }
     }
+}
 
-    pub fn generate<T: Rng>(&mut self, rng: &mut T) -> usize {
-        let token: usize = rng.gen_range(1..=self.total_num_tokens);
-        let mut num_tokens_so_far: usize = 0;
-        for mutation in 0..self.tokens.len() {
-            assert!(token > num_tokens_so_far);
-            let token: usize = token - num_tokens_so_far;
-            if token <= self.tokens[mutation] {
-                return mutation
-            }
-            num_tokens_so_far += self.tokens[mutation];
-        }
-        unreachable!();
+pub fn test_panicking_leak_handler_still_drops_items<T: Pool<Rc<()>>>() {
+    let item: Rc<()> = Rc::new(());
+    let mut pool: T = Pool::new();
+    for _ in 0..100 {
+        pool.allocate(item.clone());
     }
 
-    pub fn _get_probability(&self, mutation: usize) -> f64 {
-        return self.tokens[mutation] as f64 / self.total_num_tokens as f64
+    set_leak_handler(Some(LeakHandler::Panic));
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| drop(pool)));
+    set_leak_handler(None);
+    assert!(result.is_err());
+    assert!(Rc::strong_count(&item) == 1);
+}
+
+pub fn test_slot_guard<T: Pool<Item>>() {
+    let mut pool: T = T::new();
+    let kept: usize = pool.allocate(1);
+
+    // Dropping the guard deallocates the item
+    {
+        let mut guard: SlotGuard<Item, T> = allocate_scoped(&mut pool, 2);
+        assert!(*guard == 2);
+        *guard += 1;
+        assert!(*guard == 3);
     }
+    assert!(pool.len() == 1);
 
-    pub fn _relative_probabilities(&self) -> impl Iterator<Item=&usize> {
-        return self.tokens.iter()
+    // into_id() keeps it
+    let mut guard: SlotGuard<Item, T> = allocate_scoped(&mut pool, 4);
+    *guard += 1;
+    let id: usize = guard.id();
+    assert!(guard.into_id() == id);
+    assert!(pool.len() == 2);
+    assert!(*pool.get(id) == 5);
+
+    // An early return doesn't leak the slot
+    fn store_if_positive<T: Pool<Item>>(pool: &mut T, item: Item) -> Result<usize, ()> {
+        let guard: SlotGuard<Item, T> = allocate_scoped(pool, item);
+        if *guard <= 0 {
+            return Err(())
+        }
+        return Ok(guard.into_id())
+    }
+    for item in -50..50 {
+        let _ = store_if_positive(&mut pool, item);
     }
+    assert!(pool.len() == 2+49);
+    assert!(pool.iter().all(|item: &Item| *item > 0));
+    assert!(*pool.get(kept) == 1);
 }
 
-#[derive(Debug)]
-enum EqualityError {
-    NumItemsDontMatch,
-    ItemsDontMatch,
-    GetsDontMatch,
+/*
+    Every test in this binary allocates through this, but it only counts the
+    allocations of threads that have called count_allocations(), so the tests
+    can keep running in parallel.
+*/
+#[global_allocator]
+static COUNTING_ALLOCATOR: CountingAllocator = CountingAllocator;
+
+thread_local! {
+    static NUM_ALLOCATIONS: Cell<Option<usize>> = const { Cell::new(None) };
+    static NUM_BYTES_ALLOCATED: Cell<Option<isize>> = const { Cell::new(None) };
 }
 
-fn compare_for_equality<T: Pool<Item>> (
-    test: &T, 
-    reference: &Reference<Item>,
-    allocations: &Allocations,
-) 
--> Result<(), EqualityError>
-{
-    if test.len() != reference.len() {
-        return Err( EqualityError::NumItemsDontMatch )
-    }
+fn add_bytes_allocated(num_bytes: isize) {
+    let _ = NUM_BYTES_ALLOCATED.try_with(|count| {
+        if let Some(n) = count.get() {
+            count.set(Some(n + num_bytes));
+        }
+    });
+}
 
-    let mut items_in_test: Vec<Item> = test.iter().copied().collect();
-    let mut items_in_reference: Vec<Item> = reference.iter().copied().collect();
-    items_in_test.sort();
-    items_in_reference.sort();
-    if items_in_test != items_in_reference {
-        return Err( EqualityError::ItemsDontMatch )
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = NUM_ALLOCATIONS.try_with(|count| {
+            if let Some(n) = count.get() {
+                count.set(Some(n+1));
+            }
+        });
+        add_bytes_allocated(layout.size() as isize);
+        return System.alloc(layout)
     }
 
-    for pair in allocations.pairs_of_ids() {
-        if *test.get( pair.id_in_test ) != *reference.get( pair.id_in_reference ) {
-            return Err( EqualityError::GetsDontMatch )
-        }
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let _ = NUM_ALLOCATIONS.try_with(|count| {
+            if let Some(n) = count.get() {
+                count.set(Some(n+1));
+            }
+        });
+        add_bytes_allocated(new_size as isize - layout.size() as isize);
+        return System.realloc(ptr, layout, new_size)
     }
 
-    return Ok(())
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        add_bytes_allocated(-(layout.size() as isize));
+        System.dealloc(ptr, layout);
+    }
 }
 
-#[allow(dead_code)]
-#[derive(Clone, Copy, Debug)]
-enum LogEntry {
-    Construction(Construction),
-    Mutation(Mutation),
+// Returns the number of heap allocations (and reallocations) made by f on this thread
+pub fn count_allocations<F: FnOnce()>(f: F) -> usize {
+    NUM_ALLOCATIONS.with(|count| count.set(Some(0)));
+    f();
+    return NUM_ALLOCATIONS.with(|count| count.replace(None).unwrap())
 }
 
-fn fuzz<T: Rng, U: Pool<Item>>(rng: &mut T, num_mutations_to_try: usize) {
-    let mut log: Vec<LogEntry> = Vec::new();
-    let mut allocations: Allocations = Allocations::new();
+// Returns how many more bytes of the global allocator's memory are in use on this thread after f than before it
+pub fn count_bytes_allocated<F: FnOnce()>(f: F) -> isize {
+    NUM_BYTES_ALLOCATED.with(|count| count.set(Some(0)));
+    f();
+    return NUM_BYTES_ALLOCATED.with(|count| count.replace(None).unwrap())
+}
 
-    let mut test: U;
-    let mut reference: Reference<Item>;
-    match generate_random_construction(rng) {
-        Construction::New => {
-            test = Pool::new();
-            reference = Pool::new();
-            log.push( LogEntry::Construction( Construction::New ) );
+// Allocates, deallocates and drops a pool, all while making sure it never touches the global allocator
+pub fn test_all_memory_goes_through_allocator<T: Pool<Item>>() {
+    reset_counting_alloc();
+    let mut ids: Vec<usize> = Vec::with_capacity(3000);
+    let num_global_allocations: usize = count_allocations(|| {
+        let mut pool: T = Pool::with_capacity(100);
+        for i in 0..3000 {
+            ids.push( pool.allocate(i) );
+        }
+        for i in (0..ids.len()).step_by(3) {
+            *pool.get_mut(ids[i]) += 1;
+            pool.deallocate(ids[i]);
+        }
+        for i in 0..1000 {
+            pool.allocate(i);
+        }
+        drop(pool);
+    });
 
-        },
+    assert!(num_global_allocations == 0, "{} allocations went through the global allocator", num_global_allocations);
+    assert!(num_counting_alloc_allocations() > 0);
+    assert!(num_counting_alloc_bytes_in_use() == 0);
+}
 
-        Construction::WithCapacity{ capacity } => {
-            test = Pool::with_capacity(capacity);
-            reference = Pool::with_capacity(capacity);
-            log.push( LogEntry::Construction( Construction::WithCapacity{ capacity }  ) );
-        },
-    }
+/*
+    Builds the same pool for a small item and a big one, both with CountingAlloc.
+    Everything but the slots is the same size either way, so the difference in
+    what they allocate is all in the slots, and it has to be exactly the
+    difference in the items. An Option<T> in every slot would cost more.
+*/
+pub fn test_slots_dont_store_a_discriminant<Small: Pool<u8>, Big: Pool<[u64; 3]>>() {
+    const CAPACITY: usize = 64;
+    reset_counting_alloc();
+    let small: Small = Pool::with_capacity(CAPACITY);
+    let small_bytes: usize = num_counting_alloc_bytes_in_use();
+    drop(small);
+
+    reset_counting_alloc();
+    let big: Big = Pool::with_capacity(CAPACITY);
+    let big_bytes: usize = num_counting_alloc_bytes_in_use();
+    drop(big);
+
+    assert!(big_bytes - small_bytes == CAPACITY*(size_of::<[u64; 3]>() - size_of::<u8>()), "{} bytes for small items, {} for big ones", small_bytes, big_bytes);
+    assert!(size_of::<Option<[u64; 3]>>() > size_of::<[u64; 3]>());
+}
 
-    if let Err(error) = compare_for_equality(&test, &reference, &allocations) {
-        panic!("{:?}\n{:?}", error, log);
-    }
+// The pool has to use CountingAlloc, so that what it reports can be checked against what it actually holds
+pub fn test_heap_size_matches_allocator<T: Pool<Item>>() {
+    const RNG_SEED: u64 = 41;
+    let mut rng: Xoshiro256StarStar = Xoshiro256StarStar::seed_from_u64(RNG_SEED);
+    reset_counting_alloc();
+    let mut pool: T = Pool::new();
+    assert!(pool.heap_size_bytes().total_bytes() == num_counting_alloc_bytes_in_use());
 
-    /*
-        todo: write a get_error() function for every impl of ItemPool and call it
-        after each mutation to validate the internal state of test. 
-        
-        Right now this validates the externally-apparent behavior of each impl, 
-        which points to the internal state of test being valid, but doesn't 
-        necessarily guarantee it.
-    */
-    const MAX_NUM_ITEMS_TO_ALLOCATE: usize = 100_000; // don't want to OoM
-    let mut generator: MutationGenerator = MutationGenerator::new(rng);
-    for _ in 0..num_mutations_to_try {
-        match generator.generate(rng) {
-            SET => {
-                if reference.len() == 0 {
-                    continue;
-                }
-                
-                let pair: PairOfIds = allocations.get_random_pair_of_ids(rng).unwrap();
-                let id_in_test: usize = pair.id_in_test;
-                let id_in_reference: usize = pair.id_in_reference;
-                let value: Item = generate_random_item(rng);
-                *test.get_mut(id_in_test) = value;
-                *reference.get_mut(id_in_reference) = value;
-
-                log.push( 
-                    LogEntry::Mutation( 
-                        Mutation::Set {
-                            id_in_test,
-                            id_in_reference, 
-                            value
-                        } 
-                    ) 
-                );  
-            },
+    let mut ids: Vec<usize> = Vec::new();
+    for _ in 0..20 {
+        for _ in 0..rng.gen_range(0..200) {
+            ids.push( pool.allocate(generate_random_item(&mut rng)) );
+        }
+        for _ in 0..rng.gen_range(0..=ids.len()) {
+            let id: usize = ids.swap_remove(rng.gen_range(0..ids.len()));
+            pool.deallocate(id);
+        }
 
-            ALLOCATE => {
-                if reference.len() >= MAX_NUM_ITEMS_TO_ALLOCATE {
-                    continue;
-                }
+        let heap_usage: HeapUsage = pool.heap_size_bytes();
+        assert!(heap_usage.total_bytes() == num_counting_alloc_bytes_in_use());
+        assert!(heap_usage.bytes_of("item_heap") == Some(0));
 
-                let item: Item = generate_random_item(rng);
-                let id_in_test: usize = test.allocate(item);
-                let id_in_reference: usize = reference.allocate(item);
-                allocations.add( 
-                    PairOfIds {
-                        id_in_test, 
-                        id_in_reference
-                    }
-                );
-                
-                log.push(
-                    LogEntry::Mutation( 
-                        Mutation::Allocate { 
-                            item
-                        } 
-                    ) 
-                );
-            },
+        // Pretend every item owns as many bytes as its value mod 100
+        let item_heap_size_bytes = |item: &Item| item.rem_euclid(100) as usize;
+        let expected_item_heap: usize = pool.iter().map(item_heap_size_bytes).sum();
+        let with_item_heap: HeapUsage = pool.heap_size_bytes_with(item_heap_size_bytes);
+        assert!(with_item_heap.bytes_of("item_heap") == Some(expected_item_heap));
+        assert!(with_item_heap.total_bytes() == heap_usage.total_bytes() + expected_item_heap);
+    }
 
-            DEALLOCATE => {
-                if reference.len() == 0 {
-                    continue;
-                }
+    drop(pool);
+    assert!(num_counting_alloc_bytes_in_use() == 0);
+}
 
-                let pair: PairOfIds = allocations.get_random_pair_of_ids(rng).unwrap();
-                let id_in_test: usize = pair.id_in_test;
-                let id_in_reference: usize = pair.id_in_reference;
-                test.deallocate(id_in_test);
-                reference.deallocate(id_in_reference);
-                allocations.remove(pair);
-
-                log.push( 
-                    LogEntry::Mutation(
-                        Mutation::Deallocate {
-                            id_in_test, 
-                            id_in_reference
-                        } 
-                    ) 
-                );
-            },
+/*
+    A stand-in for an arena or NUMA-aware allocator. It gets its memory straight 
+    from the system allocator, so it doesn't show up in count_allocations(), and 
+    it keeps its own per-thread counts of what it's handed out.
+*/
+#[derive(Clone, Copy, Default)]
+pub struct CountingAlloc;
+
+thread_local! {
+    static COUNTING_ALLOC_ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    static COUNTING_ALLOC_BYTES_IN_USE: Cell<usize> = const { Cell::new(0) };
+}
 
-            _ => unreachable!(),
+unsafe impl Allocator for CountingAlloc {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if layout.size() == 0 {
+            let dangling: NonNull<u8> = NonNull::new(layout.align() as *mut u8).unwrap();
+            return Ok( NonNull::slice_from_raw_parts(dangling, 0) )
         }
 
-        if let Err(error) = compare_for_equality(&test, &reference, &allocations) {
-            panic!("{:?}\n{:?}", error, log);
+        let ptr: *mut u8 = unsafe { System.alloc(layout) };
+        let ptr: NonNull<u8> = NonNull::new(ptr).ok_or(AllocError)?;
+        COUNTING_ALLOC_ALLOCATIONS.with(|count| count.set(count.get()+1));
+        COUNTING_ALLOC_BYTES_IN_USE.with(|bytes| bytes.set(bytes.get()+layout.size()));
+        return Ok( NonNull::slice_from_raw_parts(ptr, layout.size()) )
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() == 0 {
+            return
         }
+
+        System.dealloc(ptr.as_ptr(), layout);
+        COUNTING_ALLOC_BYTES_IN_USE.with(|bytes| bytes.set(bytes.get()-layout.size()));
     }
 }
 
-fn fuzz_many_item_pools<T: Pool<Item>>(num_pools_to_fuzz: usize, max_num_mutations: usize) {
-    /*
-        Xoshiro256StarStar is a deterministic PRNG that's seeded from the same value every time, 
-        which makes these tests consistently reproducible (you get exactly the same sequence of 
-        operations every single time this fuzz_many_item_pools() is called)
-    */
-    const RNG_SEED: u64 = 2049;
-    let mut rng: Xoshiro256StarStar = Xoshiro256StarStar::seed_from_u64(RNG_SEED);
+pub fn reset_counting_alloc() {
+    COUNTING_ALLOC_ALLOCATIONS.with(|count| count.set(0));
+    COUNTING_ALLOC_BYTES_IN_USE.with(|bytes| bytes.set(0));
+}
 
-    for _ in 0..num_pools_to_fuzz {
-        fuzz::<_, T>(&mut rng, max_num_mutations);
-    }
+pub fn num_counting_alloc_allocations() -> usize {
+    return COUNTING_ALLOC_ALLOCATIONS.with(|count| count.get())
 }
 
-fn generate_random_item<T: Rng>(rng: &mut T) -> Item {
-    return rng.gen_range(Item::MIN..=Item::MAX)
-}
\ No newline at end of file
+pub fn num_counting_alloc_bytes_in_use() -> usize {
+    return COUNTING_ALLOC_BYTES_IN_USE.with(|bytes| bytes.get())
+}
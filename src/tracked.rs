@@ -0,0 +1,245 @@
+use core::marker::PhantomData;
+
+use super::{PreallocatedPool, PoolStats, HeapUsage, InvariantError};
+use super::flag_based::HierarchicalBitVec;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Change {
+    Added(usize),
+    Changed(usize),
+    Removed(usize),
+}
+
+/*
+    A pool that remembers which ids were allocated, deallocated or handed out
+    through get_mut() since the last call to drain_changed(), so only those need
+    to be sent somewhere else.
+
+    Every id with something to report has its bit set in dirty, and draining
+    just keeps taking its lowest set bit, which skips clean stretches of ids a
+    whole summary block at a time. An item that's allocated and deallocated
+    again between two drains was never seen, so it isn't reported at all. An id
+    whose item was deallocated and then reused for a new item is reported as
+    Removed and then Added.
+
+    Ids are used as bit indices, so only the backends with dense ids can be
+    wrapped, and the bits are kept in the same allocator as the pool's storage.
+*/
+/// ```compile_fail
+/// // Reference's ids are addresses, which would need a bit for every one below the highest
+/// let pool: pool_party::Tracked<u32, pool_party::Reference<u32>> = pool_party::Tracked::new();
+/// ```
+pub struct Tracked<T, P: PreallocatedPool<T>> {
+    pool: P,
+    dirty: HierarchicalBitVec<P::Alloc>,   // ids with something to report
+    added: HierarchicalBitVec<P::Alloc>,   // ids allocated since the last drain
+    removed: HierarchicalBitVec<P::Alloc>, // ids whose item from before the last drain has since been deallocated
+    _items: PhantomData<T>,
+}
+
+impl <T, P: PreallocatedPool<T>> Tracked<T, P> {
+    pub fn new() -> Self {
+        return Self::with_capacity(0)
+    }
+
+    pub fn with_capacity(num_items: usize) -> Self {
+        let pool: P = P::with_capacity(num_items);
+        let alloc: P::Alloc = pool.allocator().clone();
+        return Self {
+            dirty: HierarchicalBitVec::with_bits_in(num_items, false, alloc.clone()),
+            added: HierarchicalBitVec::with_bits_in(num_items, false, alloc.clone()),
+            removed: HierarchicalBitVec::with_bits_in(num_items, false, alloc),
+            pool,
+            _items: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        return self.pool.len()
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        return self.pool.stats()
+    }
+
+    pub fn heap_size_bytes(&self) -> HeapUsage {
+        return self.heap_size_bytes_with(|_: &T| 0)
+    }
+
+    // The pool's own breakdown, plus what the change tracking takes
+    pub fn heap_size_bytes_with<F: FnMut(&T) -> usize>(&self, item_heap_size_bytes: F) -> HeapUsage {
+        return self.pool.heap_size_bytes_with(item_heap_size_bytes)
+            .with("change_flags", self.dirty.heap_size_bytes() + self.added.heap_size_bytes() + self.removed.heap_size_bytes())
+    }
+
+    pub fn check_invariants(&self) -> Result<(), InvariantError> {
+        self.dirty.check_invariants()?;
+        self.added.check_invariants()?;
+        self.removed.check_invariants()?;
+        return self.pool.check_invariants()
+    }
+
+    #[cfg_attr(feature = "debug-checks", track_caller)]
+    pub fn get(&self, id: usize) -> &T {
+        return self.pool.get(id)
+    }
+
+    // Marks the item as changed whether or not it's actually written to
+    #[cfg_attr(feature = "debug-checks", track_caller)]
+    pub fn get_mut(&mut self, id: usize) -> &mut T {
+        let item: &mut T = self.pool.get_mut(id);
+        self.dirty.set_bit(id, true);
+        return item
+    }
+
+    #[cfg_attr(feature = "debug-checks", track_caller)]
+    pub fn allocate(&mut self, item: T) -> usize {
+        let id: usize = self.pool.allocate(item);
+        self.expand_if_needed(id);
+        self.added.set_bit(id, true);
+        self.dirty.set_bit(id, true);
+        return id
+    }
+
+    #[cfg_attr(feature = "debug-checks", track_caller)]
+    pub fn deallocate(&mut self, id: usize) {
+        self.pool.deallocate(id);
+        if self.added.get_bit(id) {
+            self.added.set_bit(id, false);
+            if !self.removed.get_bit(id) {
+                self.dirty.set_bit(id, false);
+            }
+            return
+        }
+
+        self.removed.set_bit(id, true);
+        self.dirty.set_bit(id, true);
+    }
+
+    pub fn iter<'a>(&'a self) -> P::Iter<'a> where T: 'a {
+        return self.pool.iter()
+    }
+
+    // Changes come out in ascending id order. Dropping the Drain early still forgets the changes it didn't get to.
+    pub fn drain_changed(&mut self) -> Drain<'_, T, P> {
+        return Drain {
+            tracked: self,
+            added_after_removal: None,
+        }
+    }
+
+    pub fn into_inner(self) -> P {
+        return self.pool
+    }
+
+    fn expand_if_needed(&mut self, id: usize) {
+        let num_bits: usize = self.dirty.num_bits();
+        if id < num_bits {
+            return
+        }
+
+        const GROWTH_FACTOR: usize = 2;
+        let new_num_bits: usize = (id+1).max(num_bits*GROWTH_FACTOR);
+        self.dirty.add_bits(new_num_bits - num_bits, false);
+        self.added.add_bits(new_num_bits - num_bits, false);
+        self.removed.add_bits(new_num_bits - num_bits, false);
+    }
+}
+
+impl <T, P: PreallocatedPool<T>> Default for Tracked<T, P> {
+    fn default() -> Self {
+        return Self::new()
+    }
+}
+
+pub struct Drain<'a, T, P: PreallocatedPool<T>> {
+    tracked: &'a mut Tracked<T, P>,
+    added_after_removal: Option<usize>, // an id that was reused, whose Added comes right after its Removed
+}
+
+impl <'a, T, P: PreallocatedPool<T>> Iterator for Drain<'a, T, P> {
+    type Item = Change;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(id) = self.added_after_removal.take() {
+            return Some(Change::Added(id))
+        }
+
+        let id: usize = self.tracked.dirty.find_a_true_bit()?; // always the lowest, since every bit below it has been cleared
+        let added: bool = self.tracked.added.get_bit(id);
+        let removed: bool = self.tracked.removed.get_bit(id);
+        self.tracked.dirty.set_bit(id, false);
+        self.tracked.added.set_bit(id, false);
+        self.tracked.removed.set_bit(id, false);
+
+        if removed && added {
+            self.added_after_removal = Some(id);
+            return Some(Change::Removed(id))
+        }
+        else if removed {
+            return Some(Change::Removed(id))
+        }
+        else if added {
+            return Some(Change::Added(id))
+        }
+        return Some(Change::Changed(id))
+    }
+}
+
+impl <'a, T, P: PreallocatedPool<T>> Drop for Drain<'a, T, P> {
+    fn drop(&mut self) {
+        for _ in self.by_ref() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Tracked, Change};
+    use crate::testing;
+    use crate::testing::{Item, CountingAlloc};
+    use crate::{FreeList, Stacks};
+
+    testing::test_every_backend!(test_tracked_changes; except Reference);
+
+    #[test]
+    fn test_reused_and_short_lived_ids() {
+        let mut pool: Tracked<Item, FreeList<Item>> = Tracked::new();
+        let first: usize = pool.allocate(1);
+        let second: usize = pool.allocate(2);
+        assert!(pool.drain_changed().eq([Change::Added(first), Change::Added(second)]));
+
+        pool.deallocate(first);
+        let reused: usize = pool.allocate(3);
+        assert!(reused == first);
+        let short_lived: usize = pool.allocate(4);
+        pool.deallocate(short_lived);
+        *pool.get_mut(second) += 1;
+        assert!(pool.drain_changed().eq([Change::Removed(first), Change::Added(first), Change::Changed(second)]));
+        assert!(pool.drain_changed().next().is_none());
+    }
+
+    #[test]
+    fn test_dropping_drain_early_forgets_the_rest() {
+        let mut pool: Tracked<Item, Stacks<Item>> = Tracked::new();
+        for i in 0..100 {
+            pool.allocate(i);
+        }
+        assert!(pool.drain_changed().take(10).count() == 10);
+        assert!(pool.drain_changed().next().is_none());
+    }
+
+    #[test]
+    fn test_change_flags_go_through_pools_allocator() {
+        testing::reset_counting_alloc();
+        let num_global_allocations: usize = testing::count_allocations(|| {
+            let mut pool: Tracked<Item, FreeList<Item, CountingAlloc>> = Tracked::with_capacity(10);
+            for i in 0..1000 {
+                pool.allocate(i);
+            }
+            assert!(pool.drain_changed().count() == 1000);
+            drop(pool);
+        });
+        assert!(num_global_allocations == 0, "{} allocations went through the global allocator", num_global_allocations);
+        assert!(testing::num_counting_alloc_bytes_in_use() == 0);
+    }
+}